@@ -0,0 +1,220 @@
+//! Publishes Cyphal/CAN messages and calls services.
+//!
+//! Payloads are given as YAML and encoded with the DSDL type of the port, or as
+//! raw hex with `--hex`.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use cyphal::session::StdVecSessionManager;
+use cyphal::time::StdClock;
+use cyphal::types::{NodeId, PortId, TransferId};
+use cyphal::{Priority, TransferKind};
+use cyphal_mon::command::{self, parse_hex, parse_priority, parse_transfer_id, CommandNode};
+use cyphal_mon::dsdl::{parse_assignment, TypeSet};
+use cyphal_mon::yaml;
+
+#[derive(Parser)]
+#[command(
+    name = "cyphal-cmd",
+    about = "Publish Cyphal/CAN messages and call services"
+)]
+struct Args {
+    /// CAN interface to use, e.g. can0
+    #[arg(short, long)]
+    iface: String,
+    /// Node ID to send from. Without one, only single-frame messages can be published
+    #[arg(short, long)]
+    node_id: Option<NodeId>,
+    /// Transfer priority, by name or number (0 is exceptional)
+    #[arg(short, long, default_value = "nominal", value_parser = parse_priority)]
+    priority: Priority,
+    /// Payloads are raw hex, e.g. "01 02 ab", instead of YAML
+    #[arg(long)]
+    hex: bool,
+    /// Transfer ID of the first transfer sent, random if not given. Each publication
+    /// after it takes the next one
+    #[arg(long, value_parser = parse_transfer_id)]
+    transfer_id: Option<TransferId>,
+
+    /// Root namespace directory of DSDL definitions, e.g. public_regulated_data_types/uavcan
+    #[arg(long)]
+    dsdl: Vec<PathBuf>,
+    /// Encode a port with a type that has no fixed port ID, as PORT=full.type.Name.1.0
+    #[arg(long = "type", value_parser = parse_assignment)]
+    types: Vec<(PortId, String)>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Publish a message, e.g. `pub 7509 '{uptime: 16, health: {value: 0}}'`
+    Pub {
+        subject: PortId,
+        /// Message as YAML, or hex with --hex. Missing fields are zero
+        #[arg(default_value = "")]
+        payload: String,
+        /// How many times to publish it, 0 for until interrupted
+        #[arg(short, long, default_value_t = 1)]
+        count: u64,
+        /// Time between publications, in seconds
+        #[arg(long, default_value_t = 1.0)]
+        period: f64,
+    },
+    /// Send a request and print the response
+    Call {
+        server: NodeId,
+        service: PortId,
+        /// Request as YAML, or hex with --hex. Missing fields are zero
+        #[arg(default_value = "")]
+        payload: String,
+        /// How long to wait for the response, in seconds
+        #[arg(long, default_value_t = 1.0)]
+        timeout: f64,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cyphal-cmd: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut types = TypeSet::new();
+    for dir in &args.dsdl {
+        types.load_namespace(dir)?;
+    }
+    for (port, name) in &args.types {
+        types.assign(*port, name)?;
+    }
+
+    let clock = StdClock::new();
+    let mut media = open(&args.iface, &clock)?;
+    let mut node = CommandNode::new(args.node_id, StdVecSessionManager::new());
+    let transfer_id = args.transfer_id.unwrap_or_else(command::random_transfer_id);
+
+    match &args.command {
+        Command::Pub {
+            subject,
+            payload,
+            count,
+            period,
+        } => {
+            let period = seconds(*period)?;
+            let payload = encode(&args, &types, TransferKind::Message, *subject, payload)?;
+            let mut sent = 0;
+            loop {
+                command::publish(
+                    &mut node,
+                    &mut media,
+                    &clock,
+                    args.priority,
+                    *subject,
+                    command::nth_transfer_id(transfer_id, sent),
+                    &payload,
+                )?;
+                sent += 1;
+                if sent == *count {
+                    return Ok(());
+                }
+                thread::sleep(period);
+            }
+        }
+        Command::Call {
+            server,
+            service,
+            payload,
+            timeout,
+        } => {
+            if args.node_id.is_none() {
+                return Err("calling a service needs a --node-id".into());
+            }
+            let timeout = seconds(*timeout)?;
+            let request = encode(&args, &types, TransferKind::Request, *service, payload)?;
+            let response = command::call(
+                &mut node,
+                &mut media,
+                &clock,
+                args.priority,
+                *server,
+                *service,
+                transfer_id,
+                &request,
+                timeout,
+            )?
+            .ok_or_else(|| format!("no response from node {}", server))?;
+
+            match types.decode(TransferKind::Response, *service, &response) {
+                Some((_, Ok(value))) => println!("{}", value),
+                Some((name, Err(err))) => {
+                    return Err(format!("{} response undecodable: {}", name, err))
+                }
+                None => {
+                    let bytes: Vec<String> = response
+                        .iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect();
+                    println!("{}", bytes.join(" "));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Turn a payload argument into bytes.
+fn encode(
+    args: &Args,
+    types: &TypeSet,
+    kind: TransferKind,
+    port: PortId,
+    payload: &str,
+) -> Result<Vec<u8>, String> {
+    if args.hex {
+        return parse_hex(payload);
+    }
+    let value = yaml::parse(payload)?;
+    match types.encode(kind, port, &value) {
+        Some((_, Ok(payload))) => Ok(payload),
+        Some((name, Err(err))) => Err(format!("{}: {}", name, err)),
+        None => Err(format!(
+            "no type known for port {}, give one with --dsdl and --type, or use --hex",
+            port
+        )),
+    }
+}
+
+fn seconds(seconds: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("invalid duration {}", seconds))
+}
+
+#[cfg(feature = "socketcan")]
+fn open(
+    iface: &str,
+    clock: &StdClock,
+) -> Result<cyphal::media::socketcan::SocketCan<StdClock>, String> {
+    use cyphal::media::socketcan::SocketCan;
+
+    let can = SocketCan::open(iface, clock.clone()).map_err(|err| format!("{}: {}", iface, err))?;
+    can.set_read_timeout(Some(Duration::from_millis(10)))
+        .map_err(|err| format!("{}: {}", iface, err))?;
+    Ok(can)
+}
+
+#[cfg(not(feature = "socketcan"))]
+fn open(
+    iface: &str,
+    _: &StdClock,
+) -> Result<std::collections::VecDeque<cyphal::transport::can::CanFrame<StdClock>>, String> {
+    Err(format!("{}: built without SocketCAN support", iface))
+}
@@ -0,0 +1,384 @@
+//! Publishing messages and calling services from the command line.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use cyphal::media::{CanMedia, MediaError};
+use cyphal::session::{SessionManager, StdVecSessionManager, SubscriptionError};
+use cyphal::time::StdClock;
+use cyphal::transfer::{RefTransfer, TransferMetadata};
+use cyphal::transport::can::{Can, CanFrame, CanMetadata};
+use cyphal::transport::Transport;
+use cyphal::types::{NodeId, PortId, TransferId};
+use cyphal::{Node, Priority, Subscription, TransferKind};
+use embedded_time::duration::Milliseconds;
+use embedded_time::Clock;
+
+pub type CommandNode = Node<StdVecSessionManager<CanMetadata, StdClock>, Can, StdClock>;
+
+/// Largest response that will be accepted.
+const RESPONSE_EXTENT: usize = 4096;
+/// Transfer IDs wrap around at this.
+const TRANSFER_ID_MODULO: usize = <Can as Transport<StdClock>>::TRANSFER_ID_MODULO;
+
+/// Parse a payload given as hex, e.g. `01 02 ab` or `0102ab`.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".into());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("invalid hex {}", pair))
+        })
+        .collect()
+}
+
+/// Parse a priority by name, e.g. `nominal`, or number, 0 being exceptional.
+pub fn parse_priority(text: &str) -> Result<Priority, String> {
+    const NAMES: [(&str, Priority); 8] = [
+        ("exceptional", Priority::Exceptional),
+        ("immediate", Priority::Immediate),
+        ("fast", Priority::Fast),
+        ("high", Priority::High),
+        ("nominal", Priority::Nominal),
+        ("low", Priority::Low),
+        ("slow", Priority::Slow),
+        ("optional", Priority::Optional),
+    ];
+    let text = text.to_ascii_lowercase();
+    NAMES
+        .iter()
+        .enumerate()
+        .find(|(number, (name, _))| *name == text || number.to_string() == text)
+        .map(|(_, (_, priority))| *priority)
+        .ok_or_else(|| format!("unknown priority {}", text))
+}
+
+/// Parse a transfer ID, which has to be below the CAN transfer-ID modulo.
+pub fn parse_transfer_id(text: &str) -> Result<TransferId, String> {
+    match text.parse::<usize>() {
+        Ok(transfer_id) if transfer_id < TRANSFER_ID_MODULO => Ok(transfer_id as TransferId),
+        _ => Err(format!(
+            "invalid transfer ID {}, must be below {}",
+            text, TRANSFER_ID_MODULO
+        )),
+    }
+}
+
+/// Transfer ID to start from when none is given.
+///
+/// Receivers drop a transfer-ID they've just seen from the same node, so every run
+/// starting at the same one would have its transfers taken for repeats of the last
+/// run's. A random start makes that unlikely.
+pub fn random_transfer_id() -> TransferId {
+    let random = RandomState::new().build_hasher().finish();
+    (random % TRANSFER_ID_MODULO as u64) as TransferId
+}
+
+/// Transfer ID of the transfer `count` after one with `first`.
+pub fn nth_transfer_id(first: TransferId, count: u64) -> TransferId {
+    ((first as u64 + count) % TRANSFER_ID_MODULO as u64) as TransferId
+}
+
+/// Publish a message.
+pub fn publish<M>(
+    node: &mut CommandNode,
+    media: &mut M,
+    clock: &StdClock,
+    priority: Priority,
+    subject: PortId,
+    transfer_id: TransferId,
+    payload: &[u8],
+) -> Result<(), String>
+where
+    M: CanMedia<CanFrame<StdClock>>,
+    M::Error: Debug,
+{
+    let transfer = RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().map_err(|err| format!("{:?}", err))?,
+            priority,
+            transfer_kind: TransferKind::Message,
+            port_id: subject,
+            remote_node_id: None,
+            transfer_id,
+        },
+        payload,
+    };
+    node.transmit_to(&transfer, media).map_err(media_error)
+}
+
+/// Send a request to `server` and wait up to `timeout` for its response, returning
+/// the response's payload, or `None` if there wasn't one in time.
+///
+/// Everything else received meanwhile is ignored.
+#[allow(clippy::too_many_arguments)]
+pub fn call<M>(
+    node: &mut CommandNode,
+    media: &mut M,
+    clock: &StdClock,
+    priority: Priority,
+    server: NodeId,
+    service: PortId,
+    transfer_id: TransferId,
+    payload: &[u8],
+    timeout: Duration,
+) -> Result<Option<Vec<u8>>, String>
+where
+    M: CanMedia<CanFrame<StdClock>>,
+    M::Error: Debug,
+{
+    let subscription = Subscription::new(
+        TransferKind::Response,
+        service,
+        RESPONSE_EXTENT,
+        Milliseconds(timeout.as_millis() as u32),
+    );
+    match node.sessions.subscribe(subscription) {
+        Ok(()) | Err(SubscriptionError::SubscriptionExists) => {}
+        Err(err) => return Err(format!("{:?}", err)),
+    }
+
+    let request = RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().map_err(|err| format!("{:?}", err))?,
+            priority,
+            transfer_kind: TransferKind::Request,
+            port_id: service,
+            remote_node_id: Some(server),
+            transfer_id,
+        },
+        payload,
+    };
+    node.transmit_to(&request, media).map_err(media_error)?;
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let response = match node.receive_from(media) {
+            Ok(Some(response)) => response,
+            // Other traffic on the bus
+            Ok(None) | Err(MediaError::Rx(_)) => continue,
+            Err(err) => return Err(media_error(err)),
+        };
+        let metadata = &response.metadata;
+        if metadata.transfer_kind == TransferKind::Response
+            && metadata.port_id == service
+            && metadata.remote_node_id == Some(server)
+            && metadata.transfer_id == transfer_id
+        {
+            return Ok(Some(response.payload.to_vec()));
+        }
+    }
+    Ok(None)
+}
+
+fn media_error<E: Debug>(err: MediaError<E>) -> String {
+    match err {
+        MediaError::Media(err) => format!("{:?}", err),
+        MediaError::Rx(err) => err.to_string(),
+        MediaError::Tx(err) => format!("{:?}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use cyphal::StreamingIterator;
+
+    use super::*;
+    use crate::monitor::{Filter, Monitor};
+
+    /// Bus with a server on it, which answers every request to itself by echoing the
+    /// payload back reversed.
+    struct Server {
+        node: CommandNode,
+        responses: VecDeque<CanFrame<StdClock>>,
+    }
+
+    impl Server {
+        fn new(id: NodeId, service: PortId) -> Self {
+            let mut node = CommandNode::new(Some(id), StdVecSessionManager::new());
+            node.sessions
+                .subscribe(Subscription::new(
+                    TransferKind::Request,
+                    service,
+                    64,
+                    Milliseconds(1000),
+                ))
+                .unwrap();
+            Self {
+                node,
+                responses: VecDeque::new(),
+            }
+        }
+    }
+
+    impl CanMedia<CanFrame<StdClock>> for Server {
+        type Error = ();
+
+        fn receive(&mut self) -> Result<Option<CanFrame<StdClock>>, ()> {
+            Ok(self.responses.pop_front())
+        }
+
+        fn transmit(&mut self, frame: &CanFrame<StdClock>) -> Result<(), ()> {
+            let request = match self.node.try_receive_frame(frame.clone()) {
+                Ok(Some(request)) => request,
+                _ => return Ok(()),
+            };
+            let payload: Vec<u8> = request.payload.iter().rev().copied().collect();
+            let response = RefTransfer {
+                metadata: TransferMetadata {
+                    timestamp: request.metadata.timestamp,
+                    priority: request.metadata.priority,
+                    transfer_kind: TransferKind::Response,
+                    port_id: request.metadata.port_id,
+                    remote_node_id: request.metadata.remote_node_id,
+                    transfer_id: request.metadata.transfer_id,
+                },
+                payload: &payload,
+            };
+            let mut frames = self.node.transmit(&response).unwrap();
+            while let Some(frame) = frames.next() {
+                self.responses.push_back(frame.clone());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_arguments() {
+        assert_eq!(parse_hex("01 02ab").unwrap(), vec![1, 2, 0xAB]);
+        assert_eq!(parse_hex("").unwrap(), Vec::<u8>::new());
+        assert!(parse_hex("012").is_err());
+        assert!(parse_hex("0g").is_err());
+
+        assert_eq!(parse_priority("Nominal").unwrap(), Priority::Nominal);
+        assert_eq!(parse_priority("0").unwrap(), Priority::Exceptional);
+        assert!(parse_priority("8").is_err());
+        assert!(parse_priority("urgent").is_err());
+
+        assert_eq!(parse_transfer_id("31").unwrap(), 31);
+        assert!(parse_transfer_id("32").is_err());
+        assert!(parse_transfer_id("-1").is_err());
+    }
+
+    #[test]
+    fn transfer_ids_wrap() {
+        assert_eq!(nth_transfer_id(5, 0), 5);
+        assert_eq!(nth_transfer_id(30, 3), 1);
+        assert_eq!(nth_transfer_id(0, 64), 0);
+        assert!((random_transfer_id() as usize) < TRANSFER_ID_MODULO);
+    }
+
+    #[test]
+    fn publish_messages() {
+        let clock = StdClock::new();
+        let mut node = CommandNode::new(Some(42), StdVecSessionManager::new());
+        let mut bus = VecDeque::new();
+        let payload: Vec<u8> = (0..10).collect();
+        publish(
+            &mut node,
+            &mut bus,
+            &clock,
+            Priority::High,
+            100,
+            3,
+            &payload,
+        )
+        .unwrap();
+        assert_eq!(bus.len(), 2);
+
+        let mut monitor = Monitor::new(Filter::default());
+        let transfer = bus
+            .drain(..)
+            .filter_map(|frame| monitor.process(frame))
+            .next()
+            .unwrap();
+        assert_eq!(transfer.priority, Priority::High);
+        assert_eq!(transfer.port, 100);
+        assert_eq!(transfer.source, Some(42));
+        assert_eq!(transfer.transfer_id, 3);
+        assert_eq!(transfer.payload, payload);
+
+        // Anonymous nodes can only publish single frames
+        let mut anonymous = CommandNode::new(None, StdVecSessionManager::new());
+        publish(
+            &mut anonymous,
+            &mut bus,
+            &clock,
+            Priority::High,
+            100,
+            0,
+            &[1],
+        )
+        .unwrap();
+        assert!(publish(
+            &mut anonymous,
+            &mut bus,
+            &clock,
+            Priority::High,
+            100,
+            0,
+            &payload
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn call_service() {
+        let clock = StdClock::new();
+        let mut node = CommandNode::new(Some(42), StdVecSessionManager::new());
+        let mut server = Server::new(10, 430);
+        let timeout = Duration::from_millis(100);
+
+        let response = call(
+            &mut node,
+            &mut server,
+            &clock,
+            Priority::Nominal,
+            10,
+            430,
+            7,
+            &[1, 2, 3],
+            timeout,
+        )
+        .unwrap();
+        assert_eq!(response, Some(vec![3, 2, 1]));
+
+        // Calling again reuses the subscription
+        let response = call(
+            &mut node,
+            &mut server,
+            &clock,
+            Priority::Nominal,
+            10,
+            430,
+            8,
+            &[4, 5],
+            timeout,
+        )
+        .unwrap();
+        assert_eq!(response, Some(vec![5, 4]));
+
+        // Nobody at node 11
+        let response = call(
+            &mut node,
+            &mut server,
+            &clock,
+            Priority::Nominal,
+            11,
+            430,
+            9,
+            &[],
+            timeout,
+        )
+        .unwrap();
+        assert_eq!(response, None);
+    }
+}
@@ -0,0 +1,1113 @@
+//! Enough of DSDL to decode transfers for display, and encode them from values
+//! given on the command line.
+//!
+//! Definitions are loaded from root namespace directories (e.g. `uavcan` from the
+//! public regulated data types), and types with a fixed port ID are picked up
+//! automatically. Primitive types, padding, fixed and variable-length arrays, unions,
+//! and nested sealed or delimited composites are supported. Constants are skipped,
+//! except as array capacities, which must be literals or constants from the same file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use cyphal::types::PortId;
+use cyphal::TransferKind;
+
+/// Nesting deeper than this is assumed to be a broken definition.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Bool,
+    Unsigned(u32),
+    Signed(u32),
+    Float(u32),
+    Void(u32),
+    /// Full name, e.g. `uavcan.node.Health.1.0`.
+    Composite(String),
+    Array(Box<Type>, usize),
+    /// Variable-length array, with its capacity.
+    VarArray(Box<Type>, usize),
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    /// `None` for padding.
+    name: Option<String>,
+    ty: Type,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Structure {
+    fields: Vec<Field>,
+    union: bool,
+    sealed: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Definition {
+    Message(Structure),
+    Service {
+        request: Structure,
+        response: Structure,
+    },
+}
+
+/// Decoded value of a field or a whole transfer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Array(Vec<Value>),
+    /// Fields in order, or just the selected one for a union.
+    Struct(Vec<(String, Value)>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Known data types, and the ports they're used on.
+#[derive(Default)]
+pub struct TypeSet {
+    types: HashMap<String, Definition>,
+    subjects: HashMap<PortId, String>,
+    services: HashMap<PortId, String>,
+}
+
+impl TypeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every definition under a root namespace directory.
+    pub fn load_namespace(&mut self, dir: &Path) -> Result<(), String> {
+        let root = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("{}: not a namespace directory", dir.display()))?;
+        self.load_dir(dir, root)
+    }
+
+    fn load_dir(&mut self, dir: &Path, namespace: &str) -> Result<(), String> {
+        let entries = fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("{}: {}", dir.display(), err))?
+                .path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+
+            if path.is_dir() {
+                self.load_dir(&path, &format!("{}.{}", namespace, name))?;
+                continue;
+            }
+            let stem = match name.strip_suffix(".dsdl") {
+                Some(stem) => stem,
+                None => continue,
+            };
+
+            // `[port.]Name.major.minor`
+            let (port, short_name) = match stem.split_once('.') {
+                Some((port, rest)) if port.bytes().all(|c| c.is_ascii_digit()) => {
+                    (port.parse::<PortId>().ok(), rest)
+                }
+                _ => (None, stem),
+            };
+            let source =
+                fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            self.add(&format!("{}.{}", namespace, short_name), port, &source)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        Ok(())
+    }
+
+    /// Add a definition, e.g. `uavcan.node.Heartbeat.1.0`, with its fixed port ID if
+    /// it has one.
+    pub fn add(
+        &mut self,
+        full_name: &str,
+        port: Option<PortId>,
+        source: &str,
+    ) -> Result<(), String> {
+        let namespace = namespace_of(full_name)?;
+        let definition = parse(namespace, source)?;
+        if let Some(port) = port {
+            match definition {
+                Definition::Message(_) => self.subjects.insert(port, full_name.to_owned()),
+                Definition::Service { .. } => self.services.insert(port, full_name.to_owned()),
+            };
+        }
+        self.types.insert(full_name.to_owned(), definition);
+        Ok(())
+    }
+
+    /// Use a type on a port without a fixed ID.
+    pub fn assign(&mut self, port: PortId, full_name: &str) -> Result<(), String> {
+        match self.types.get(full_name) {
+            Some(Definition::Message(_)) => self.subjects.insert(port, full_name.to_owned()),
+            Some(Definition::Service { .. }) => self.services.insert(port, full_name.to_owned()),
+            None => return Err(format!("unknown type {}", full_name)),
+        };
+        Ok(())
+    }
+
+    /// Decode a transfer, if the type on its port is known, giving the type's name
+    /// along with the value.
+    pub fn decode(
+        &self,
+        kind: TransferKind,
+        port: PortId,
+        payload: &[u8],
+    ) -> Option<(&str, Result<Value, String>)> {
+        let (name, structure) = self.structure(kind, port)?;
+        let mut reader = BitReader::new(payload);
+        Some((name, self.decode_structure(structure, &mut reader, 0)))
+    }
+
+    /// Encode a transfer, if the type on its port is known, giving the type's name
+    /// along with the payload.
+    ///
+    /// Fields missing from `value` are zero, and unsigned values can be given for
+    /// any numeric field.
+    pub fn encode(
+        &self,
+        kind: TransferKind,
+        port: PortId,
+        value: &Value,
+    ) -> Option<(&str, Result<Vec<u8>, String>)> {
+        let (name, structure) = self.structure(kind, port)?;
+        let mut writer = BitWriter::default();
+        let result = self
+            .encode_structure(structure, Some(value), &mut writer, 0)
+            .map(|()| writer.into_bytes());
+        Some((name, result))
+    }
+
+    /// Type used on a port, and the half of it that `kind` carries.
+    fn structure(&self, kind: TransferKind, port: PortId) -> Option<(&str, &Structure)> {
+        let name = match kind {
+            TransferKind::Message => self.subjects.get(&port),
+            TransferKind::Request | TransferKind::Response => self.services.get(&port),
+        }?;
+        let structure = match (&self.types[name], kind) {
+            (Definition::Message(structure), _) => structure,
+            (Definition::Service { request, .. }, TransferKind::Request) => request,
+            (Definition::Service { response, .. }, _) => response,
+        };
+        Some((name, structure))
+    }
+
+    /// Look up a composite used as a field.
+    fn composite(&self, name: &str) -> Result<&Structure, String> {
+        match self.types.get(name) {
+            Some(Definition::Message(structure)) => Ok(structure),
+            Some(Definition::Service { .. }) => {
+                Err(format!("service type {} used as a field", name))
+            }
+            None => Err(format!("unknown type {}", name)),
+        }
+    }
+
+    fn decode_structure(
+        &self,
+        structure: &Structure,
+        reader: &mut BitReader,
+        depth: usize,
+    ) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("types nested too deeply".into());
+        }
+
+        if structure.union {
+            let options: Vec<&Field> = structure
+                .fields
+                .iter()
+                .filter(|field| field.name.is_some())
+                .collect();
+            let tag = reader.read(length_field_bits(options.len().saturating_sub(1))) as usize;
+            let field = options
+                .get(tag)
+                .ok_or_else(|| format!("union tag {} out of range", tag))?;
+            let value = self.decode_type(&field.ty, reader, depth)?;
+            return Ok(Value::Struct(vec![(field.name.clone().unwrap(), value)]));
+        }
+
+        let mut fields = Vec::new();
+        for field in &structure.fields {
+            let value = self.decode_type(&field.ty, reader, depth)?;
+            if let Some(name) = &field.name {
+                fields.push((name.clone(), value));
+            }
+        }
+        Ok(Value::Struct(fields))
+    }
+
+    fn decode_type(
+        &self,
+        ty: &Type,
+        reader: &mut BitReader,
+        depth: usize,
+    ) -> Result<Value, String> {
+        Ok(match ty {
+            Type::Bool => Value::Bool(reader.read(1) != 0),
+            Type::Unsigned(bits) => Value::Unsigned(reader.read(*bits)),
+            Type::Signed(bits) => {
+                let shift = 64 - bits;
+                Value::Signed((reader.read(*bits) << shift) as i64 >> shift)
+            }
+            Type::Float(16) => Value::Float(f16_to_f64(reader.read(16) as u16)),
+            Type::Float(32) => Value::Float(f32::from_bits(reader.read(32) as u32) as f64),
+            Type::Float(_) => Value::Float(f64::from_bits(reader.read(64))),
+            Type::Void(bits) => {
+                reader.read(*bits);
+                Value::Struct(Vec::new())
+            }
+            Type::Array(element, len) => Value::Array(
+                (0..*len)
+                    .map(|_| self.decode_type(element, reader, depth))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::VarArray(element, capacity) => {
+                let len = reader.read(length_field_bits(*capacity)) as usize;
+                if len > *capacity {
+                    return Err(format!("array length {} over capacity {}", len, capacity));
+                }
+                Value::Array(
+                    (0..len)
+                        .map(|_| self.decode_type(element, reader, depth))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Type::Composite(name) => {
+                let structure = self.composite(name)?;
+
+                // Composites are byte-aligned, and delimited ones have their length first
+                reader.align();
+                if structure.sealed {
+                    let value = self.decode_structure(structure, reader, depth + 1)?;
+                    reader.align();
+                    value
+                } else {
+                    let len = reader.read(32) as usize;
+                    let mut inner = reader.delimited(len);
+                    let value = self.decode_structure(structure, &mut inner, depth + 1)?;
+                    reader.skip(len * 8);
+                    value
+                }
+            }
+        })
+    }
+    /// Encode `value` as `structure`, or zeros if it's `None`.
+    fn encode_structure(
+        &self,
+        structure: &Structure,
+        value: Option<&Value>,
+        writer: &mut BitWriter,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err("types nested too deeply".into());
+        }
+        let given: &[(String, Value)] = match value {
+            Some(Value::Struct(fields)) => fields,
+            Some(value) => return Err(format!("expected a structure, not {}", value)),
+            None => &[],
+        };
+        let named = || structure.fields.iter().filter(|field| field.name.is_some());
+        for (name, _) in given {
+            if !named().any(|field| field.name.as_ref() == Some(name)) {
+                return Err(format!("unknown field {}", name));
+            }
+        }
+
+        if structure.union {
+            let (tag, field, value) = match given {
+                [(name, value)] => {
+                    let (tag, field) = named()
+                        .enumerate()
+                        .find(|(_, field)| field.name.as_ref() == Some(name))
+                        .unwrap();
+                    (tag, field, Some(value))
+                }
+                // Zeros select the first option
+                [] => (0, named().next().ok_or("empty union")?, None),
+                _ => return Err("a union takes exactly one field".into()),
+            };
+            let options = named().count();
+            writer.write(length_field_bits(options.saturating_sub(1)), tag as u64);
+            return self.encode_field(field, value, writer, depth);
+        }
+
+        for field in &structure.fields {
+            let value = field.name.as_ref().and_then(|name| {
+                given
+                    .iter()
+                    .find(|(given, _)| given == name)
+                    .map(|(_, value)| value)
+            });
+            self.encode_field(field, value, writer, depth)?;
+        }
+        Ok(())
+    }
+
+    fn encode_field(
+        &self,
+        field: &Field,
+        value: Option<&Value>,
+        writer: &mut BitWriter,
+        depth: usize,
+    ) -> Result<(), String> {
+        self.encode_type(&field.ty, value, writer, depth)
+            .map_err(|err| match &field.name {
+                Some(name) => format!("{}: {}", name, err),
+                None => err,
+            })
+    }
+
+    fn encode_type(
+        &self,
+        ty: &Type,
+        value: Option<&Value>,
+        writer: &mut BitWriter,
+        depth: usize,
+    ) -> Result<(), String> {
+        match ty {
+            Type::Bool => {
+                let bit = match value {
+                    Some(Value::Bool(value)) => *value,
+                    Some(Value::Unsigned(value @ (0 | 1))) => *value == 1,
+                    Some(value) => return Err(format!("{} isn't a bool", value)),
+                    None => false,
+                };
+                writer.write(1, bit as u64);
+            }
+            Type::Unsigned(bits) => {
+                let max = u64::MAX >> (64 - bits);
+                let integer = match value {
+                    Some(Value::Unsigned(value)) if *value <= max => *value,
+                    Some(Value::Signed(value)) if *value >= 0 && *value as u64 <= max => {
+                        *value as u64
+                    }
+                    Some(Value::Bool(value)) => *value as u64,
+                    Some(value) => return Err(format!("{} doesn't fit in uint{}", value, bits)),
+                    None => 0,
+                };
+                writer.write(*bits, integer);
+            }
+            Type::Signed(bits) => {
+                let max = i64::MAX >> (64 - bits);
+                let integer = match value {
+                    Some(Value::Signed(value)) if (-max - 1..=max).contains(value) => *value,
+                    Some(Value::Unsigned(value)) if *value <= max as u64 => *value as i64,
+                    Some(value) => return Err(format!("{} doesn't fit in int{}", value, bits)),
+                    None => 0,
+                };
+                writer.write(*bits, integer as u64);
+            }
+            Type::Float(bits) => {
+                let float = match value {
+                    Some(Value::Float(value)) => *value,
+                    Some(Value::Unsigned(value)) => *value as f64,
+                    Some(Value::Signed(value)) => *value as f64,
+                    Some(value) => return Err(format!("{} isn't a number", value)),
+                    None => 0.0,
+                };
+                match bits {
+                    16 => writer.write(16, f64_to_f16(float) as u64),
+                    32 => writer.write(32, (float as f32).to_bits() as u64),
+                    _ => writer.write(64, float.to_bits()),
+                }
+            }
+            Type::Void(bits) => writer.write(*bits, 0),
+            Type::Array(element, len) => {
+                let values = array_of(value)?;
+                match values {
+                    Some(values) if values.len() != *len => {
+                        return Err(format!("expected {} elements, not {}", len, values.len()))
+                    }
+                    _ => {}
+                }
+                for i in 0..*len {
+                    let value = values.map(|values| &values[i]);
+                    self.encode_type(element, value, writer, depth)?;
+                }
+            }
+            Type::VarArray(element, capacity) => {
+                let values = array_of(value)?.unwrap_or_default();
+                if values.len() > *capacity {
+                    return Err(format!(
+                        "{} elements over capacity {}",
+                        values.len(),
+                        capacity
+                    ));
+                }
+                writer.write(length_field_bits(*capacity), values.len() as u64);
+                for value in values {
+                    self.encode_type(element, Some(value), writer, depth)?;
+                }
+            }
+            Type::Composite(name) => {
+                let structure = self.composite(name)?;
+                writer.align();
+                if structure.sealed {
+                    self.encode_structure(structure, value, writer, depth + 1)?;
+                    writer.align();
+                } else {
+                    let mut inner = BitWriter::default();
+                    self.encode_structure(structure, value, &mut inner, depth + 1)?;
+                    let bytes = inner.into_bytes();
+                    writer.write(32, bytes.len() as u64);
+                    for byte in bytes {
+                        writer.write(8, byte as u64);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Elements of an array value. Strings come in as arrays of bytes already.
+fn array_of(value: Option<&Value>) -> Result<Option<&[Value]>, String> {
+    match value {
+        Some(Value::Array(values)) => Ok(Some(values)),
+        Some(value) => Err(format!("expected an array, not {}", value)),
+        None => Ok(None),
+    }
+}
+
+/// Parse a port's type given on the command line, as `PORT=full.type.Name.1.0`.
+pub fn parse_assignment(arg: &str) -> Result<(PortId, String), String> {
+    let (port, name) = arg
+        .split_once('=')
+        .ok_or("expected PORT=full.type.Name.1.0")?;
+    let port = port.parse().map_err(|_| format!("invalid port {}", port))?;
+    Ok((port, name.to_owned()))
+}
+
+/// Namespace of a full type name, i.e. everything before `Name.major.minor`.
+fn namespace_of(full_name: &str) -> Result<&str, String> {
+    let mut parts = full_name.rsplitn(4, '.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(_), Some(_), Some(namespace)) => Ok(namespace),
+        _ => Err(format!("{} isn't a full type name", full_name)),
+    }
+}
+
+fn parse(namespace: &str, source: &str) -> Result<Definition, String> {
+    let mut request = Structure::default();
+    let mut response = None;
+    let mut constants = HashMap::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        if line.is_empty() {
+            continue;
+        }
+        let structure = response.as_mut().unwrap_or(&mut request);
+
+        if line == "---" {
+            if response.is_some() {
+                return Err(error("more than one `---`"));
+            }
+            response = Some(Structure::default());
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('@') {
+            match directive.split_whitespace().next() {
+                Some("sealed") => structure.sealed = true,
+                Some("union") => structure.union = true,
+                // Extent only matters for encoding, and other directives not at all
+                _ => {}
+            }
+            continue;
+        }
+
+        // Constants are only kept for array capacities. Capacities can have `=` too.
+        let after_type = line.rfind(']').map_or(line, |end| &line[end..]);
+        if let Some((declaration, value)) = after_type.split_once('=') {
+            let name = declaration.split_whitespace().last().unwrap_or_default();
+            if let Ok(value) = value.trim().parse::<usize>() {
+                constants.insert(name.to_owned(), value);
+            }
+            continue;
+        }
+
+        let mut tokens = line
+            .split_whitespace()
+            .skip_while(|token| *token == "saturated" || *token == "truncated");
+        let ty = tokens.next().ok_or_else(|| error("missing type"))?;
+        let ty = parse_type(namespace, ty, &constants).map_err(|err| error(&err))?;
+        let name = tokens.next().map(str::to_owned);
+        if name.is_none() && !matches!(ty, Type::Void(_)) {
+            return Err(error("missing field name"));
+        }
+        structure.fields.push(Field { name, ty });
+    }
+
+    Ok(match response {
+        Some(response) => Definition::Service { request, response },
+        None => Definition::Message(request),
+    })
+}
+
+fn parse_type(
+    namespace: &str,
+    token: &str,
+    constants: &HashMap<String, usize>,
+) -> Result<Type, String> {
+    if let Some((element, suffix)) = token.split_once('[') {
+        let element = parse_type(namespace, element, constants)?;
+        let size = suffix
+            .strip_suffix(']')
+            .ok_or_else(|| format!("bad array type {}", token))?;
+        let value = |size: &str| {
+            size.trim()
+                .parse::<usize>()
+                .ok()
+                .or_else(|| constants.get(size.trim()).copied())
+                .ok_or_else(|| format!("unsupported array size {}", size))
+        };
+        return Ok(if let Some(capacity) = size.strip_prefix("<=") {
+            Type::VarArray(Box::new(element), value(capacity)?)
+        } else if let Some(bound) = size.strip_prefix('<') {
+            Type::VarArray(Box::new(element), value(bound)?.saturating_sub(1))
+        } else {
+            Type::Array(Box::new(element), value(size)?)
+        });
+    }
+
+    if token == "bool" {
+        return Ok(Type::Bool);
+    }
+    let bits = |prefix| {
+        token
+            .strip_prefix(prefix)
+            .and_then(|bits: &str| bits.parse::<u32>().ok())
+    };
+    let primitive = if let Some(bits) = bits("uint") {
+        Some(Type::Unsigned(bits))
+    } else if let Some(bits) = bits("int") {
+        Some(Type::Signed(bits))
+    } else if let Some(bits) = bits("float") {
+        Some(Type::Float(bits))
+    } else {
+        bits("void").map(Type::Void)
+    };
+    match primitive {
+        Some(
+            ty @ (Type::Unsigned(1..=64)
+            | Type::Signed(1..=64)
+            | Type::Float(16 | 32 | 64)
+            | Type::Void(1..=64)),
+        ) => return Ok(ty),
+        Some(_) => return Err(format!("invalid type {}", token)),
+        None => {}
+    }
+
+    // Composite types are `[namespace.]Name.major.minor`
+    match token.split('.').count() {
+        0..=2 => Err(format!("unknown type {}", token)),
+        3 => Ok(Type::Composite(format!("{}.{}", namespace, token))),
+        _ => Ok(Type::Composite(token.to_owned())),
+    }
+}
+
+/// Size of the length prefix of a variable-length array, or the tag of a union, that
+/// has to hold `max`.
+fn length_field_bits(max: usize) -> u32 {
+    let bits = usize::BITS - max.leading_zeros();
+    bits.max(8).next_power_of_two()
+}
+
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let fraction = (bits & 0x3FF) as f64;
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        0x1F if fraction == 0.0 => f64::INFINITY,
+        0x1F => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// Nearest float16, rounding halfway cases to even.
+fn f64_to_f16(value: f64) -> u16 {
+    let bits = (value as f32).to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent == 0xFF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    // Shift the full 24-bit significand down to 10 bits, or fewer when subnormal
+    let exponent = exponent - 127 + 15;
+    let (significand, shift) = if exponent >= 1 {
+        (mantissa | 0x80_0000, 13)
+    } else {
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    };
+    if shift > 24 {
+        return sign;
+    }
+    let half = 1 << (shift - 1);
+    let remainder = significand & ((1 << shift) - 1);
+    let mut rounded = significand >> shift;
+    if remainder > half || (remainder == half && rounded & 1 == 1) {
+        rounded += 1;
+    }
+
+    // Rounding can carry into the exponent, which the addition below takes care of
+    let biased = if exponent >= 1 {
+        ((exponent as u32) << 10) + (rounded - 0x400)
+    } else {
+        rounded
+    };
+    if biased >= 0x7C00 {
+        sign | 0x7C00
+    } else {
+        sign | biased as u16
+    }
+}
+
+/// Little-endian bit stream, reading zeros past the end, as Cyphal's implicit
+/// zero extension rule requires.
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    /// Bits past here read as zero.
+    limit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            offset: 0,
+            limit: data.len() * 8,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> u64 {
+        let mut value = 0;
+        for i in 0..bits {
+            let bit = if self.offset < self.limit {
+                self.data
+                    .get(self.offset / 8)
+                    .map_or(0, |byte| (byte >> (self.offset % 8)) & 1)
+            } else {
+                0
+            };
+            value |= (bit as u64) << i;
+            self.offset += 1;
+        }
+        value
+    }
+
+    fn align(&mut self) {
+        self.offset = self.offset.div_ceil(8) * 8;
+    }
+
+    fn skip(&mut self, bits: usize) {
+        self.offset += bits;
+    }
+
+    /// Reader for a delimited composite of `len` bytes starting here.
+    fn delimited(&self, len: usize) -> BitReader<'a> {
+        BitReader {
+            data: self.data,
+            offset: self.offset,
+            limit: self.limit.min(self.offset + len * 8),
+        }
+    }
+}
+
+/// Little-endian bit stream, for encoding.
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, value: u64) {
+        for i in 0..bits {
+            if self.offset.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.data.last_mut().unwrap() |= bit << (self.offset % 8);
+            self.offset += 1;
+        }
+    }
+
+    fn align(&mut self) {
+        self.offset = self.data.len() * 8;
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn heartbeat_types() -> TypeSet {
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "uavcan.node.Health.1.0",
+                None,
+                "uint2 value\nuint2 NOMINAL = 0\n@sealed\n",
+            )
+            .unwrap();
+        types
+            .add("uavcan.node.Mode.1.0", None, "uint3 value\n@sealed\n")
+            .unwrap();
+        types
+            .add(
+                "uavcan.node.Heartbeat.1.0",
+                Some(7509),
+                "# Abridged\n\
+                 uint16 MAX_PUBLICATION_PERIOD = 1\n\
+                 uint32 uptime\n\
+                 Health.1.0 health\n\
+                 Mode.1.0 mode\n\
+                 uint8 vendor_specific_status_code\n\
+                 @extent 12 * 8\n",
+            )
+            .unwrap();
+        types
+    }
+
+    #[test]
+    fn decode_heartbeat() {
+        let types = heartbeat_types();
+        let (name, value) = types
+            .decode(TransferKind::Message, 7509, &[0x10, 0, 0, 0, 2, 1, 0xAB])
+            .unwrap();
+        assert_eq!(name, "uavcan.node.Heartbeat.1.0");
+        assert_eq!(
+            value.unwrap().to_string(),
+            "{uptime: 16, health: {value: 2}, mode: {value: 1}, vendor_specific_status_code: 171}"
+        );
+
+        // Truncated payloads are zero-extended
+        let (_, value) = types.decode(TransferKind::Message, 7509, &[1]).unwrap();
+        assert_eq!(
+            value.unwrap().to_string(),
+            "{uptime: 1, health: {value: 0}, mode: {value: 0}, vendor_specific_status_code: 0}"
+        );
+
+        assert!(types.decode(TransferKind::Message, 7510, &[]).is_none());
+        assert!(types.decode(TransferKind::Request, 7509, &[]).is_none());
+    }
+
+    #[test]
+    fn decode_arrays_and_primitives() {
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "test.Mixed.1.0",
+                None,
+                "uint8 MAX = 3\n\
+                 bool flag\n\
+                 void3\n\
+                 int4 small\n\
+                 float16 half\n\
+                 uint8[<=MAX] bytes\n\
+                 int16[2] pair\n\
+                 @sealed\n",
+            )
+            .unwrap();
+        types.assign(100, "test.Mixed.1.0").unwrap();
+
+        let payload = [
+            0b1110_0001, // flag, padding, small = -2
+            0x00,
+            0x3C, // 1.0
+            2,
+            0xAA,
+            0xBB,
+            0xFF,
+            0xFF, // -1
+            0x02,
+            0x00,
+        ];
+        let (_, value) = types.decode(TransferKind::Message, 100, &payload).unwrap();
+        assert_eq!(
+            value.unwrap().to_string(),
+            "{flag: true, small: -2, half: 1, bytes: [170, 187], pair: [-1, 2]}"
+        );
+
+        // Length prefix over the capacity
+        let (_, value) = types
+            .decode(TransferKind::Message, 100, &[0, 0, 0, 4])
+            .unwrap();
+        assert!(value.is_err());
+    }
+
+    #[test]
+    fn decode_services_and_unions() {
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "test.Choice.1.0",
+                None,
+                "@union\nuint8 a\nuint16 b\n@sealed\n",
+            )
+            .unwrap();
+        types
+            .add("test.Inner.1.0", None, "uint8 x\nuint8 y\n@extent 8 * 8\n")
+            .unwrap();
+        types
+            .add(
+                "test.Call.1.0",
+                Some(10),
+                "test.Inner.1.0 inner\nuint8 after\n@sealed\n---\nChoice.1.0 choice\n@sealed\n",
+            )
+            .unwrap();
+
+        // Delimited composite that's grown a field since this definition
+        let request = [3, 0, 0, 0, 1, 2, 9, 7];
+        let (name, value) = types.decode(TransferKind::Request, 10, &request).unwrap();
+        assert_eq!(name, "test.Call.1.0");
+        assert_eq!(
+            value.unwrap().to_string(),
+            "{inner: {x: 1, y: 2}, after: 7}"
+        );
+
+        let (_, value) = types
+            .decode(TransferKind::Response, 10, &[1, 0x34, 0x12])
+            .unwrap();
+        assert_eq!(value.unwrap().to_string(), "{choice: {b: 4660}}");
+
+        let (_, value) = types.decode(TransferKind::Response, 10, &[2]).unwrap();
+        assert!(value.is_err());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let types = heartbeat_types();
+        let value =
+            crate::yaml::parse("{uptime: 16, health: {value: 2}, mode: {value: 1}}").unwrap();
+        let (name, payload) = types.encode(TransferKind::Message, 7509, &value).unwrap();
+        assert_eq!(name, "uavcan.node.Heartbeat.1.0");
+        let payload = payload.unwrap();
+        assert_eq!(payload, [0x10, 0, 0, 0, 2, 1, 0]);
+        let (_, decoded) = types.decode(TransferKind::Message, 7509, &payload).unwrap();
+        assert_eq!(
+            decoded.unwrap().to_string(),
+            "{uptime: 16, health: {value: 2}, mode: {value: 1}, vendor_specific_status_code: 0}"
+        );
+
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "test.Mixed.1.0",
+                None,
+                "bool flag
+void3
+int4 small
+float16 half
+uint8[<=3] bytes
+int16[2] pair
+@sealed
+",
+            )
+            .unwrap();
+        types.assign(100, "test.Mixed.1.0").unwrap();
+        let value = crate::yaml::parse(
+            "{flag: true, small: -2, half: 1, bytes: [170, 187], pair: [-1, 2]}",
+        )
+        .unwrap();
+        let (_, payload) = types.encode(TransferKind::Message, 100, &value).unwrap();
+        assert_eq!(
+            payload.unwrap(),
+            [
+                0b1110_0001,
+                0x00,
+                0x3C,
+                2,
+                0xAA,
+                0xBB,
+                0xFF,
+                0xFF,
+                0x02,
+                0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_services_and_unions() {
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "test.Choice.1.0",
+                None,
+                "@union\nuint8 a\nuint16 b\n@sealed\n",
+            )
+            .unwrap();
+        types
+            .add("test.Inner.1.0", None, "uint8 x\nuint8 y\n@extent 8 * 8\n")
+            .unwrap();
+        types
+            .add(
+                "test.Call.1.0",
+                Some(10),
+                "test.Inner.1.0 inner\nuint8 after\n@sealed\n---\nChoice.1.0 choice\n@sealed\n",
+            )
+            .unwrap();
+
+        let request = crate::yaml::parse("{inner: {x: 1, y: 2}, after: 7}").unwrap();
+        let (_, payload) = types.encode(TransferKind::Request, 10, &request).unwrap();
+        assert_eq!(payload.unwrap(), [2, 0, 0, 0, 1, 2, 7]);
+
+        let response = crate::yaml::parse("{choice: {b: 4660}}").unwrap();
+        let (_, payload) = types.encode(TransferKind::Response, 10, &response).unwrap();
+        assert_eq!(payload.unwrap(), [1, 0x34, 0x12]);
+
+        // An empty union picks the first option
+        let (_, payload) = types
+            .encode(TransferKind::Response, 10, &Value::Struct(vec![]))
+            .unwrap();
+        assert_eq!(payload.unwrap(), [0, 0]);
+    }
+
+    #[test]
+    fn encode_errors() {
+        let types = heartbeat_types();
+        let encode = |yaml: &str| {
+            let value = crate::yaml::parse(yaml).unwrap();
+            types.encode(TransferKind::Message, 7509, &value).unwrap().1
+        };
+        assert!(encode("{uptime: 16}").is_ok());
+        assert_eq!(
+            encode("{uptime: -1}").unwrap_err(),
+            "uptime: -1 doesn't fit in uint32"
+        );
+        assert_eq!(
+            encode("{health: {value: 4}}").unwrap_err(),
+            "health: value: 4 doesn't fit in uint2"
+        );
+        assert_eq!(encode("{speed: 1}").unwrap_err(), "unknown field speed");
+        assert!(encode("{uptime: [1]}").is_err());
+        assert!(encode("[1]").is_err());
+        assert!(types
+            .encode(TransferKind::Message, 1, &Value::Struct(vec![]))
+            .is_none());
+
+        let mut types = TypeSet::new();
+        types
+            .add(
+                "test.Choice.1.0",
+                Some(20),
+                "@union\nuint8 a\nuint16 b\nint8[<=2] c\n@sealed\n",
+            )
+            .unwrap();
+        let encode = |yaml: &str| {
+            let value = crate::yaml::parse(yaml).unwrap();
+            types.encode(TransferKind::Message, 20, &value).unwrap().1
+        };
+        assert!(encode("{a: 1, b: 2}").is_err());
+        assert!(encode("{c: [1, 2, 3]}").is_err());
+        assert!(encode("{c: [-128, 127]}").is_ok());
+        assert!(encode("{c: [-129]}").is_err());
+    }
+
+    #[test]
+    fn float16_conversion() {
+        // Exactly representable
+        for (value, bits) in [
+            (1.0, 0x3C00),
+            (-2.0, 0xC000),
+            (65504.0, 0x7BFF),
+            (2f64.powi(-24), 0x0001),
+            (2f64.powi(-14), 0x0400),
+        ] {
+            assert_eq!(f64_to_f16(value), bits, "{}", value);
+            assert_eq!(f16_to_f64(bits), value);
+        }
+
+        // Rounded, halfway to even
+        assert_eq!(f64_to_f16(1.0 + 2f64.powi(-11)), 0x3C00);
+        assert_eq!(f64_to_f16(1.0 + 3.0 * 2f64.powi(-11)), 0x3C02);
+        assert_eq!(f64_to_f16(1e-10), 0x0000);
+        assert_eq!(f64_to_f16(65520.0), 0x7C00);
+        assert_eq!(f64_to_f16(f64::INFINITY), 0x7C00);
+        assert!(f16_to_f64(f64_to_f16(f64::NAN)).is_nan());
+    }
+
+    #[test]
+    fn load_namespace_directory() {
+        let root = std::env::temp_dir().join(format!("cyphal-mon-{}", std::process::id()));
+        let dir = root.join("reg").join("demo");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1234.Ping.1.0.dsdl"), "uint8 count\n@sealed\n").unwrap();
+        fs::write(dir.join("README.md"), "not a definition").unwrap();
+
+        let mut types = TypeSet::new();
+        let result = types.load_namespace(&root.join("reg"));
+        fs::remove_dir_all(&root).unwrap();
+        result.unwrap();
+
+        let (name, value) = types.decode(TransferKind::Message, 1234, &[5]).unwrap();
+        assert_eq!(name, "reg.demo.Ping.1.0");
+        assert_eq!(
+            value.unwrap(),
+            Value::Struct(vec![("count".into(), Value::Unsigned(5))])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut types = TypeSet::new();
+        assert!(types.add("test.Bad.1.0", None, "uint65 x\n").is_err());
+        assert!(types.add("test.Bad.1.0", None, "float8 x\n").is_err());
+        assert!(types.add("test.Bad.1.0", None, "uint8[<=N] x\n").is_err());
+        assert!(types.add("test.Bad.1.0", None, "uint8\n").is_err());
+        assert!(types.add("Bad", None, "uint8 x\n").is_err());
+        assert!(types.assign(1, "test.Missing.1.0").is_err());
+    }
+}
@@ -0,0 +1,7 @@
+//! Command-line tools for Cyphal/CAN: `cyphal-mon` prints the traffic on a bus, and
+//! `cyphal-cmd` publishes messages and calls services.
+
+pub mod command;
+pub mod dsdl;
+pub mod monitor;
+pub mod yaml;
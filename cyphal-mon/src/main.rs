@@ -0,0 +1,181 @@
+//! Cyphal/CAN bus monitor.
+//!
+//! Prints every transfer on a live interface, or in a `candump -l` log or pcap
+//! capture, and counts frames that couldn't be received.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use cyphal::media::candump::Replay;
+use cyphal::media::pcap::PcapReader;
+use cyphal::media::AnyCanFrame;
+use cyphal::time::StdClock;
+use cyphal::transport::can::CanFrame;
+use cyphal::types::{NodeId, PortId};
+use cyphal_mon::dsdl::{parse_assignment, TypeSet};
+use cyphal_mon::monitor::{Filter, Monitor};
+
+#[derive(Parser)]
+#[command(
+    name = "cyphal-mon",
+    about = "Print Cyphal/CAN transfers from a bus or a recording"
+)]
+#[command(group = clap::ArgGroup::new("input").required(true))]
+struct Args {
+    /// CAN interface to attach to, e.g. can0
+    #[arg(short, long, group = "input")]
+    iface: Option<String>,
+    /// candump -l log to read
+    #[arg(long, group = "input")]
+    candump: Option<PathBuf>,
+    /// pcap capture (LINKTYPE_CAN_SOCKETCAN) to read
+    #[arg(long, group = "input")]
+    pcap: Option<PathBuf>,
+
+    /// Only show these subjects (repeatable)
+    #[arg(short, long = "subject")]
+    subjects: Vec<PortId>,
+    /// Only show these services (repeatable)
+    #[arg(long = "service")]
+    services: Vec<PortId>,
+    /// Only show transfers from or to these nodes (repeatable)
+    #[arg(short, long = "node")]
+    nodes: Vec<NodeId>,
+
+    /// Root namespace directory of DSDL definitions, e.g. public_regulated_data_types/uavcan
+    #[arg(long)]
+    dsdl: Vec<PathBuf>,
+    /// Decode a port with a type that has no fixed port ID, as PORT=full.type.Name.1.0
+    #[arg(long = "type", value_parser = parse_assignment)]
+    types: Vec<(PortId, String)>,
+
+    /// How often to print error counts while attached to an interface, in seconds
+    #[arg(long, default_value_t = 10)]
+    stats: u64,
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cyphal-mon: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut types = TypeSet::new();
+    for dir in &args.dsdl {
+        types.load_namespace(dir)?;
+    }
+    for (port, name) in &args.types {
+        types.assign(*port, name)?;
+    }
+
+    let mut monitor = Monitor::new(Filter {
+        subjects: args.subjects.clone(),
+        services: args.services.clone(),
+        nodes: args.nodes.clone(),
+    });
+
+    if let Some(iface) = &args.iface {
+        return attach(iface, &mut monitor, &types, Duration::from_secs(args.stats));
+    }
+
+    let frames: Box<dyn Iterator<Item = Result<AnyCanFrame<StdClock>, String>>> =
+        if let Some(path) = &args.candump {
+            let log =
+                fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let frames: Vec<_> = Replay::new(&log)
+                .map(|frame| frame.map_err(|err| format!("{}: {:?}", path.display(), err)))
+                .collect();
+            Box::new(frames.into_iter())
+        } else if let Some(path) = &args.pcap {
+            let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let path = path.display().to_string();
+            Box::new(
+                PcapReader::new(BufReader::new(file))
+                    .map_err(|err| format!("{}: {}", path, err))?
+                    .map(move |frame| frame.map_err(|err| format!("{}: {}", path, err))),
+            )
+        } else {
+            unreachable!("clap requires an input");
+        };
+
+    for frame in frames {
+        match frame? {
+            AnyCanFrame::Classic(frame) => show(&mut monitor, &types, frame),
+            AnyCanFrame::Fd(_) => count(&mut monitor, "CAN FD frame, not supported"),
+        }
+    }
+    print_errors(&monitor);
+    Ok(())
+}
+
+#[cfg(feature = "socketcan")]
+fn attach(
+    iface: &str,
+    monitor: &mut Monitor,
+    types: &TypeSet,
+    stats: Duration,
+) -> Result<(), String> {
+    use std::time::Instant;
+
+    use cyphal::media::socketcan::SocketCan;
+    use cyphal::media::CanMedia;
+
+    let mut can =
+        SocketCan::open(iface, StdClock::new()).map_err(|err| format!("{}: {}", iface, err))?;
+    can.set_read_timeout(Some(Duration::from_millis(100)))
+        .map_err(|err| format!("{}: {}", iface, err))?;
+
+    let mut last_stats = Instant::now();
+    let mut reported = monitor.errors.clone();
+    loop {
+        if let Some(frame) = can.receive().map_err(|err| format!("{}: {}", iface, err))? {
+            show(monitor, types, frame);
+        }
+        if last_stats.elapsed() >= stats && monitor.errors != reported {
+            print_errors(monitor);
+            reported = monitor.errors.clone();
+            last_stats = Instant::now();
+        }
+    }
+}
+
+#[cfg(not(feature = "socketcan"))]
+fn attach(iface: &str, _: &mut Monitor, _: &TypeSet, _: Duration) -> Result<(), String> {
+    Err(format!("{}: built without SocketCAN support", iface))
+}
+
+fn show(monitor: &mut Monitor, types: &TypeSet, frame: CanFrame<StdClock>) {
+    let transfer = match monitor.process(frame) {
+        Some(transfer) => transfer,
+        None => return,
+    };
+    println!("{}", transfer);
+    match types.decode(transfer.kind, transfer.port, &transfer.payload) {
+        Some((name, Ok(value))) => println!("    {} {}", name, value),
+        Some((name, Err(err))) => println!("    {} (undecodable: {})", name, err),
+        None => {}
+    }
+}
+
+fn count(monitor: &mut Monitor, error: &str) {
+    *monitor.errors.entry(error.to_owned()).or_default() += 1;
+}
+
+fn print_errors(monitor: &Monitor) {
+    if monitor.errors.is_empty() {
+        return;
+    }
+    eprintln!("rejected frames:");
+    for (error, count) in &monitor.errors {
+        eprintln!("    {:<52} {}", error, count);
+    }
+}
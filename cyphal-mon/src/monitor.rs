@@ -0,0 +1,455 @@
+//! Reassembles every transfer on a bus, whoever it's addressed to.
+//!
+//! A `Node` only receives services addressed to itself, so the monitor keeps one
+//! node per destination it sees, along with one for all messages. Their sessions
+//! are kept per port, which is subscribed to when its first frame turns up.
+//! Anonymous messages don't have sessions, and are decoded straight from their
+//! single frame.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use cyphal::session::{
+    InternalRxFrame, SessionError, SessionManager, StdVecSessionManager, SubscriptionError,
+};
+use cyphal::time::{StdClock, Timestamp};
+use cyphal::transfer::RefTransfer;
+use cyphal::transport::can::{Can, CanFrame, CanMessageId, CanMetadata, CanServiceId, TailByte};
+use cyphal::types::{NodeId, PortId, TransferId};
+use cyphal::{Node, Priority, RxError, RxErrorKind, Subscription, TransferKind};
+use embedded_time::duration::{Microseconds, Milliseconds};
+use embedded_time::fixed_point::FixedPoint;
+use num_traits::FromPrimitive;
+
+type MonitorNode = Node<MonitorSessions, Can, StdClock>;
+
+/// Largest transfer that will be shown in full.
+const EXTENT: usize = 4096;
+
+/// Which transfers to show. Empty lists match everything.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub subjects: Vec<PortId>,
+    pub services: Vec<PortId>,
+    /// Matches either the source or the destination.
+    pub nodes: Vec<NodeId>,
+}
+
+impl Filter {
+    fn matches(&self, transfer: &Captured) -> bool {
+        let port_matches = match (self.subjects.is_empty(), self.services.is_empty()) {
+            (true, true) => true,
+            _ => match transfer.kind {
+                TransferKind::Message => self.subjects.contains(&transfer.port),
+                _ => self.services.contains(&transfer.port),
+            },
+        };
+        let node_matches = self.nodes.is_empty()
+            || [transfer.source, transfer.destination]
+                .iter()
+                .flatten()
+                .any(|node| self.nodes.contains(node));
+        port_matches && node_matches
+    }
+}
+
+/// A reassembled transfer.
+#[derive(Clone, Debug)]
+pub struct Captured {
+    /// Time of the first frame, in µs.
+    pub time: u64,
+    pub priority: Priority,
+    pub kind: TransferKind,
+    pub port: PortId,
+    /// `None` for anonymous messages.
+    pub source: Option<NodeId>,
+    /// `None` for messages.
+    pub destination: Option<NodeId>,
+    pub transfer_id: TransferId,
+    pub payload: Vec<u8>,
+}
+
+impl fmt::Display for Captured {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            TransferKind::Message => "msg",
+            TransferKind::Request => "req",
+            TransferKind::Response => "rsp",
+        };
+        let node = |node: Option<NodeId>| node.map_or("-".to_owned(), |node| node.to_string());
+        write!(
+            f,
+            "{:>6}.{:06} {:<11} {} {:>4} {:>3} -> {:<3} tid {:>2} [{}]",
+            self.time / 1_000_000,
+            self.time % 1_000_000,
+            format!("{:?}", self.priority),
+            kind,
+            self.port,
+            node(self.source),
+            node(self.destination),
+            self.transfer_id,
+            self.payload.len(),
+        )?;
+        for byte in &self.payload {
+            write!(f, " {:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Monitor {
+    messages: MonitorNode,
+    /// Nodes receiving services, by destination.
+    services: HashMap<NodeId, MonitorNode>,
+    filter: Filter,
+    /// How many frames were rejected, by reason.
+    pub errors: BTreeMap<String, usize>,
+}
+
+impl Monitor {
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            messages: Node::new(None, MonitorSessions::default()),
+            services: HashMap::new(),
+            filter,
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Take in a frame, returning the transfer it completes if that passes the filter.
+    pub fn process(&mut self, frame: CanFrame<StdClock>) -> Option<Captured> {
+        let id = frame.id.as_raw();
+        let result = if CanServiceId(id).is_svc() {
+            let destination = CanServiceId(id).destination_id();
+            self.services
+                .entry(destination)
+                .or_insert_with(|| service_node(destination))
+                .try_receive_frame(frame)
+                .map(|transfer| {
+                    transfer.map(|transfer| Captured {
+                        time: micros(transfer.metadata.timestamp),
+                        priority: transfer.metadata.priority,
+                        kind: transfer.metadata.transfer_kind,
+                        port: transfer.metadata.port_id,
+                        source: transfer.metadata.remote_node_id,
+                        destination: Some(destination),
+                        transfer_id: transfer.metadata.transfer_id,
+                        payload: transfer.payload.to_vec(),
+                    })
+                })
+        } else if CanMessageId(id).is_anon() {
+            anonymous(&frame)
+        } else {
+            self.messages.try_receive_frame(frame).map(|transfer| {
+                transfer.map(|transfer| Captured {
+                    time: micros(transfer.metadata.timestamp),
+                    priority: transfer.metadata.priority,
+                    kind: transfer.metadata.transfer_kind,
+                    port: transfer.metadata.port_id,
+                    source: transfer.metadata.remote_node_id,
+                    destination: None,
+                    transfer_id: transfer.metadata.transfer_id,
+                    payload: transfer.payload.to_vec(),
+                })
+            })
+        };
+
+        match result {
+            Ok(transfer) => transfer.filter(|transfer| self.filter.matches(transfer)),
+            Err(err) => {
+                *self.errors.entry(err.kind.to_string()).or_default() += 1;
+                None
+            }
+        }
+    }
+}
+
+fn subscription(kind: TransferKind, port: PortId) -> Subscription {
+    Subscription::new(kind, port, EXTENT, Milliseconds(2000))
+}
+
+fn service_node(node_id: NodeId) -> MonitorNode {
+    Node::new(Some(node_id), MonitorSessions::default())
+}
+
+/// Session manager that takes every port, keeping a session manager per port.
+///
+/// Ports are subscribed to on their first frame, so a frame only has to be matched
+/// against its own port's subscription, however many ports are on the bus.
+#[derive(Default)]
+struct MonitorSessions {
+    ports: BTreeMap<(TransferKind, PortId), StdVecSessionManager<CanMetadata, StdClock>>,
+}
+
+impl SessionManager<StdClock> for MonitorSessions {
+    fn ingest<'a>(
+        &'a mut self,
+        frame: InternalRxFrame<StdClock>,
+    ) -> Result<Option<RefTransfer<'a, StdClock>>, SessionError> {
+        let (kind, port) = (frame.transfer_kind, frame.port_id);
+        self.ports
+            .entry((kind, port))
+            .or_insert_with(|| {
+                let mut sessions = StdVecSessionManager::new();
+                sessions.subscribe(subscription(kind, port)).unwrap();
+                sessions
+            })
+            .ingest(frame)
+    }
+
+    fn update_sessions(&mut self, timestamp: Timestamp<StdClock>) {
+        for sessions in self.ports.values_mut() {
+            sessions.update_sessions(timestamp);
+        }
+    }
+
+    fn subscribe(&mut self, subscription: Subscription) -> Result<(), SubscriptionError> {
+        match self
+            .ports
+            .entry((subscription.transfer_kind(), subscription.port_id()))
+        {
+            Entry::Occupied(_) => Err(SubscriptionError::SubscriptionExists),
+            Entry::Vacant(entry) => entry
+                .insert(StdVecSessionManager::new())
+                .subscribe(subscription),
+        }
+    }
+
+    fn unsubscribe(&mut self, subscription: Subscription) -> Result<(), SubscriptionError> {
+        let key = (subscription.transfer_kind(), subscription.port_id());
+        match self.ports.get(&key) {
+            Some(sessions) if sessions.subscriptions().any(|s| *s == subscription) => {
+                self.ports.remove(&key);
+                Ok(())
+            }
+            _ => Err(SubscriptionError::SubscriptionDoesNotExist),
+        }
+    }
+
+    fn subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.ports
+            .values()
+            .flat_map(|sessions| sessions.subscriptions())
+    }
+}
+
+/// Anonymous messages are always single frames, so there's nothing to reassemble.
+fn anonymous(frame: &CanFrame<StdClock>) -> Result<Option<Captured>, RxError> {
+    let id = CanMessageId(frame.id.as_raw());
+    let (&tail, payload) = frame
+        .payload
+        .split_last()
+        .ok_or(RxError::new(RxErrorKind::FrameEmpty))?;
+    if !id.valid() {
+        return Err(RxErrorKind::InvalidCanId.into());
+    }
+    let tail = TailByte(tail);
+    if !(tail.start_of_transfer() && tail.end_of_transfer() && tail.toggle()) {
+        return Err(RxErrorKind::AnonNotSingleFrame.into());
+    }
+
+    Ok(Some(Captured {
+        time: micros(frame.timestamp),
+        priority: Priority::from_u8(id.priority()).unwrap(),
+        kind: TransferKind::Message,
+        port: id.subject_id(),
+        source: None,
+        destination: None,
+        transfer_id: tail.transfer_id(),
+        payload: payload.to_vec(),
+    }))
+}
+
+fn micros(timestamp: Timestamp<StdClock>) -> u64 {
+    Microseconds::<u64>::try_from(timestamp.duration_since_epoch())
+        .map(|micros| micros.integer())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use cyphal::media::candump::Replay;
+    use cyphal::media::AnyCanFrame;
+    use cyphal::transfer::{RefTransfer, TransferMetadata};
+    use cyphal::StreamingIterator;
+
+    use super::*;
+
+    fn frames_of(
+        source: NodeId,
+        metadata: TransferMetadata<StdClock>,
+        payload: &[u8],
+    ) -> Vec<CanFrame<StdClock>> {
+        let node = MonitorNode::new(Some(source), MonitorSessions::default());
+        let transfer = RefTransfer { metadata, payload };
+        let mut frames = Vec::new();
+        let mut iter = node.transmit(&transfer).unwrap();
+        while let Some(frame) = iter.next() {
+            frames.push(frame.clone());
+        }
+        frames
+    }
+
+    fn metadata(
+        kind: TransferKind,
+        port: PortId,
+        remote: Option<NodeId>,
+    ) -> TransferMetadata<StdClock> {
+        TransferMetadata {
+            timestamp: Timestamp::new(1_500_000),
+            priority: Priority::Nominal,
+            transfer_kind: kind,
+            port_id: port,
+            remote_node_id: remote,
+            transfer_id: 5,
+        }
+    }
+
+    #[test]
+    fn captures_messages_and_services() {
+        let mut monitor = Monitor::new(Filter::default());
+        let payload: Vec<u8> = (0..12).collect();
+
+        let mut captured = Vec::new();
+        for frame in frames_of(10, metadata(TransferKind::Message, 7509, None), &payload)
+            .into_iter()
+            .chain(frames_of(
+                10,
+                metadata(TransferKind::Request, 430, Some(20)),
+                &[1],
+            ))
+            .chain(frames_of(
+                20,
+                metadata(TransferKind::Response, 430, Some(10)),
+                &[2],
+            ))
+        {
+            captured.extend(monitor.process(frame));
+        }
+
+        assert_eq!(captured.len(), 3);
+        assert_eq!(captured[0].payload, payload);
+        assert_eq!(
+            (captured[0].source, captured[0].destination),
+            (Some(10), None)
+        );
+        assert!(matches!(captured[1].kind, TransferKind::Request));
+        assert_eq!(
+            (captured[1].source, captured[1].destination),
+            (Some(10), Some(20))
+        );
+        assert_eq!(
+            (captured[2].source, captured[2].destination),
+            (Some(20), Some(10))
+        );
+        assert!(monitor.errors.is_empty());
+
+        // Only the ports seen have been subscribed to
+        let subscriptions = |node: &MonitorNode| {
+            node.sessions
+                .subscriptions()
+                .map(|s| (s.transfer_kind(), s.port_id()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            subscriptions(&monitor.messages),
+            [(TransferKind::Message, 7509)]
+        );
+        assert_eq!(
+            subscriptions(&monitor.services[&20]),
+            [(TransferKind::Request, 430)]
+        );
+        assert_eq!(
+            subscriptions(&monitor.services[&10]),
+            [(TransferKind::Response, 430)]
+        );
+
+        assert_eq!(
+            captured[1].to_string(),
+            "     1.500000 Nominal     req  430  10 -> 20  tid  5 [1] 01"
+        );
+    }
+
+    #[test]
+    fn anonymous_messages() {
+        let mut monitor = Monitor::new(Filter::default());
+        let frame = frames_of(0, metadata(TransferKind::Message, 8184, None), &[7, 8])
+            .pop()
+            .unwrap();
+        let mut frame = frame;
+        frame.id = CanMessageId::new(Priority::Nominal, 8184, None);
+
+        let captured = monitor.process(frame.clone()).unwrap();
+        assert_eq!(captured.source, None);
+        assert_eq!(captured.port, 8184);
+        assert_eq!(captured.payload, [7, 8]);
+
+        // Clear the start of transfer flag
+        let tail = frame.payload.last_mut().unwrap();
+        let mut tail_byte = TailByte(*tail);
+        tail_byte.set_start_of_transfer(false);
+        *tail = tail_byte.0;
+        assert!(monitor.process(frame).is_none());
+        assert_eq!(monitor.errors["multi-frame anonymous transfer"], 1);
+    }
+
+    #[test]
+    fn filters() {
+        let log = "\
+(0.000000) can0 107D550A#00000000000000E0
+(0.000000) can0 107D5514#00000000000000E0
+(0.000000) can0 1060640A#01E0
+";
+        let captured = |filter: Filter| {
+            let mut monitor = Monitor::new(filter);
+            Replay::<StdClock>::new(log)
+                .filter_map(|frame| match frame.unwrap() {
+                    AnyCanFrame::Classic(frame) => monitor.process(frame),
+                    AnyCanFrame::Fd(_) => None,
+                })
+                .map(|transfer| (transfer.port, transfer.source.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(captured(Filter::default()).len(), 3);
+        assert_eq!(
+            captured(Filter {
+                subjects: vec![100],
+                ..Filter::default()
+            }),
+            [(100, 10)]
+        );
+        assert_eq!(
+            captured(Filter {
+                nodes: vec![20],
+                ..Filter::default()
+            }),
+            [(7509, 20)]
+        );
+        assert!(captured(Filter {
+            services: vec![430],
+            ..Filter::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn errors_counted() {
+        let mut monitor = Monitor::new(Filter::default());
+        let log = "\
+(0.000000) can0 107D550A#
+(0.000000) can0 107D550A#0000000000000001
+(0.100000) can0 107D550A#00000000000000E0
+(0.200000) can0 107D550A#00000000000000E0
+";
+        for frame in Replay::<StdClock>::new(log) {
+            if let AnyCanFrame::Classic(frame) = frame.unwrap() {
+                monitor.process(frame);
+            }
+        }
+        assert_eq!(monitor.errors["empty frame"], 1);
+        assert_eq!(monitor.errors["frame without the start of its transfer"], 1);
+        assert_eq!(monitor.errors["duplicate or out of order transfer ID"], 1);
+    }
+}
@@ -0,0 +1,84 @@
+//! Values given as YAML, e.g. `{uptime: 16, health: {value: 0}}`, the way yakut
+//! takes them. This is the same form `Value` is displayed in.
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::dsdl::Value;
+
+/// Parse a value. An empty document is an empty structure, i.e. all zeros.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let documents = YamlLoader::load_from_str(text).map_err(|err| err.to_string())?;
+    match documents.as_slice() {
+        [] => Ok(Value::Struct(Vec::new())),
+        [document] => convert(document),
+        _ => Err("expected a single document".into()),
+    }
+}
+
+fn convert(yaml: &Yaml) -> Result<Value, String> {
+    Ok(match yaml {
+        Yaml::Boolean(value) => Value::Bool(*value),
+        Yaml::Integer(value) if *value >= 0 => Value::Unsigned(*value as u64),
+        Yaml::Integer(value) => Value::Signed(*value),
+        Yaml::Real(_) => Value::Float(yaml.as_f64().ok_or("invalid number")?),
+        // Strings go into byte arrays, e.g. `uavcan.primitive.String`
+        Yaml::String(value) => Value::Array(
+            value
+                .bytes()
+                .map(|byte| Value::Unsigned(byte as u64))
+                .collect(),
+        ),
+        Yaml::Array(values) => Value::Array(values.iter().map(convert).collect::<Result<_, _>>()?),
+        Yaml::Hash(fields) => Value::Struct(
+            fields
+                .iter()
+                .map(|(name, value)| match name {
+                    Yaml::String(name) => Ok((name.clone(), convert(value)?)),
+                    _ => Err(format!("field names must be strings, not {:?}", name)),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        // e.g. `health:` with nothing after it
+        Yaml::Null => Value::Struct(Vec::new()),
+        Yaml::Alias(_) | Yaml::BadValue => return Err("unsupported YAML".into()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flow_and_block_style() {
+        let flow =
+            parse("{uptime: 16, health: {value: 2}, offset: -3, gain: 0.5, on: true}").unwrap();
+        let block =
+            parse("uptime: 16\nhealth:\n  value: 2\noffset: -3\ngain: 0.5\non: true\n").unwrap();
+        assert_eq!(flow, block);
+        assert_eq!(
+            flow.to_string(),
+            "{uptime: 16, health: {value: 2}, offset: -3, gain: 0.5, on: true}"
+        );
+    }
+
+    #[test]
+    fn strings_arrays_and_defaults() {
+        assert_eq!(
+            parse("{value: hi, list: [1, 2]}").unwrap().to_string(),
+            "{value: [104, 105], list: [1, 2]}"
+        );
+        assert_eq!(parse("").unwrap(), Value::Struct(Vec::new()));
+        assert_eq!(parse("{}").unwrap(), Value::Struct(Vec::new()));
+        assert_eq!(
+            parse("health:").unwrap(),
+            Value::Struct(vec![("health".into(), Value::Struct(Vec::new()))])
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(parse("{uptime: ").is_err());
+        assert!(parse("{1: 2}").is_err());
+        assert!(parse("a: 1\n---\nb: 2\n").is_err());
+    }
+}
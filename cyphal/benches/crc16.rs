@@ -0,0 +1,18 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use cyphal::Crc16;
+
+fn crc_data_len_9(c: &mut Criterion) {
+    let payload = "123456789";
+    c.bench_function("crc data len 9", |b| {
+        b.iter(|| {
+            let mut crc = Crc16::init();
+            crc.digest(black_box(payload));
+            crc.get_crc()
+        })
+    });
+}
+
+criterion_group!(benches, crc_data_len_9);
+criterion_main!(benches);
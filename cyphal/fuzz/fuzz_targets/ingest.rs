@@ -0,0 +1,69 @@
+//! Arbitrary frames straight into a session manager, bypassing the checks
+//! `Can::rx_process_frame` does. Ports, nodes and transfer IDs are kept to a few
+//! values so frames land in the same sessions.
+
+#![no_main]
+
+use cyphal::session::{InternalRxFrame, SessionManager, StdVecSessionManager};
+use cyphal::time::StdClock;
+use cyphal::transport::can::CanMetadata;
+use cyphal::{Priority, Subscription, TransferKind};
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use libfuzzer_sys::arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+
+/// Small enough for transfers to be truncated.
+const EXTENT: usize = 20;
+
+const KINDS: [TransferKind; 3] = [
+    TransferKind::Message,
+    TransferKind::Request,
+    TransferKind::Response,
+];
+
+fn frame<'a>(u: &mut Unstructured<'a>, now: &mut u64) -> Result<InternalRxFrame<'a, StdClock>> {
+    // Time only moves forwards, up to a second between frames
+    *now += u.int_in_range(0..=1_000_000)?;
+    let transfer_kind = *u.choose(&KINDS)?;
+    let source_node_id = match u.int_in_range(0..=3)? {
+        0 => None,
+        id => Some(id),
+    };
+    // Every frame has a tail byte
+    let len = u.int_in_range(1..=8)?;
+    Ok(InternalRxFrame {
+        timestamp: Instant::new(*now),
+        priority: Priority::Nominal,
+        transfer_kind,
+        port_id: u.int_in_range(0..=1)?,
+        source_node_id,
+        destination_node_id: Some(42),
+        transfer_id: u.int_in_range(0..=31)?,
+        is_svc: transfer_kind != TransferKind::Message,
+        start_of_transfer: u.arbitrary()?,
+        end_of_transfer: u.arbitrary()?,
+        iface: u.int_in_range(0..=1)?,
+        payload: u.bytes(len)?,
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut sessions = StdVecSessionManager::<CanMetadata, StdClock>::new();
+    for kind in KINDS {
+        sessions
+            .subscribe(Subscription::new(kind, 0, EXTENT, Milliseconds(500)))
+            .unwrap();
+    }
+
+    let mut u = Unstructured::new(data);
+    let mut now = 0;
+    while !u.is_empty() {
+        let Ok(frame) = frame(&mut u, &mut now) else {
+            break;
+        };
+        if let Ok(Some(transfer)) = sessions.ingest(frame) {
+            assert!(transfer.payload.len() <= EXTENT);
+        }
+    }
+});
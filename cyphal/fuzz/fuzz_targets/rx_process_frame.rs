@@ -0,0 +1,27 @@
+//! Arbitrary CAN frames through `Can::rx_process_frame`, received by node 42.
+
+#![no_main]
+
+use cyphal::time::StdClock;
+use cyphal::transport::can::{Can, CanFrame};
+use cyphal::transport::Transport;
+use embedded_hal::can::{ExtendedId, Frame};
+use libfuzzer_sys::arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+
+fn frame(u: &mut Unstructured) -> Result<CanFrame<StdClock>> {
+    let id = ExtendedId::new(u.arbitrary::<u32>()? & 0x1FFF_FFFF).unwrap();
+    let len = u.int_in_range(0..=8)?;
+    Ok(CanFrame::new(id, u.bytes(len)?).unwrap())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    while !u.is_empty() {
+        let Ok(frame) = frame(&mut u) else { break };
+        if let Ok(Some(frame)) = Can::rx_process_frame(&Some(42), &frame) {
+            // Frames always keep their tail byte
+            assert!(!frame.payload.is_empty());
+        }
+    }
+});
@@ -0,0 +1,218 @@
+//! Adapters for running an `AsyncNode` on embassy.
+//!
+//! HAL CAN drivers don't share a common async trait, so `ChannelCan` instead talks
+//! to a pair of embassy channels, and a small task forwards frames between those
+//! and the peripheral (converting to and from `CanFrame` and timestamping on the
+//! way in). For timeouts, `embassy_time::Delay` can be used directly.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Receiver, Sender};
+
+use super::AsyncCan;
+
+/// Driver backed by a pair of embassy channels.
+pub struct ChannelCan<'ch, M: RawMutex, F, const N: usize> {
+    tx: Sender<'ch, M, F, N>,
+    rx: Receiver<'ch, M, F, N>,
+}
+
+impl<'ch, M: RawMutex, F, const N: usize> ChannelCan<'ch, M, F, N> {
+    /// Create a driver which sends frames to `tx` and receives them from `rx`.
+    pub fn new(tx: Sender<'ch, M, F, N>, rx: Receiver<'ch, M, F, N>) -> Self {
+        Self { tx, rx }
+    }
+}
+
+impl<'ch, M: RawMutex, F: Clone, const N: usize> AsyncCan for ChannelCan<'ch, M, F, N> {
+    type Frame = F;
+    type Error = core::convert::Infallible;
+
+    async fn transmit(&mut self, frame: &F) -> Result<(), Self::Error> {
+        self.tx.send(frame.clone()).await;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<F, Self::Error> {
+        Ok(self.rx.receive().await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::block_on;
+    use embassy_futures::join::join;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::channel::Channel;
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+
+    use super::ChannelCan;
+    use crate::asynch::{AsyncError, AsyncNode};
+    use crate::testing::TestSessionManager;
+    use crate::time::TestClock;
+    use crate::transfer::Transfer;
+    use crate::transport::can::{Can, CanFrame, CanMetadata};
+    use crate::uavcan::node::*;
+    use crate::{Node, Priority, TransferKind};
+
+    type Frames = Channel<NoopRawMutex, CanFrame<TestClock>, 8>;
+
+    type TestNode<'ch> = AsyncNode<
+        TestSessionManager<CanMetadata, TestClock>,
+        Can,
+        TestClock,
+        ChannelCan<'ch, NoopRawMutex, CanFrame<TestClock>, 8>,
+        TestDelay,
+    >;
+
+    /// Delay that either expires immediately or never, as tests have no timer.
+    struct TestDelay {
+        expires: bool,
+    }
+
+    impl DelayNs for TestDelay {
+        async fn delay_ns(&mut self, _ns: u32) {
+            if !self.expires {
+                core::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Node `id`, sending frames to `tx` and receiving them from `rx`.
+    fn make_node<'ch>(id: u16, tx: &'ch Frames, rx: &'ch Frames, expires: bool) -> TestNode<'ch> {
+        AsyncNode::new(
+            Node::new(Some(id), TestSessionManager::new()),
+            ChannelCan::new(tx.sender(), rx.receiver()),
+            TestDelay { expires },
+        )
+    }
+
+    const HEARTBEAT: Heartbeat = Heartbeat {
+        uptime: 10,
+        health: Health::Nominal,
+        mode: Mode::Operational,
+        vendor_specific_status_code: 0,
+    };
+
+    #[test]
+    fn publish_receive() {
+        let clock = TestClock::default();
+        let (a_to_b, b_to_a) = (Frames::new(), Frames::new());
+        let mut a = make_node(41, &a_to_b, &b_to_a, false);
+        let mut b = make_node(42, &b_to_a, &a_to_b, false);
+        b.node
+            .subscribe::<Heartbeat>(TransferKind::Message, HEARTBEAT_SUBJECT, Milliseconds(50))
+            .unwrap();
+
+        block_on(async {
+            a.publish(
+                clock.try_now().unwrap(),
+                Priority::Nominal,
+                HEARTBEAT_SUBJECT,
+                3,
+                &HEARTBEAT,
+            )
+            .await
+            .unwrap();
+
+            let mut buffer = [0; 12];
+            let transfer = b.receive(&mut buffer).await.unwrap();
+            assert_eq!(transfer.metadata.remote_node_id, Some(41));
+            assert_eq!(transfer.deserialize::<Heartbeat>().unwrap(), HEARTBEAT);
+        });
+    }
+
+    /// A multi-frame response gets through the channels, and a heartbeat published
+    /// before it is received afterwards.
+    #[test]
+    fn request_response() {
+        let clock = TestClock::default();
+        let (a_to_b, b_to_a) = (Frames::new(), Frames::new());
+        let mut client = make_node(41, &a_to_b, &b_to_a, false);
+        let mut server = make_node(42, &b_to_a, &a_to_b, false);
+        client
+            .node
+            .subscribe::<GetInfoResponse>(
+                TransferKind::Response,
+                GET_INFO_SERVICE,
+                Milliseconds(50),
+            )
+            .unwrap();
+        client
+            .node
+            .subscribe::<Heartbeat>(TransferKind::Message, HEARTBEAT_SUBJECT, Milliseconds(50))
+            .unwrap();
+        server
+            .node
+            .subscribe::<GetInfoRequest>(TransferKind::Request, GET_INFO_SERVICE, Milliseconds(50))
+            .unwrap();
+
+        let info = GetInfoResponse {
+            name: heapless::Vec::from_slice(b"org.example.server").unwrap(),
+            ..Default::default()
+        };
+        let request = client.request::<_, GetInfoResponse>(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            GET_INFO_SERVICE,
+            42,
+            7,
+            &GetInfoRequest,
+            Milliseconds(50),
+        );
+        let serve = async {
+            let mut buffer = [0; 0];
+            let transfer = server.receive(&mut buffer).await.unwrap();
+            let now = clock.try_now().unwrap();
+            server
+                .publish(now, Priority::Nominal, HEARTBEAT_SUBJECT, 0, &HEARTBEAT)
+                .await
+                .unwrap();
+            server
+                .respond(
+                    now,
+                    transfer.metadata.priority,
+                    GET_INFO_SERVICE,
+                    41,
+                    transfer.metadata.transfer_id,
+                    &info,
+                )
+                .await
+                .unwrap();
+        };
+
+        let (response, ()) = block_on(join(request, serve));
+        assert_eq!(response.unwrap(), info);
+
+        let mut buffer = [0; 12];
+        let transfer = block_on(client.receive(&mut buffer)).unwrap();
+        assert_eq!(transfer.deserialize::<Heartbeat>().unwrap(), HEARTBEAT);
+    }
+
+    #[test]
+    fn request_timeout() {
+        let clock = TestClock::default();
+        let (a_to_b, b_to_a) = (Frames::new(), Frames::new());
+        let mut client = make_node(41, &a_to_b, &b_to_a, true);
+        client
+            .node
+            .subscribe::<GetInfoResponse>(
+                TransferKind::Response,
+                GET_INFO_SERVICE,
+                Milliseconds(50),
+            )
+            .unwrap();
+
+        let response = block_on(client.request::<_, GetInfoResponse>(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            GET_INFO_SERVICE,
+            42,
+            0,
+            &GetInfoRequest,
+            Milliseconds(50),
+        ));
+        assert!(matches!(response, Err(AsyncError::Timeout)));
+    }
+}
@@ -0,0 +1,619 @@
+//! Async wrapper around `Node`.
+//!
+//! `Node` itself never touches hardware: frames go in through `try_receive_frame`
+//! and come out of `transmit`. `AsyncNode` pairs it with an async driver, so an
+//! application task can simply `.await` the next transfer, or the response to a
+//! request, instead of writing the polling loop itself.
+//!
+//! The driver is anything implementing `AsyncCan`. Adapters are provided for tokio
+//! (feature `tokio`) and for embassy channels (feature `embassy`), and request
+//! timeouts use an `embedded_hal_async::delay::DelayNs` implementation, which
+//! `embassy_time::Delay` already is.
+
+#[cfg(feature = "embassy")]
+pub mod embassy;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+use alloc::vec::Vec;
+
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_time::fixed_point::FixedPoint;
+
+use crate::serialization::{self, Deserialize, DeserializeError, Serialize};
+use crate::session::SessionManager;
+use crate::time::{Duration, Timestamp};
+use crate::transfer::{OwnedTransfer, RefTransfer, Transfer, TransferMetadata};
+use crate::transport::Transport;
+use crate::types::*;
+use crate::{Node, Priority, RxError, StreamingIterator, TransferKind, TxError};
+
+/// Async CAN driver.
+///
+/// Implementations are expected to timestamp received frames, as the transport
+/// frame type carries the timestamp.
+#[allow(async_fn_in_trait)]
+pub trait AsyncCan {
+    type Frame;
+    type Error;
+
+    /// Send a frame out on the bus.
+    async fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error>;
+
+    /// Wait for the next frame from the bus.
+    async fn receive(&mut self) -> Result<Self::Frame, Self::Error>;
+}
+
+/// Errors possible from the async API.
+#[derive(Copy, Clone, Debug)]
+pub enum AsyncError<E> {
+    /// The driver failed to send or receive a frame.
+    Driver(E),
+    /// An incoming frame couldn't be received.
+    Rx(RxError),
+    /// The outgoing transfer couldn't be turned into frames.
+    Tx(TxError),
+    /// The response payload couldn't be deserialized.
+    Deserialize(DeserializeError),
+    /// The received payload didn't fit in the provided buffer.
+    BufferTooSmall,
+    /// No response arrived in time.
+    Timeout,
+}
+
+/// `Node` driven by an async driver.
+///
+/// Up to `P` transfers that complete while a request waits for its response are kept
+/// for `receive`. Any more are dropped, and counted in `pending_dropped`.
+pub struct AsyncNode<S, T, C, D, Y, const P: usize = 4>
+where
+    S: SessionManager<C>,
+    T: Transport<C>,
+    C: embedded_time::Clock,
+{
+    /// Underlying node. Made public so subscriptions can be managed directly.
+    pub node: Node<S, T, C>,
+    driver: D,
+    delay: Y,
+    // Transfers received while waiting for a response, for `receive` to return
+    pending: heapless::Deque<OwnedTransfer<C, Vec<u8>>, P>,
+    pending_dropped: usize,
+}
+
+impl<S, T, C, D, Y, const P: usize> AsyncNode<S, T, C, D, Y, P>
+where
+    S: SessionManager<C>,
+    T: Transport<C>,
+    C: embedded_time::Clock + Clone,
+    D: AsyncCan<Frame = T::Frame>,
+    Y: DelayNs,
+{
+    pub fn new(node: Node<S, T, C>, driver: D, delay: Y) -> Self {
+        Self {
+            node,
+            driver,
+            delay,
+            pending: heapless::Deque::new(),
+            pending_dropped: 0,
+        }
+    }
+
+    /// Transfers dropped because `P` of them were already waiting for `receive`.
+    pub fn pending_dropped(&self) -> usize {
+        self.pending_dropped
+    }
+
+    /// Wait for the next complete transfer, copying its payload into `buffer`.
+    ///
+    /// The returned transfer borrows from `buffer` rather than the session manager,
+    /// so the node can keep receiving while it is still in use. Transfers that
+    /// completed while a request waited for its response are returned first.
+    pub async fn receive<'b>(
+        &mut self,
+        buffer: &'b mut [u8],
+    ) -> Result<RefTransfer<'b, C>, AsyncError<D::Error>> {
+        let (metadata, len) = match self.pending.pop_front() {
+            Some(transfer) => (transfer.metadata, copy_payload(buffer, &transfer.payload)?),
+            None => loop {
+                let frame = self.driver.receive().await.map_err(AsyncError::Driver)?;
+                if let Some(transfer) =
+                    self.node.try_receive_frame(frame).map_err(AsyncError::Rx)?
+                {
+                    let len = copy_payload(buffer, transfer.payload)?;
+                    break (transfer.metadata, len);
+                }
+            },
+        };
+
+        Ok(RefTransfer {
+            metadata,
+            payload: &buffer[..len],
+        })
+    }
+
+    /// Serialize and publish a message.
+    pub async fn publish<M: Serialize>(
+        &mut self,
+        timestamp: Timestamp<C>,
+        priority: Priority,
+        subject: PortId,
+        transfer_id: TransferId,
+        message: &M,
+    ) -> Result<(), AsyncError<D::Error>> {
+        self.node.add_publisher(subject);
+        let metadata = TransferMetadata {
+            timestamp,
+            priority,
+            transfer_kind: TransferKind::Message,
+            port_id: subject,
+            remote_node_id: None,
+            transfer_id,
+        };
+        self.send(metadata, message).await
+    }
+
+    /// Send a request and wait up to `timeout` for the matching response.
+    ///
+    /// The node must already be subscribed to responses from `service`. Up to `P` other
+    /// transfers completed while waiting are kept, and returned by the following calls
+    /// to `receive`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn request<Req: Serialize, Resp: Deserialize>(
+        &mut self,
+        timestamp: Timestamp<C>,
+        priority: Priority,
+        service: PortId,
+        destination: NodeId,
+        transfer_id: TransferId,
+        request: &Req,
+        timeout: Duration,
+    ) -> Result<Resp, AsyncError<D::Error>> {
+        let metadata = TransferMetadata {
+            timestamp,
+            priority,
+            transfer_kind: TransferKind::Request,
+            port_id: service,
+            remote_node_id: Some(destination),
+            transfer_id,
+        };
+        self.send(metadata, request).await?;
+
+        let response = wait_for_response(
+            &mut self.node,
+            &mut self.driver,
+            &mut self.pending,
+            &mut self.pending_dropped,
+            service,
+            destination,
+            transfer_id,
+        );
+        match select(response, self.delay.delay_ms(timeout.integer())).await {
+            Either::First(response) => response,
+            Either::Second(()) => Err(AsyncError::Timeout),
+        }
+    }
+
+    /// Serialize and send the response to a request received from `destination`.
+    ///
+    /// `transfer_id` must be the transfer ID of the request.
+    pub async fn respond<M: Serialize>(
+        &mut self,
+        timestamp: Timestamp<C>,
+        priority: Priority,
+        service: PortId,
+        destination: NodeId,
+        transfer_id: TransferId,
+        response: &M,
+    ) -> Result<(), AsyncError<D::Error>> {
+        let metadata = TransferMetadata {
+            timestamp,
+            priority,
+            transfer_kind: TransferKind::Response,
+            port_id: service,
+            remote_node_id: Some(destination),
+            transfer_id,
+        };
+        self.send(metadata, response).await
+    }
+
+    async fn send<M: Serialize>(
+        &mut self,
+        metadata: TransferMetadata<C>,
+        value: &M,
+    ) -> Result<(), AsyncError<D::Error>> {
+        let mut buffer = serialization::buffer_for::<M>();
+        let len = value.serialize(buffer.as_mut());
+
+        let transfer = RefTransfer {
+            metadata,
+            payload: &buffer.as_ref()[..len],
+        };
+
+        let mut frames = self.node.transmit(&transfer).map_err(AsyncError::Tx)?;
+        let mut count = 0;
+        while let Some(frame) = frames.next() {
+            self.driver
+                .transmit(frame)
+                .await
+                .map_err(AsyncError::Driver)?;
+            count += 1;
+        }
+
+        self.node.count_transmitted(count, len);
+        Ok(())
+    }
+}
+
+/// Copy a received payload into the caller's buffer, returning its length.
+fn copy_payload<E>(buffer: &mut [u8], payload: &[u8]) -> Result<usize, AsyncError<E>> {
+    buffer
+        .get_mut(..payload.len())
+        .ok_or(AsyncError::BufferTooSmall)?
+        .copy_from_slice(payload);
+    Ok(payload.len())
+}
+
+/// Receive until the response to a request arrives, queueing any other transfer in
+/// `pending`, or counting it in `dropped` if that's full. Reception errors are skipped,
+/// as they are most likely caused by unrelated traffic.
+async fn wait_for_response<S, T, C, D, Resp, const P: usize>(
+    node: &mut Node<S, T, C>,
+    driver: &mut D,
+    pending: &mut heapless::Deque<OwnedTransfer<C, Vec<u8>>, P>,
+    dropped: &mut usize,
+    service: PortId,
+    destination: NodeId,
+    transfer_id: TransferId,
+) -> Result<Resp, AsyncError<D::Error>>
+where
+    S: SessionManager<C>,
+    T: Transport<C>,
+    C: embedded_time::Clock + Clone,
+    D: AsyncCan<Frame = T::Frame>,
+    Resp: Deserialize,
+{
+    loop {
+        let frame = driver.receive().await.map_err(AsyncError::Driver)?;
+        if let Ok(Some(transfer)) = node.try_receive_frame(frame) {
+            let metadata = &transfer.metadata;
+            if metadata.transfer_kind == TransferKind::Response
+                && metadata.port_id == service
+                && metadata.remote_node_id == Some(destination)
+                && metadata.transfer_id == transfer_id
+            {
+                return transfer.deserialize().map_err(AsyncError::Deserialize);
+            }
+            if pending.is_full() {
+                *dropped = dropped.wrapping_add(1);
+            } else {
+                let _ = pending.push_back(transfer.into());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+
+    use super::tokio::{ChannelCan, TokioDelay};
+    use super::{AsyncError, AsyncNode};
+    use crate::serialization::{Deserialize, DeserializeError, Serialize};
+    use crate::testing::TestSessionManager;
+    use crate::time::{Duration, TestClock};
+    use crate::transfer::Transfer;
+    use crate::transport::can::{Can, CanFrame, CanMetadata};
+    use crate::{Node, Priority, TransferKind};
+
+    type TestNode = AsyncNode<
+        TestSessionManager<CanMetadata, TestClock>,
+        Can,
+        TestClock,
+        ChannelCan<CanFrame<TestClock>>,
+        TokioDelay,
+    >;
+
+    /// Multi-frame sized value, to exercise reassembly.
+    #[derive(Debug, PartialEq)]
+    struct Counter([u8; 12]);
+
+    impl Serialize for Counter {
+        const MAX_SIZE: usize = 12;
+        type Buffer = [u8; 12];
+
+        fn serialize(&self, buffer: &mut [u8]) -> usize {
+            buffer[..12].copy_from_slice(&self.0);
+            12
+        }
+    }
+
+    impl Deserialize for Counter {
+        const EXTENT: usize = 12;
+
+        fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+            let mut value = [0; 12];
+            let len = buffer.len().min(12);
+            value[..len].copy_from_slice(&buffer[..len]);
+            Ok(Self(value))
+        }
+    }
+
+    fn make_nodes() -> (TestNode, TestNode) {
+        let (a, b) = ChannelCan::pair(32);
+        let a = AsyncNode::new(
+            Node::new(Some(41), TestSessionManager::new()),
+            a,
+            TokioDelay,
+        );
+        let b = AsyncNode::new(
+            Node::new(Some(42), TestSessionManager::new()),
+            b,
+            TokioDelay,
+        );
+        (a, b)
+    }
+
+    fn timeout() -> Duration {
+        Milliseconds(50)
+    }
+
+    #[tokio::test]
+    async fn publish_receive() {
+        let clock = TestClock::default();
+        let (mut a, mut b) = make_nodes();
+        b.node
+            .subscribe::<Counter>(TransferKind::Message, 100, timeout())
+            .unwrap();
+
+        let message = Counter([7; 12]);
+        a.publish(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            100,
+            3,
+            &message,
+        )
+        .await
+        .unwrap();
+
+        let mut buffer = [0; 64];
+        let transfer = b.receive(&mut buffer).await.unwrap();
+        assert_eq!(transfer.metadata.remote_node_id, Some(41));
+        assert_eq!(transfer.metadata.transfer_id, 3);
+        assert_eq!(transfer.deserialize::<Counter>().unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn receive_buffer_too_small() {
+        let clock = TestClock::default();
+        let (mut a, mut b) = make_nodes();
+        b.node
+            .subscribe::<Counter>(TransferKind::Message, 100, timeout())
+            .unwrap();
+
+        a.publish(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            100,
+            0,
+            &Counter([1; 12]),
+        )
+        .await
+        .unwrap();
+
+        let mut buffer = [0; 4];
+        assert!(matches!(
+            b.receive(&mut buffer).await,
+            Err(AsyncError::BufferTooSmall)
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_response() {
+        let clock = TestClock::default();
+        let (mut client, mut server) = make_nodes();
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Response, 20, timeout())
+            .unwrap();
+        server
+            .node
+            .subscribe::<Counter>(TransferKind::Request, 20, timeout())
+            .unwrap();
+
+        let request = client.request::<_, Counter>(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            20,
+            42,
+            5,
+            &Counter([1; 12]),
+            timeout(),
+        );
+        let serve = async {
+            let mut buffer = [0; 12];
+            let transfer = server.receive(&mut buffer).await.unwrap();
+            let mut response = transfer.deserialize::<Counter>().unwrap();
+            response.0.reverse();
+            response.0[0] = 2;
+            server
+                .respond(
+                    clock.try_now().unwrap(),
+                    transfer.metadata.priority,
+                    transfer.metadata.port_id,
+                    transfer.metadata.remote_node_id.unwrap(),
+                    transfer.metadata.transfer_id,
+                    &response,
+                )
+                .await
+                .unwrap();
+        };
+
+        let (response, ()) = ::tokio::join!(request, serve);
+        let mut expected = [1; 12];
+        expected[0] = 2;
+        assert_eq!(response.unwrap(), Counter(expected));
+    }
+
+    /// Transfers arriving while a request waits for its response are received after.
+    #[tokio::test]
+    async fn request_keeps_other_transfers() {
+        let clock = TestClock::default();
+        let (mut client, mut server) = make_nodes();
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Response, 20, timeout())
+            .unwrap();
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Message, 100, timeout())
+            .unwrap();
+        server
+            .node
+            .subscribe::<Counter>(TransferKind::Request, 20, timeout())
+            .unwrap();
+
+        let request = client.request::<_, Counter>(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            20,
+            42,
+            5,
+            &Counter([1; 12]),
+            timeout(),
+        );
+        let serve = async {
+            let mut buffer = [0; 12];
+            let transfer = server.receive(&mut buffer).await.unwrap();
+            server
+                .publish(
+                    clock.try_now().unwrap(),
+                    Priority::Nominal,
+                    100,
+                    0,
+                    &Counter([3; 12]),
+                )
+                .await
+                .unwrap();
+            server
+                .respond(
+                    clock.try_now().unwrap(),
+                    transfer.metadata.priority,
+                    transfer.metadata.port_id,
+                    transfer.metadata.remote_node_id.unwrap(),
+                    transfer.metadata.transfer_id,
+                    &Counter([2; 12]),
+                )
+                .await
+                .unwrap();
+        };
+
+        let (response, ()) = ::tokio::join!(request, serve);
+        assert_eq!(response.unwrap(), Counter([2; 12]));
+
+        let mut buffer = [0; 12];
+        let transfer = client.receive(&mut buffer).await.unwrap();
+        assert_eq!(transfer.metadata.transfer_kind, TransferKind::Message);
+        assert_eq!(transfer.deserialize::<Counter>().unwrap(), Counter([3; 12]));
+    }
+
+    /// Transfers that don't fit in the pending queue are dropped and counted.
+    #[tokio::test]
+    async fn request_drops_transfers_past_capacity() {
+        let clock = TestClock::default();
+        let (a, b) = ChannelCan::pair(32);
+        let mut client: AsyncNode<_, Can, _, _, _, 1> = AsyncNode::new(
+            Node::new(Some(41), TestSessionManager::<CanMetadata, _>::new()),
+            a,
+            TokioDelay,
+        );
+        let mut server: TestNode = AsyncNode::new(
+            Node::new(Some(42), TestSessionManager::new()),
+            b,
+            TokioDelay,
+        );
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Response, 20, timeout())
+            .unwrap();
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Message, 100, timeout())
+            .unwrap();
+        server
+            .node
+            .subscribe::<Counter>(TransferKind::Request, 20, timeout())
+            .unwrap();
+
+        let request = client.request::<_, Counter>(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            20,
+            42,
+            5,
+            &Counter([1; 12]),
+            timeout(),
+        );
+        let serve = async {
+            let mut buffer = [0; 12];
+            let transfer = server.receive(&mut buffer).await.unwrap();
+            for transfer_id in 0..3 {
+                server
+                    .publish(
+                        clock.try_now().unwrap(),
+                        Priority::Nominal,
+                        100,
+                        transfer_id,
+                        &Counter([transfer_id; 12]),
+                    )
+                    .await
+                    .unwrap();
+            }
+            server
+                .respond(
+                    clock.try_now().unwrap(),
+                    transfer.metadata.priority,
+                    transfer.metadata.port_id,
+                    transfer.metadata.remote_node_id.unwrap(),
+                    transfer.metadata.transfer_id,
+                    &Counter([2; 12]),
+                )
+                .await
+                .unwrap();
+        };
+
+        let (response, ()) = ::tokio::join!(request, serve);
+        assert_eq!(response.unwrap(), Counter([2; 12]));
+        assert_eq!(client.pending_dropped(), 2);
+
+        // The first one was kept
+        let mut buffer = [0; 12];
+        let transfer = client.receive(&mut buffer).await.unwrap();
+        assert_eq!(transfer.metadata.transfer_id, 0);
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        let clock = TestClock::default();
+        let (mut client, _server) = make_nodes();
+        client
+            .node
+            .subscribe::<Counter>(TransferKind::Response, 20, timeout())
+            .unwrap();
+
+        let response = client
+            .request::<_, Counter>(
+                clock.try_now().unwrap(),
+                Priority::Nominal,
+                20,
+                42,
+                0,
+                &Counter([0; 12]),
+                timeout(),
+            )
+            .await;
+        assert!(matches!(response, Err(AsyncError::Timeout)));
+    }
+}
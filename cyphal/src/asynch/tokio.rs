@@ -0,0 +1,60 @@
+//! Adapters for running an `AsyncNode` on tokio.
+//!
+//! `ChannelCan` moves frames over tokio channels, so the actual interface can be
+//! serviced by whatever task owns it (a SocketCAN socket, a serial adapter, ...).
+//! `ChannelCan::pair` connects two nodes directly, which is handy for tests.
+
+use ::tokio::sync::mpsc;
+
+use super::AsyncCan;
+
+/// The other end of the channel was dropped.
+#[derive(Copy, Clone, Debug)]
+pub struct ChannelClosed;
+
+/// Driver backed by a pair of tokio channels.
+pub struct ChannelCan<F> {
+    tx: mpsc::Sender<F>,
+    rx: mpsc::Receiver<F>,
+}
+
+impl<F> ChannelCan<F> {
+    /// Create a driver which sends frames to `tx` and receives them from `rx`.
+    pub fn new(tx: mpsc::Sender<F>, rx: mpsc::Receiver<F>) -> Self {
+        Self { tx, rx }
+    }
+
+    /// Create two drivers connected to each other, each buffering up to `capacity` frames.
+    pub fn pair(capacity: usize) -> (Self, Self) {
+        let (a_tx, b_rx) = mpsc::channel(capacity);
+        let (b_tx, a_rx) = mpsc::channel(capacity);
+        (Self::new(a_tx, a_rx), Self::new(b_tx, b_rx))
+    }
+}
+
+impl<F: Clone> AsyncCan for ChannelCan<F> {
+    type Frame = F;
+    type Error = ChannelClosed;
+
+    async fn transmit(&mut self, frame: &F) -> Result<(), ChannelClosed> {
+        self.tx.send(frame.clone()).await.map_err(|_| ChannelClosed)
+    }
+
+    async fn receive(&mut self) -> Result<F, ChannelClosed> {
+        self.rx.recv().await.ok_or(ChannelClosed)
+    }
+}
+
+/// `DelayNs` implementation using tokio's timer.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TokioDelay;
+
+impl embedded_hal_async::delay::DelayNs for TokioDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        ::tokio::time::sleep(core::time::Duration::from_nanos(ns.into())).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        ::tokio::time::sleep(core::time::Duration::from_millis(ms.into())).await
+    }
+}
@@ -1,3 +1,5 @@
+//! CRC-16/CCITT-FALSE, which protects multi-frame transfers on CAN.
+
 /// Taken from crate [crc_any](https://docs.rs/crc-any/2.4.0/crc_any/)
 static NO_REF_16_1021: [u16; 256] = [
     0u16, 4129u16, 8258u16, 12387u16, 16516u16, 20645u16, 24774u16, 28903u16, 33032u16, 37161u16,
@@ -33,7 +35,7 @@ static NO_REF_16_1021: [u16; 256] = [
 
 /// calculate a crc16
 ///
-/// ```not_run
+/// ```text
 /// |Check |Poly  |Init  |Ref  |XorOut|
 /// |---   |---   |---   |---  |---   |
 /// |0x29B1|0x1021|0xFFFF|false|0x0000|
@@ -73,8 +75,6 @@ impl Crc16 {
 
 #[cfg(test)]
 mod test {
-    use ::test::Bencher;
-
     use super::*;
     #[test]
     fn calculate_crc() {
@@ -107,15 +107,4 @@ mod test {
 
         assert_eq!(ref_crc, crc);
     }
-
-    #[bench]
-    fn bench_crc_data_len_9(b: &mut Bencher) {
-        let payload = "123456789";
-        b.iter(|| {
-            let mut crc_impl = Crc16::init();
-            crc_impl.digest(payload);
-            let crc = crc_impl.get_crc();
-            crc
-        });
-    }
 }
@@ -0,0 +1,318 @@
+//! Callback-based dispatch of received transfers.
+//!
+//! Instead of matching on the kind and port of every transfer that comes out of
+//! `Node::try_receive_frame`, handlers can be registered per port and the node will
+//! route completed transfers to them with `Node::dispatch_frame`. Request handlers
+//! can write a response, which the node turns into frames and queues for transmission.
+//!
+//! Two registries are provided: `HandlerTable`, a fixed-size table of function pointers
+//! for `no_std`, and `BoxedHandlers`, which accepts any closure on `std`. For fully static
+//! dispatch, implement `Handlers` yourself (e.g. with a `match` on the port ID).
+
+use crate::serialization::Buffer;
+use crate::session::SubscriptionError;
+use crate::transfer::RefTransfer;
+use crate::types::*;
+use crate::TransferKind;
+
+/// Routes completed transfers to the application.
+pub trait Handlers<C: embedded_time::Clock> {
+    /// Scratch buffer responses are written into. Should be `[u8; <largest response>]`.
+    type ResponseBuffer: Buffer;
+
+    /// Handle a completed transfer.
+    ///
+    /// For requests, the response payload can be written into `response`, and its
+    /// length returned to have it sent back to the requester. A length past the end of
+    /// `response` is rejected with `TxError::PayloadOutOfBounds`. The return value is
+    /// ignored for messages and responses.
+    fn dispatch(&mut self, transfer: &RefTransfer<C>, response: &mut [u8]) -> Option<usize>;
+}
+
+/// Handler for messages and service responses.
+pub type TransferFn<C> = fn(&RefTransfer<C>);
+
+/// Handler for service requests. Returns the length of the response, if any.
+pub type RequestFn<C> = fn(&RefTransfer<C>, &mut [u8]) -> Option<usize>;
+
+enum HandlerFn<C: embedded_time::Clock> {
+    Transfer(TransferFn<C>),
+    Request(RequestFn<C>),
+}
+
+// Manual impls, deriving would require C: Copy
+impl<C: embedded_time::Clock> Clone for HandlerFn<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: embedded_time::Clock> Copy for HandlerFn<C> {}
+
+struct Route<C: embedded_time::Clock> {
+    transfer_kind: TransferKind,
+    port_id: PortId,
+    handler: HandlerFn<C>,
+}
+
+impl<C: embedded_time::Clock> Clone for Route<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: embedded_time::Clock> Copy for Route<C> {}
+
+/// Fixed-size table of function pointer handlers, for `no_std` use.
+///
+/// `N` is the maximum number of handlers, and `R` the size of the largest response
+/// any request handler will write.
+pub struct HandlerTable<C: embedded_time::Clock, const N: usize, const R: usize> {
+    routes: [Option<Route<C>>; N],
+}
+
+impl<C: embedded_time::Clock, const N: usize, const R: usize> HandlerTable<C, N, R> {
+    pub fn new() -> Self {
+        Self { routes: [None; N] }
+    }
+
+    /// Register a handler for messages on a subject.
+    pub fn on_message(
+        &mut self,
+        subject: PortId,
+        handler: TransferFn<C>,
+    ) -> Result<(), SubscriptionError> {
+        self.insert(TransferKind::Message, subject, HandlerFn::Transfer(handler))
+    }
+
+    /// Register a handler for requests to a service.
+    pub fn on_request(
+        &mut self,
+        service: PortId,
+        handler: RequestFn<C>,
+    ) -> Result<(), SubscriptionError> {
+        self.insert(TransferKind::Request, service, HandlerFn::Request(handler))
+    }
+
+    /// Register a handler for responses from a service.
+    pub fn on_response(
+        &mut self,
+        service: PortId,
+        handler: TransferFn<C>,
+    ) -> Result<(), SubscriptionError> {
+        self.insert(
+            TransferKind::Response,
+            service,
+            HandlerFn::Transfer(handler),
+        )
+    }
+
+    /// Remove the handler for a port.
+    pub fn remove(
+        &mut self,
+        transfer_kind: TransferKind,
+        port_id: PortId,
+    ) -> Result<(), SubscriptionError> {
+        let slot = self
+            .routes
+            .iter_mut()
+            .find(|r| matches!(r, Some(r) if r.transfer_kind == transfer_kind && r.port_id == port_id))
+            .ok_or(SubscriptionError::SubscriptionDoesNotExist)?;
+        *slot = None;
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        transfer_kind: TransferKind,
+        port_id: PortId,
+        handler: HandlerFn<C>,
+    ) -> Result<(), SubscriptionError> {
+        if self.find(transfer_kind, port_id).is_some() {
+            return Err(SubscriptionError::SubscriptionExists);
+        }
+
+        let slot = self
+            .routes
+            .iter_mut()
+            .find(|r| r.is_none())
+            .ok_or(SubscriptionError::OutOfSpace)?;
+        *slot = Some(Route {
+            transfer_kind,
+            port_id,
+            handler,
+        });
+        Ok(())
+    }
+
+    fn find(&self, transfer_kind: TransferKind, port_id: PortId) -> Option<HandlerFn<C>> {
+        self.routes
+            .iter()
+            .flatten()
+            .find(|r| r.transfer_kind == transfer_kind && r.port_id == port_id)
+            .map(|r| r.handler)
+    }
+}
+
+impl<C: embedded_time::Clock, const N: usize, const R: usize> Default for HandlerTable<C, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: embedded_time::Clock, const N: usize, const R: usize> Handlers<C>
+    for HandlerTable<C, N, R>
+{
+    type ResponseBuffer = [u8; R];
+
+    fn dispatch(&mut self, transfer: &RefTransfer<C>, response: &mut [u8]) -> Option<usize> {
+        match self.find(transfer.metadata.transfer_kind, transfer.metadata.port_id)? {
+            HandlerFn::Transfer(handler) => {
+                handler(transfer);
+                None
+            }
+            HandlerFn::Request(handler) => handler(transfer, response),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use boxed::BoxedHandlers;
+
+#[cfg(feature = "std")]
+mod boxed {
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use super::*;
+
+    type BoxedTransferFn<C> = Box<dyn FnMut(&RefTransfer<C>)>;
+    type BoxedRequestFn<C> = Box<dyn FnMut(&RefTransfer<C>, &mut [u8]) -> Option<usize>>;
+
+    enum BoxedHandler<C: embedded_time::Clock> {
+        Transfer(BoxedTransferFn<C>),
+        Request(BoxedRequestFn<C>),
+    }
+
+    /// Growable set of closure handlers, for `std` use.
+    ///
+    /// `R` is the size of the largest response any request handler will write.
+    pub struct BoxedHandlers<C: embedded_time::Clock, const R: usize> {
+        routes: Vec<(TransferKind, PortId, BoxedHandler<C>)>,
+    }
+
+    impl<C: embedded_time::Clock, const R: usize> BoxedHandlers<C, R> {
+        pub fn new() -> Self {
+            Self { routes: Vec::new() }
+        }
+
+        /// Register a handler for messages on a subject.
+        pub fn on_message<F>(
+            &mut self,
+            subject: PortId,
+            handler: F,
+        ) -> Result<(), SubscriptionError>
+        where
+            F: FnMut(&RefTransfer<C>) + 'static,
+        {
+            self.insert(
+                TransferKind::Message,
+                subject,
+                BoxedHandler::Transfer(Box::new(handler)),
+            )
+        }
+
+        /// Register a handler for requests to a service.
+        pub fn on_request<F>(
+            &mut self,
+            service: PortId,
+            handler: F,
+        ) -> Result<(), SubscriptionError>
+        where
+            F: FnMut(&RefTransfer<C>, &mut [u8]) -> Option<usize> + 'static,
+        {
+            self.insert(
+                TransferKind::Request,
+                service,
+                BoxedHandler::Request(Box::new(handler)),
+            )
+        }
+
+        /// Register a handler for responses from a service.
+        pub fn on_response<F>(
+            &mut self,
+            service: PortId,
+            handler: F,
+        ) -> Result<(), SubscriptionError>
+        where
+            F: FnMut(&RefTransfer<C>) + 'static,
+        {
+            self.insert(
+                TransferKind::Response,
+                service,
+                BoxedHandler::Transfer(Box::new(handler)),
+            )
+        }
+
+        /// Remove the handler for a port.
+        pub fn remove(
+            &mut self,
+            transfer_kind: TransferKind,
+            port_id: PortId,
+        ) -> Result<(), SubscriptionError> {
+            match self
+                .routes
+                .iter()
+                .position(|(kind, port, _)| *kind == transfer_kind && *port == port_id)
+            {
+                Some(pos) => {
+                    self.routes.remove(pos);
+                    Ok(())
+                }
+                None => Err(SubscriptionError::SubscriptionDoesNotExist),
+            }
+        }
+
+        fn insert(
+            &mut self,
+            transfer_kind: TransferKind,
+            port_id: PortId,
+            handler: BoxedHandler<C>,
+        ) -> Result<(), SubscriptionError> {
+            if self
+                .routes
+                .iter()
+                .any(|(kind, port, _)| *kind == transfer_kind && *port == port_id)
+            {
+                return Err(SubscriptionError::SubscriptionExists);
+            }
+
+            self.routes.push((transfer_kind, port_id, handler));
+            Ok(())
+        }
+    }
+
+    impl<C: embedded_time::Clock, const R: usize> Default for BoxedHandlers<C, R> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<C: embedded_time::Clock, const R: usize> Handlers<C> for BoxedHandlers<C, R> {
+        type ResponseBuffer = [u8; R];
+
+        fn dispatch(&mut self, transfer: &RefTransfer<C>, response: &mut [u8]) -> Option<usize> {
+            let (_, _, handler) = self.routes.iter_mut().find(|(kind, port, _)| {
+                *kind == transfer.metadata.transfer_kind && *port == transfer.metadata.port_id
+            })?;
+
+            match handler {
+                BoxedHandler::Transfer(handler) => {
+                    handler(transfer);
+                    None
+                }
+                BoxedHandler::Request(handler) => handler(transfer, response),
+            }
+        }
+    }
+}
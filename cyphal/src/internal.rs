@@ -21,6 +21,9 @@ pub struct InternalRxFrame<'a, C: embedded_time::Clock> {
     pub is_svc: bool,
     pub start_of_transfer: bool,
     pub end_of_transfer: bool,
+    /// Index of the interface the frame arrived on, for redundant transports.
+    /// Always 0 otherwise.
+    pub iface: usize,
     pub payload: &'a [u8],
 }
 
@@ -48,6 +51,7 @@ impl<'a, C: embedded_time::Clock> InternalRxFrame<'a, C> {
             is_svc: false,
             start_of_transfer: start,
             end_of_transfer: end,
+            iface: 0,
             payload,
         }
     }
@@ -77,6 +81,7 @@ impl<'a, C: embedded_time::Clock> InternalRxFrame<'a, C> {
             is_svc: true,
             start_of_transfer: start,
             end_of_transfer: end,
+            iface: 0,
             payload,
         }
     }
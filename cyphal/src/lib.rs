@@ -23,28 +23,36 @@
 //! environments, but I'll get to those when I get to them.
 #![no_std]
 #![deny(warnings)]
-#![feature(generic_associated_types)]
-#![feature(test)]
 
 #[allow(unused_imports)]
 #[cfg(feature = "std")]
 #[macro_use]
 extern crate std;
 
-#[cfg(test)]
-extern crate test;
-
 #[macro_use]
 extern crate num_derive;
 
 extern crate alloc;
 
+use core::fmt;
+
 pub mod time;
 
-mod crc16;
+#[cfg(feature = "async")]
+pub mod asynch;
+
+pub mod handler;
+pub mod media;
+pub mod serialization;
+#[cfg(feature = "statistics")]
+pub mod statistics;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tracker;
 pub mod transfer;
 pub mod transport;
 pub mod types;
+pub mod uavcan;
 
 pub use node::Node;
 use time::Duration;
@@ -52,6 +60,11 @@ pub use transfer::TransferKind;
 
 pub use streaming_iterator::StreamingIterator;
 
+// Only public for the benchmarks
+#[doc(hidden)]
+pub use crc16::Crc16;
+
+mod crc16;
 mod internal;
 mod node;
 pub mod session;
@@ -59,8 +72,11 @@ pub mod session;
 use types::*;
 
 /// Protocol errors possible from receiving incoming frames.
-#[derive(Copy, Clone, Debug)]
-pub enum RxError {
+///
+/// There's no error for a transfer longer than its subscription's extent: the spec has
+/// it truncated to the extent rather than dropped, so it's received as usual.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RxErrorKind {
     TransferStartMissingToggle,
     /// Anonymous transfers must only use a single frame
     AnonNotSingleFrame,
@@ -70,14 +86,100 @@ pub enum RxError {
     FrameEmpty,
     /// Id field is formatted incorrectly
     InvalidCanId,
-    /// Non-start frame received without session
-    NewSessionNoStart,
-    /// Session has expired
-    Timeout,
-    /// Frame is part of new
-    InvalidTransferId,
-    /// Internal SessionManager error
-    SessionError(session::SessionError),
+    /// Error from the session manager, e.g. a session timing out
+    Session(session::SessionError),
+}
+
+impl fmt::Display for RxErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RxErrorKind::TransferStartMissingToggle => {
+                f.write_str("start of transfer without the toggle bit set")
+            }
+            RxErrorKind::AnonNotSingleFrame => f.write_str("multi-frame anonymous transfer"),
+            RxErrorKind::NonLastUnderUtilization => {
+                f.write_str("frame other than the last one not using the full MTU")
+            }
+            RxErrorKind::FrameEmpty => f.write_str("empty frame"),
+            RxErrorKind::InvalidCanId => f.write_str("invalid CAN ID"),
+            RxErrorKind::Session(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<session::SessionError> for RxErrorKind {
+    fn from(err: session::SessionError) -> Self {
+        RxErrorKind::Session(err)
+    }
+}
+
+/// Error receiving a frame, with as much as could be told about the transfer the frame
+/// belonged to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RxError {
+    pub kind: RxErrorKind,
+    pub transfer_kind: Option<TransferKind>,
+    pub port_id: Option<PortId>,
+    /// Source of the transfer, `None` if it's anonymous or unknown.
+    pub source_node_id: Option<NodeId>,
+    pub transfer_id: Option<TransferId>,
+}
+
+impl RxError {
+    /// Error without anything known about the transfer.
+    pub fn new(kind: RxErrorKind) -> Self {
+        Self {
+            kind,
+            transfer_kind: None,
+            port_id: None,
+            source_node_id: None,
+            transfer_id: None,
+        }
+    }
+}
+
+impl From<RxErrorKind> for RxError {
+    fn from(kind: RxErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+/// E.g. `transfer CRC mismatch: message 7509, source node 12, transfer ID 3`.
+impl fmt::Display for RxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+
+        let mut separator = ": ";
+        if let Some(port_id) = self.port_id {
+            match self.transfer_kind {
+                Some(transfer_kind) => write!(f, "{}{} {}", separator, transfer_kind, port_id)?,
+                None => write!(f, "{}port {}", separator, port_id)?,
+            }
+            separator = ", ";
+        }
+        if let Some(source_node_id) = self.source_node_id {
+            write!(f, "{}source node {}", separator, source_node_id)?;
+            separator = ", ";
+        }
+        if let Some(transfer_id) = self.transfer_id {
+            write!(f, "{}transfer ID {}", separator, transfer_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RxError {}
+
+/// Errors possible while dispatching received frames to handlers.
+#[derive(Copy, Clone, Debug)]
+pub enum DispatchError {
+    /// The incoming frame couldn't be received.
+    Rx(RxError),
+    /// The response couldn't be turned into frames.
+    Tx(TxError),
+    /// There was no room left in the transmit queue for the response.
+    QueueFull,
 }
 
 /// Errors that can be caused by incorrect parameters for transmission
@@ -89,6 +191,9 @@ pub enum TxError {
     AnonNotSingleFrame,
     ServiceNoSourceID,
     ServiceNoDestinationID,
+    /// The payload length is past the end of its buffer, e.g. a handler returned a
+    /// response longer than `Handlers::ResponseBuffer`.
+    PayloadOutOfBounds,
 }
 
 // TODO could replace with custom impl's to reduce dependencies
@@ -115,6 +220,7 @@ pub struct Subscription {
     port_id: PortId,
     extent: usize,
     timeout: Duration,
+    transfer_id_timeout: Duration,
 }
 
 impl Subscription {
@@ -129,8 +235,41 @@ impl Subscription {
             port_id,
             extent,
             timeout,
+            transfer_id_timeout: timeout,
         }
     }
+
+    /// Set the transfer-ID timeout, which defaults to the session timeout.
+    ///
+    /// Within this long after a transfer started, a transfer from the same source
+    /// that repeats its transfer ID (or uses an older one) is rejected as a duplicate.
+    /// Redundant interfaces also only fail over once their session has been quiet
+    /// for this long.
+    pub fn with_transfer_id_timeout(mut self, timeout: Duration) -> Self {
+        self.transfer_id_timeout = timeout;
+        self
+    }
+
+    /// Create a subscription for a data type, taking the extent from `M::EXTENT`.
+    pub fn for_type<M: serialization::Deserialize>(
+        transfer_kind: TransferKind,
+        port_id: PortId,
+        timeout: Duration,
+    ) -> Self {
+        Self::new(transfer_kind, port_id, M::EXTENT, timeout)
+    }
+
+    pub fn transfer_kind(&self) -> TransferKind {
+        self.transfer_kind
+    }
+
+    pub fn port_id(&self) -> PortId {
+        self.port_id
+    }
+
+    pub fn extent(&self) -> usize {
+        self.extent
+    }
 }
 
 impl PartialEq for Subscription {
@@ -0,0 +1,426 @@
+//! Reading and writing `candump -l` logs, for recording and replaying bus traffic.
+//!
+//! Each line holds one frame: `(seconds.micros) interface ID#DATA`, or
+//! `ID##<flags>DATA` for FD frames. Lines are parsed into `Record`s, and `Replay`
+//! turns a whole log into timestamped frames that can be fed to
+//! `Node::try_receive_frame`. `write_frame` and `write_fd_frame` go the other way,
+//! e.g. to log the frames coming out of `Node::transmit`.
+//!
+//! Standard ID, remote, and error frames aren't used by Cyphal, and are skipped.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use embedded_hal::can::ExtendedId;
+
+use super::{micros_since_epoch, replay_timestamp, AnyCanFrame};
+use crate::time::Timestamp;
+use crate::transport::can::{fd_dlc_to_len, fd_len_to_dlc, CanFrame, FdCanFrame};
+
+/// Set in the logged ID of error frames (`CAN_ERR_FLAG`).
+const ERROR_FLAG: u32 = 0x2000_0000;
+
+/// Errors parsing a log line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CandumpError {
+    /// Line isn't `(timestamp) interface frame`.
+    InvalidFormat,
+    /// Timestamp isn't `seconds.fraction`, or doesn't fit the clock.
+    InvalidTimestamp,
+    /// A character that should be a hex digit isn't.
+    InvalidHex,
+    /// ID doesn't fit in 29 bits.
+    InvalidId,
+    /// Too much data for the frame type, or an odd number of hex digits.
+    InvalidLength,
+}
+
+/// A single frame from a log.
+#[derive(Clone, Debug)]
+pub struct Record<'a> {
+    /// Time the frame was captured, in microseconds since the Unix epoch.
+    pub time: u64,
+    pub interface: &'a str,
+    pub id: ExtendedId,
+    /// Whether this was an FD (`##`) frame.
+    pub fd: bool,
+    /// FD flags nibble (BRS, ESI), zero for classic frames.
+    pub flags: u8,
+    pub data: ArrayVec<[u8; 64]>,
+}
+
+impl<'a> Record<'a> {
+    /// Turn the record into a frame received at `timestamp`.
+    pub fn into_frame<C: embedded_time::Clock>(self, timestamp: Timestamp<C>) -> AnyCanFrame<C> {
+        if self.fd {
+            let mut frame = FdCanFrame::new(timestamp, self.id.as_raw());
+            frame.dlc = fd_len_to_dlc(self.data.len());
+            frame.payload = self.data;
+            AnyCanFrame::Fd(frame)
+        } else {
+            let mut frame = CanFrame {
+                timestamp,
+                id: self.id,
+                payload: ArrayVec::new(),
+            };
+            frame.payload.extend(self.data.iter().copied());
+            AnyCanFrame::Classic(frame)
+        }
+    }
+}
+
+/// Parse a single log line.
+///
+/// Blank lines and frames Cyphal doesn't use are `None`.
+pub fn parse_line(line: &str) -> Result<Option<Record<'_>>, CandumpError> {
+    let mut fields = line.split_whitespace();
+    let time = match fields.next() {
+        Some(time) => time,
+        None => return Ok(None),
+    };
+    let (interface, frame) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(interface), Some(frame), None) => (interface, frame),
+        _ => return Err(CandumpError::InvalidFormat),
+    };
+    let time = parse_time(time)?;
+
+    let (id, rest) = frame.split_once('#').ok_or(CandumpError::InvalidFormat)?;
+    // Standard IDs are 3 digits, extended 8
+    if id.len() != 8 {
+        return Ok(None);
+    }
+    let id = u32::from_str_radix(id, 16).map_err(|_| CandumpError::InvalidHex)?;
+    if id & ERROR_FLAG != 0 {
+        return Ok(None);
+    }
+    let id = ExtendedId::new(id).ok_or(CandumpError::InvalidId)?;
+
+    let (fd, flags, data) = match rest.strip_prefix('#') {
+        Some(rest) => {
+            let mut chars = rest.chars();
+            let flags = chars
+                .next()
+                .and_then(|flags| flags.to_digit(16))
+                .ok_or(CandumpError::InvalidHex)?;
+            (true, flags as u8, chars.as_str())
+        }
+        None if rest.starts_with('R') => return Ok(None),
+        None => (false, 0, rest),
+    };
+
+    // Classic frames with a raw DLC above 8 are logged as `DATA_DLC`
+    let data = data.split('_').next().unwrap_or_default();
+    let max = if fd { 64 } else { 8 };
+    if data.len() % 2 != 0 || data.len() > 2 * max {
+        return Err(CandumpError::InvalidLength);
+    }
+    let mut bytes = ArrayVec::new();
+    for i in (0..data.len()).step_by(2) {
+        let byte = data
+            .get(i..i + 2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .ok_or(CandumpError::InvalidHex)?;
+        bytes.push(byte);
+    }
+    if fd && fd_dlc_to_len(fd_len_to_dlc(bytes.len())) != bytes.len() {
+        return Err(CandumpError::InvalidLength);
+    }
+
+    Ok(Some(Record {
+        time,
+        interface,
+        id,
+        fd,
+        flags,
+        data: bytes,
+    }))
+}
+
+/// Parse `(seconds.fraction)` into microseconds.
+fn parse_time(time: &str) -> Result<u64, CandumpError> {
+    let time = time
+        .strip_prefix('(')
+        .and_then(|time| time.strip_suffix(')'))
+        .ok_or(CandumpError::InvalidFormat)?;
+    let (secs, fraction) = time.split_once('.').unwrap_or((time, ""));
+    if !fraction.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(CandumpError::InvalidTimestamp);
+    }
+    let secs: u64 = secs.parse().map_err(|_| CandumpError::InvalidTimestamp)?;
+
+    // Anything below a microsecond is dropped
+    let mut micros = 0;
+    for i in 0..6 {
+        let digit = fraction.as_bytes().get(i).map_or(0, |c| c - b'0');
+        micros = micros * 10 + digit as u64;
+    }
+    secs.checked_mul(1_000_000)
+        .and_then(|secs| secs.checked_add(micros))
+        .ok_or(CandumpError::InvalidTimestamp)
+}
+
+/// Frames from a log, timestamped relative to the first.
+///
+/// The first frame is at tick zero, so the replayed traffic lines up with a
+/// freshly started clock (e.g. `TestClock`) rather than the wall-clock time it was
+/// recorded at.
+pub struct Replay<'a, C> {
+    lines: core::str::Lines<'a>,
+    start: Option<u64>,
+    _clock: PhantomData<C>,
+}
+
+impl<'a, C: embedded_time::Clock> Replay<'a, C> {
+    pub fn new(log: &'a str) -> Self {
+        Self {
+            lines: log.lines(),
+            start: None,
+            _clock: PhantomData,
+        }
+    }
+}
+
+impl<'a, C> Iterator for Replay<'a, C>
+where
+    C: embedded_time::Clock,
+    C::T: TryFrom<u64>,
+{
+    type Item = Result<AnyCanFrame<C>, CandumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = match parse_line(self.lines.next()?) {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+
+            let timestamp = replay_timestamp(&mut self.start, record.time)
+                .ok_or(CandumpError::InvalidTimestamp);
+            return Some(timestamp.map(|timestamp| record.into_frame(timestamp)));
+        }
+    }
+}
+
+/// Write a classic frame as a log line, including the newline.
+///
+/// The timestamp is the frame's time since its clock's epoch.
+pub fn write_frame<W, C>(out: &mut W, interface: &str, frame: &CanFrame<C>) -> fmt::Result
+where
+    W: fmt::Write,
+    C: embedded_time::Clock,
+    C::T: Into<u64>,
+{
+    write_time(out, frame.timestamp)?;
+    write!(out, " {} {:08X}#", interface, frame.id.as_raw())?;
+    write_data(out, &frame.payload)
+}
+
+/// Write an FD frame as a log line, including the newline.
+///
+/// The payload is zero-padded up to the next valid FD length, and no flags are set.
+pub fn write_fd_frame<W, C>(out: &mut W, interface: &str, frame: &FdCanFrame<C>) -> fmt::Result
+where
+    W: fmt::Write,
+    C: embedded_time::Clock,
+    C::T: Into<u64>,
+{
+    write_time(out, frame.timestamp)?;
+    write!(out, " {} {:08X}##0", interface, frame.id.as_raw())?;
+    let mut padded = [0u8; 64];
+    padded[..frame.payload.len()].copy_from_slice(&frame.payload);
+    write_data(
+        out,
+        &padded[..fd_dlc_to_len(fd_len_to_dlc(frame.payload.len()))],
+    )
+}
+
+fn write_time<W, C>(out: &mut W, timestamp: Timestamp<C>) -> fmt::Result
+where
+    W: fmt::Write,
+    C: embedded_time::Clock,
+    C::T: Into<u64>,
+{
+    let micros = micros_since_epoch(timestamp).ok_or(fmt::Error)?;
+    write!(out, "({}.{:06})", micros / 1_000_000, micros % 1_000_000)
+}
+
+fn write_data<W: fmt::Write>(out: &mut W, data: &[u8]) -> fmt::Result {
+    for byte in data {
+        write!(out, "{:02X}", byte)?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::testing::{message_transfer, TestNode, TestSessionManager};
+    use crate::time::TestClock;
+    use crate::{Subscription, TransferKind};
+
+    /// Heartbeats from node 42, as `candump -l` recorded them.
+    const HEARTBEAT_LOG: &str = "\
+(1700000000.000000) can0 107D552A#00000000000000E0
+(1700000000.500000) can0 123#DEADBEEF
+(1700000001.000000) can0 107D552A#01000000000000E1
+(1700000001.250000) can0 20000004#0000000000000000
+";
+
+    #[test]
+    fn parse_classic() {
+        let record = parse_line("(1436509052.249713) vcan0 107D552A#0102E0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.time, 1_436_509_052_249_713);
+        assert_eq!(record.interface, "vcan0");
+        assert_eq!(record.id.as_raw(), 0x107D552A);
+        assert!(!record.fd);
+        assert_eq!(&record.data[..], [1, 2, 0xE0]);
+
+        // Empty payload, short fraction
+        let record = parse_line("(12.5) can1 107D552A#").unwrap().unwrap();
+        assert_eq!(record.time, 12_500_000);
+        assert!(record.data.is_empty());
+    }
+
+    #[test]
+    fn parse_fd() {
+        let record = parse_line("(0.000001) can0 107D552A##1000102030405060708090AE0")
+            .unwrap()
+            .unwrap();
+        assert!(record.fd);
+        assert_eq!(record.flags, 1);
+        assert_eq!(record.data.len(), 12);
+        assert_eq!(record.data[11], 0xE0);
+
+        // 11 bytes isn't a valid FD length
+        assert_eq!(
+            parse_line("(0.0) can0 107D552A##00102030405060708090A0B").unwrap_err(),
+            CandumpError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn parse_skipped_and_invalid() {
+        assert!(parse_line("").unwrap().is_none());
+        assert!(parse_line("(0.0) can0 123#00").unwrap().is_none());
+        assert!(parse_line("(0.0) can0 107D552A#R").unwrap().is_none());
+        assert!(parse_line("(0.0) can0 20000004#0000").unwrap().is_none());
+
+        assert_eq!(
+            parse_line("can0 107D552A#00").unwrap_err(),
+            CandumpError::InvalidFormat
+        );
+        assert_eq!(
+            parse_line("(x.0) can0 107D552A#00").unwrap_err(),
+            CandumpError::InvalidTimestamp
+        );
+        assert_eq!(
+            parse_line("(0.0) can0 107D552A#0G").unwrap_err(),
+            CandumpError::InvalidHex
+        );
+        assert_eq!(
+            parse_line("(0.0) can0 107D552A#000").unwrap_err(),
+            CandumpError::InvalidLength
+        );
+        assert_eq!(
+            parse_line("(0.0) can0 107D552A#000102030405060708").unwrap_err(),
+            CandumpError::InvalidLength
+        );
+        assert_eq!(
+            parse_line("(0.0) can0 40000000#00").unwrap_err(),
+            CandumpError::InvalidId
+        );
+    }
+
+    #[test]
+    fn replay_into_node() {
+        let mut node = TestNode::new(Some(41), TestSessionManager::new());
+        node.sessions
+            .subscribe(Subscription::new(
+                TransferKind::Message,
+                7509,
+                7,
+                Milliseconds(500),
+            ))
+            .unwrap();
+
+        let mut received = Vec::new();
+        for frame in Replay::<TestClock>::new(HEARTBEAT_LOG) {
+            let frame = match frame.unwrap() {
+                AnyCanFrame::Classic(frame) => frame,
+                AnyCanFrame::Fd(_) => panic!("unexpected FD frame"),
+            };
+            if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+                let elapsed = Milliseconds::<u32>::try_from(
+                    transfer.metadata.timestamp.duration_since_epoch(),
+                )
+                .unwrap();
+                received.push((transfer.metadata.transfer_id, elapsed));
+            }
+        }
+        assert_eq!(
+            received,
+            [(0, Milliseconds(0u32)), (1, Milliseconds(1000u32))]
+        );
+    }
+
+    #[test]
+    fn log_transmitted_frames() {
+        let mut clock = TestClock::default();
+        clock.add_duration(&Milliseconds(1500u32)).unwrap();
+        let node = TestNode::new(Some(42), TestSessionManager::new());
+        let payload: Vec<u8> = (0..12).collect();
+        let transfer = message_transfer(clock.try_now().unwrap(), 100, 3, &payload);
+
+        let mut log = String::new();
+        let mut frames = node.transmit(&transfer).unwrap();
+        while let Some(frame) = frames.next() {
+            write_frame(&mut log, "can0", frame).unwrap();
+        }
+        assert_eq!(log.lines().count(), 2);
+        assert!(log.starts_with("(1.500000) can0 1060642A#00010203040506A3\n"));
+
+        // The log reads back as the same frames
+        let mut frames = node.transmit(&transfer).unwrap();
+        for record in log.lines() {
+            let record = parse_line(record).unwrap().unwrap();
+            let frame = frames.next().unwrap();
+            assert_eq!(record.id, frame.id);
+            assert_eq!(&record.data[..], &frame.payload[..]);
+        }
+    }
+
+    #[test]
+    fn fd_round_trip() {
+        let clock = TestClock::default();
+        let mut frame = FdCanFrame::<TestClock>::new(clock.try_now().unwrap(), 0x107D552A);
+        frame.payload.extend(0..10);
+
+        let mut log = String::new();
+        write_fd_frame(&mut log, "can0", &frame).unwrap();
+        assert_eq!(
+            log,
+            "(0.000000) can0 107D552A##000010203040506070809\
+             0000\n"
+        );
+
+        let record = parse_line(&log).unwrap().unwrap();
+        let frame = match record.into_frame(clock.try_now().unwrap()) {
+            AnyCanFrame::Fd(frame) => frame,
+            AnyCanFrame::Classic(_) => panic!("expected FD frame"),
+        };
+        assert_eq!(frame.dlc, 9);
+        assert_eq!(&frame.payload[..10], (0..10).collect::<Vec<u8>>());
+    }
+}
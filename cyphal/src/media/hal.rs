@@ -0,0 +1,238 @@
+//! Media for any `embedded_hal::can::nb::Can` driver (bxcan, fdcan, mcp2515, ...).
+//!
+//! `HalCan` wraps the driver along with a clock to timestamp received frames, and
+//! converts between the driver's frame type and `CanFrame`, so firmware doesn't need
+//! its own glue for every MCU family.
+//!
+//! Reception never blocks: `receive` returns `None` when the driver has nothing.
+//! Transmission blocks until the driver accepts the frame. If the driver kicks a
+//! lower-priority pending frame out of its mailbox to make room, that frame is
+//! queued again, so nothing is lost.
+
+use embedded_hal::can::nb::Can;
+use embedded_hal::can::{Frame, Id};
+
+use super::CanMedia;
+use crate::transport::can::CanFrame;
+
+/// Errors from `HalCan`.
+#[derive(Copy, Clone, Debug)]
+pub enum HalCanError<E> {
+    /// The driver reported an error.
+    Driver(E),
+    /// The clock couldn't be read to timestamp a frame.
+    Clock,
+    /// The driver couldn't build a frame for the outgoing data.
+    InvalidFrame,
+}
+
+/// `CanMedia` for an `embedded_hal` CAN driver.
+pub struct HalCan<D, C> {
+    driver: D,
+    clock: C,
+}
+
+impl<D, C> HalCan<D, C>
+where
+    D: Can,
+    C: embedded_time::Clock,
+{
+    pub fn new(driver: D, clock: C) -> Self {
+        Self { driver, clock }
+    }
+
+    /// Access the driver, e.g. to handle interrupts or reconfigure it.
+    pub fn driver(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    /// Give the driver back.
+    pub fn free(self) -> D {
+        self.driver
+    }
+}
+
+impl<D, C> CanMedia<CanFrame<C>> for HalCan<D, C>
+where
+    D: Can,
+    C: embedded_time::Clock,
+{
+    type Error = HalCanError<D::Error>;
+
+    fn receive(&mut self) -> Result<Option<CanFrame<C>>, Self::Error> {
+        let frame = match self.driver.receive() {
+            Ok(frame) => frame,
+            Err(nb::Error::WouldBlock) => return Ok(None),
+            Err(nb::Error::Other(err)) => return Err(HalCanError::Driver(err)),
+        };
+
+        // Cyphal only uses extended data frames, anything else isn't for us
+        if frame.is_remote_frame() || !matches!(frame.id(), Id::Extended(_)) {
+            return Ok(None);
+        }
+        let mut frame = match <CanFrame<C> as Frame>::new(frame.id(), frame.data()) {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        frame.timestamp = self.clock.try_now().map_err(|_| HalCanError::Clock)?;
+
+        Ok(Some(frame))
+    }
+
+    fn transmit(&mut self, frame: &CanFrame<C>) -> Result<(), Self::Error> {
+        let mut pending = D::Frame::new(Id::Extended(frame.id), &frame.payload)
+            .ok_or(HalCanError::InvalidFrame)?;
+
+        loop {
+            match nb::block!(self.driver.transmit(&pending)) {
+                Ok(Some(displaced)) => pending = displaced,
+                Ok(None) => return Ok(()),
+                Err(err) => return Err(HalCanError::Driver(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+
+    use embedded_hal::can::{ErrorKind, ExtendedId, StandardId};
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::testing::{message_transfer, TestNode, TestSessionManager};
+    use crate::time::TestClock;
+    use crate::{Subscription, TransferKind};
+
+    /// Driver's own frame type, as a HAL would define it.
+    #[derive(Clone, Debug)]
+    struct MockFrame {
+        id: Id,
+        data: Vec<u8>,
+        remote: bool,
+    }
+
+    impl Frame for MockFrame {
+        fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+            Some(Self {
+                id: id.into(),
+                data: data.to_vec(),
+                remote: false,
+            })
+        }
+
+        fn new_remote(id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+            Some(Self {
+                id: id.into(),
+                data: Vec::new(),
+                remote: true,
+            })
+        }
+
+        fn is_extended(&self) -> bool {
+            matches!(self.id, Id::Extended(_))
+        }
+
+        fn is_remote_frame(&self) -> bool {
+            self.remote
+        }
+
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn dlc(&self) -> usize {
+            self.data.len()
+        }
+
+        fn data(&self) -> &[u8] {
+            &self.data
+        }
+    }
+
+    /// Driver with a single TX mailbox, which is busy every other attempt.
+    #[derive(Default)]
+    struct MockCan {
+        rx: VecDeque<MockFrame>,
+        tx: Vec<MockFrame>,
+        busy: bool,
+    }
+
+    impl Can for MockCan {
+        type Frame = MockFrame;
+        type Error = ErrorKind;
+
+        fn transmit(&mut self, frame: &MockFrame) -> nb::Result<Option<MockFrame>, ErrorKind> {
+            self.busy = !self.busy;
+            if self.busy {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.tx.push(frame.clone());
+            Ok(None)
+        }
+
+        fn receive(&mut self) -> nb::Result<MockFrame, ErrorKind> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn round_trip_through_driver() {
+        let clock = TestClock::default();
+        let mut publisher = TestNode::new(Some(41), TestSessionManager::new());
+        let mut subscriber = TestNode::new(Some(42), TestSessionManager::new());
+        subscriber
+            .sessions
+            .subscribe(Subscription::new(
+                TransferKind::Message,
+                100,
+                16,
+                Milliseconds(500),
+            ))
+            .unwrap();
+
+        let mut media = HalCan::new(MockCan::default(), clock.clone());
+        let payload = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let transfer = message_transfer(clock.try_now().unwrap(), 100, 0, &payload);
+        publisher.transmit_to(&transfer, &mut media).unwrap();
+
+        // Loop the sent frames back, with some noise a real bus might carry
+        let driver = media.driver();
+        assert_eq!(driver.tx.len(), 2);
+        let sent: Vec<MockFrame> = driver.tx.drain(..).collect();
+        driver
+            .rx
+            .push_back(MockFrame::new(StandardId::new(100).unwrap(), &[0]).unwrap());
+        driver
+            .rx
+            .push_back(MockFrame::new_remote(ExtendedId::new(100).unwrap(), 0).unwrap());
+        driver.rx.extend(sent);
+
+        let mut received = None;
+        for _ in 0..4 {
+            if let Some(transfer) = subscriber.receive_from(&mut media).unwrap() {
+                received = Some(transfer.payload.to_vec());
+            }
+        }
+        assert_eq!(received.as_deref(), Some(&payload[..]));
+        assert!(subscriber.receive_from(&mut media).unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_conversion() {
+        let id = ExtendedId::new(0x1234567).unwrap();
+        let frame = <CanFrame<TestClock> as Frame>::new(id, &[1, 2, 3]).unwrap();
+        assert_eq!(frame.id(), Id::Extended(id));
+        assert_eq!(frame.data(), [1, 2, 3]);
+        assert_eq!(frame.dlc(), 3);
+        assert!(frame.is_extended() && frame.is_data_frame());
+
+        assert!(<CanFrame<TestClock> as Frame>::new(id, &[0; 9]).is_none());
+        assert!(<CanFrame<TestClock> as Frame>::new(StandardId::new(1).unwrap(), &[]).is_none());
+        assert!(<CanFrame<TestClock> as Frame>::new_remote(id, 0).is_none());
+    }
+}
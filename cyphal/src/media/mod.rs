@@ -0,0 +1,194 @@
+//! Media drivers, connecting a `Node` to an actual bus.
+//!
+//! Drivers only have to move single frames in and out, through `CanMedia`.
+//! `Node::receive_from` and `Node::transmit_to` take care of the rest. Because
+//! the trait is this small, tests and simulations can stand in for real hardware
+//! with a plain `VecDeque`, which acts as a loopback bus.
+//!
+//! On microcontrollers, `hal::HalCan` works with any `embedded_hal` CAN driver.
+//! `slcan` talks to USB-serial adapters, without needing SocketCAN.
+//!
+//! `candump` reads and writes `candump -l` logs, to record traffic and replay it,
+//! and `pcap` does the same with Wireshark captures.
+
+use alloc::collections::VecDeque;
+use core::convert::Infallible;
+
+use embedded_time::duration::Microseconds;
+use embedded_time::fixed_point::FixedPoint;
+
+use crate::time::Timestamp;
+use crate::transport::can::{CanFrame, FdCanFrame};
+use crate::{RxError, TxError};
+
+pub mod candump;
+pub mod hal;
+#[cfg(feature = "std")]
+pub mod pcap;
+pub mod slcan;
+#[cfg(feature = "socketcan")]
+pub mod socketcan;
+
+/// Frame-level interface to a bus, carrying frames of type `F`.
+pub trait CanMedia<F> {
+    type Error;
+
+    /// Receive the next frame, or `None` if there wasn't one.
+    ///
+    /// Whether this blocks, and for how long, is up to the driver.
+    fn receive(&mut self) -> Result<Option<F>, Self::Error>;
+
+    /// Send a frame onto the bus.
+    fn transmit(&mut self, frame: &F) -> Result<(), Self::Error>;
+}
+
+/// Classic or FD frame, from sources that can carry either.
+#[derive(Clone, Debug)]
+pub enum AnyCanFrame<C: embedded_time::Clock> {
+    Classic(CanFrame<C>),
+    Fd(FdCanFrame<C>),
+}
+
+impl<C: embedded_time::Clock> AnyCanFrame<C> {
+    /// Convert to an FD frame, which can hold classic frames as well.
+    pub fn into_fd(self) -> FdCanFrame<C> {
+        match self {
+            AnyCanFrame::Fd(frame) => frame,
+            AnyCanFrame::Classic(frame) => {
+                let mut fd = FdCanFrame::new(frame.timestamp, frame.id.as_raw());
+                fd.dlc = frame.payload.len() as u8;
+                fd.payload.extend(frame.payload.iter().copied());
+                fd
+            }
+        }
+    }
+}
+
+/// Errors possible when driving a node through `CanMedia`.
+#[derive(Copy, Clone, Debug)]
+pub enum MediaError<E> {
+    /// The driver failed to send or receive a frame.
+    Media(E),
+    /// An incoming frame couldn't be received.
+    Rx(RxError),
+    /// The outgoing transfer couldn't be turned into frames.
+    Tx(TxError),
+}
+
+/// Loopback bus: transmitted frames are received again, in order.
+impl<F: Clone> CanMedia<F> for VecDeque<F> {
+    type Error = Infallible;
+
+    fn receive(&mut self) -> Result<Option<F>, Self::Error> {
+        Ok(self.pop_front())
+    }
+
+    fn transmit(&mut self, frame: &F) -> Result<(), Self::Error> {
+        self.push_back(frame.clone());
+        Ok(())
+    }
+}
+
+/// Timestamp of a recorded frame captured at `time` (µs), relative to the first
+/// frame of the recording, whose capture time is kept in `start`.
+///
+/// `embedded_time` only has clocks counting in `u32` or `u64`, both of which convert
+/// from `u64`, but the bound still has to be spelled out.
+fn replay_timestamp<C>(start: &mut Option<u64>, time: u64) -> Option<Timestamp<C>>
+where
+    C: embedded_time::Clock,
+    C::T: TryFrom<u64>,
+{
+    let start = *start.get_or_insert(time);
+    Timestamp::<C>::new(C::T::from(0)).checked_add(Microseconds(time.saturating_sub(start)))
+}
+
+/// Time since the clock's epoch, in µs, for recording frames.
+fn micros_since_epoch<C>(timestamp: Timestamp<C>) -> Option<u64>
+where
+    C: embedded_time::Clock,
+    C::T: Into<u64>,
+{
+    Microseconds::<C::T>::try_from(timestamp.duration_since_epoch())
+        .ok()
+        .map(|micros| micros.integer().into())
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::testing::{message_transfer, TestNode, TestSessionManager};
+    use crate::time::TestClock;
+    use crate::{Subscription, TransferKind};
+
+    /// Media that fails every operation.
+    struct BrokenMedia;
+
+    impl CanMedia<CanFrame<TestClock>> for BrokenMedia {
+        type Error = ();
+
+        fn receive(&mut self) -> Result<Option<CanFrame<TestClock>>, ()> {
+            Err(())
+        }
+
+        fn transmit(&mut self, _frame: &CanFrame<TestClock>) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn loopback_round_trip() {
+        let mut publisher = TestNode::new(Some(41), TestSessionManager::new());
+        let mut subscriber = TestNode::new(Some(42), TestSessionManager::new());
+        subscriber
+            .sessions
+            .subscribe(Subscription::new(
+                TransferKind::Message,
+                100,
+                32,
+                Milliseconds(500),
+            ))
+            .unwrap();
+
+        let mut bus: VecDeque<CanFrame<TestClock>> = VecDeque::new();
+        let clock = TestClock::default();
+        let payload: Vec<u8> = (0..12).collect();
+        let transfer = message_transfer(clock.try_now().unwrap(), 100, 0, &payload);
+        publisher.transmit_to(&transfer, &mut bus).unwrap();
+        assert_eq!(bus.len(), 2);
+
+        let mut received = None;
+        while !bus.is_empty() {
+            if let Some(transfer) = subscriber.receive_from(&mut bus).unwrap() {
+                received = Some(transfer.payload.to_vec());
+            }
+        }
+        assert_eq!(received, Some(payload));
+
+        // Nothing left on the bus
+        assert!(subscriber.receive_from(&mut bus).unwrap().is_none());
+    }
+
+    #[test]
+    fn media_errors_passed_through() {
+        let mut node = TestNode::new(Some(42), TestSessionManager::new());
+        let clock = TestClock::default();
+        let payload = [1, 2, 3];
+        let transfer = message_transfer(clock.try_now().unwrap(), 100, 0, &payload);
+
+        assert!(matches!(
+            node.transmit_to(&transfer, &mut BrokenMedia),
+            Err(MediaError::Media(()))
+        ));
+        assert!(matches!(
+            node.receive_from(&mut BrokenMedia),
+            Err(MediaError::Media(()))
+        ));
+    }
+}
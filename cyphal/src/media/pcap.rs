@@ -0,0 +1,752 @@
+//! Reading and writing pcap and pcapng captures of CAN traffic, for analysis in
+//! Wireshark.
+//!
+//! Captures use `LINKTYPE_CAN_SOCKETCAN`, so each record holds a SocketCAN
+//! `can_frame` or `canfd_frame`, told apart by length. `PcapWriter` records frames
+//! with their timestamps, e.g. everything coming out of `Node::transmit`, and
+//! `PcapReader` turns a capture back into frames that can be fed to
+//! `Node::try_receive_frame`, the same way `candump::Replay` does for text logs.
+//!
+//! `PcapWriter::new` writes classic pcap, and `PcapWriter::pcapng` writes pcapng,
+//! which is what Wireshark saves by default. `PcapReader` reads either.
+
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::vec::Vec;
+
+use arrayvec::ArrayVec;
+use embedded_hal::can::ExtendedId;
+
+use super::{micros_since_epoch, replay_timestamp, AnyCanFrame};
+use crate::transport::can::{fd_dlc_to_len, fd_len_to_dlc, CanFrame, FdCanFrame};
+
+/// Microsecond resolution capture, in the writer's byte order.
+const MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+/// Nanosecond resolution capture, in the writer's byte order.
+const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const LINKTYPE_CAN_SOCKETCAN: u32 = 227;
+
+/// pcapng block types. The section header's reads the same in either byte order.
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 1;
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+/// pcapng section header magic, in the writer's byte order.
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// `if_tsresol` interface option, giving the timestamp resolution.
+const OPTION_TSRESOL: u16 = 9;
+/// Longest pcapng interface options that will be read.
+const MAX_OPTIONS: usize = 4096;
+
+/// `can_id` flags.
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+/// `canfd_frame` flag marking it as FD, even when its payload would fit classic CAN.
+const CANFD_FDF: u8 = 0x04;
+
+/// Size of `can_frame` and `canfd_frame`.
+const CAN_MTU: usize = 16;
+const CANFD_MTU: usize = 72;
+
+/// Writes frames to a pcap or pcapng capture.
+pub struct PcapWriter<W> {
+    out: W,
+    pcapng: bool,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Start a pcap capture, writing its header to `out`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        header[0..4].copy_from_slice(&MAGIC_MICROS.to_le_bytes());
+        header[4..6].copy_from_slice(&2u16.to_le_bytes());
+        header[6..8].copy_from_slice(&4u16.to_le_bytes());
+        // Time zone and accuracy are always zero
+        header[16..20].copy_from_slice(&(CANFD_MTU as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        out.write_all(&header)?;
+        Ok(Self { out, pcapng: false })
+    }
+
+    /// Start a pcapng capture with a single SocketCAN interface, writing its section
+    /// header and interface description to `out`.
+    pub fn pcapng(mut out: W) -> io::Result<Self> {
+        let mut header = [0u8; 28 + 20];
+        header[0..4].copy_from_slice(&BLOCK_SECTION_HEADER.to_le_bytes());
+        header[4..8].copy_from_slice(&28u32.to_le_bytes());
+        header[8..12].copy_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        header[12..14].copy_from_slice(&1u16.to_le_bytes());
+        // Section length isn't known up front
+        header[16..24].copy_from_slice(&(-1i64).to_le_bytes());
+        header[24..28].copy_from_slice(&28u32.to_le_bytes());
+
+        // Without options, timestamps are in microseconds
+        header[28..32].copy_from_slice(&BLOCK_INTERFACE_DESCRIPTION.to_le_bytes());
+        header[32..36].copy_from_slice(&20u32.to_le_bytes());
+        header[36..38].copy_from_slice(&(LINKTYPE_CAN_SOCKETCAN as u16).to_le_bytes());
+        header[40..44].copy_from_slice(&(CANFD_MTU as u32).to_le_bytes());
+        header[44..48].copy_from_slice(&20u32.to_le_bytes());
+        out.write_all(&header)?;
+        Ok(Self { out, pcapng: true })
+    }
+
+    /// Record a classic frame, at its timestamp since the clock's epoch.
+    pub fn write_frame<C>(&mut self, frame: &CanFrame<C>) -> io::Result<()>
+    where
+        C: embedded_time::Clock,
+        C::T: Into<u64>,
+    {
+        let mut packet = [0u8; CAN_MTU];
+        packet[0..4].copy_from_slice(&(frame.id.as_raw() | CAN_EFF_FLAG).to_be_bytes());
+        packet[4] = frame.payload.len() as u8;
+        packet[8..8 + frame.payload.len()].copy_from_slice(&frame.payload);
+        self.write_record(micros_since_epoch(frame.timestamp), &packet)
+    }
+
+    /// Record an FD frame, at its timestamp since the clock's epoch.
+    ///
+    /// The payload is zero-padded up to the next valid FD length.
+    pub fn write_fd_frame<C>(&mut self, frame: &FdCanFrame<C>) -> io::Result<()>
+    where
+        C: embedded_time::Clock,
+        C::T: Into<u64>,
+    {
+        let mut packet = [0u8; CANFD_MTU];
+        packet[0..4].copy_from_slice(&(frame.id.as_raw() | CAN_EFF_FLAG).to_be_bytes());
+        packet[4] = fd_dlc_to_len(fd_len_to_dlc(frame.payload.len())) as u8;
+        packet[5] = CANFD_FDF;
+        packet[8..8 + frame.payload.len()].copy_from_slice(&frame.payload);
+        self.write_record(micros_since_epoch(frame.timestamp), &packet)
+    }
+
+    /// Flush and give back the output.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_record(&mut self, micros: Option<u64>, packet: &[u8]) -> io::Result<()> {
+        let micros = micros.ok_or_else(|| invalid("timestamp out of range"))?;
+        if self.pcapng {
+            return self.write_packet_block(micros, packet);
+        }
+        let secs =
+            u32::try_from(micros / 1_000_000).map_err(|_| invalid("timestamp out of range"))?;
+
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(&secs.to_le_bytes());
+        header[4..8].copy_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(packet)
+    }
+
+    fn write_packet_block(&mut self, micros: u64, packet: &[u8]) -> io::Result<()> {
+        let padding = (4 - packet.len() % 4) % 4;
+        let len = (28 + packet.len() + padding + 4) as u32;
+
+        let mut header = [0u8; 28];
+        header[0..4].copy_from_slice(&BLOCK_ENHANCED_PACKET.to_le_bytes());
+        header[4..8].copy_from_slice(&len.to_le_bytes());
+        // Always the only interface, 0
+        header[12..16].copy_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        header[16..20].copy_from_slice(&(micros as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        header[24..28].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(packet)?;
+        self.out.write_all(&[0; 3][..padding])?;
+        self.out.write_all(&len.to_le_bytes())
+    }
+}
+
+/// Timestamp resolution of a pcapng interface, from its `if_tsresol` option.
+#[derive(Copy, Clone, Debug)]
+enum Resolution {
+    /// Units of 10^-n seconds.
+    Decimal(u8),
+    /// Units of 2^-n seconds.
+    Binary(u8),
+}
+
+impl Resolution {
+    fn micros(self, units: u64) -> Option<u64> {
+        let micros = match self {
+            Resolution::Decimal(exp) if exp <= 6 => units as u128 * 10u128.pow(6 - exp as u32),
+            Resolution::Decimal(exp) => units as u128 / 10u128.checked_pow(exp as u32 - 6)?,
+            Resolution::Binary(exp) => (units as u128 * 1_000_000) >> exp,
+        };
+        u64::try_from(micros).ok()
+    }
+}
+
+/// Reads frames from a pcap or pcapng capture, timestamped relative to the first.
+///
+/// Captures may come from any tool, in either byte order and at any timestamp
+/// resolution. Standard ID, remote and error frames aren't used by Cyphal, and are
+/// skipped. So are packets of pcapng interfaces that aren't SocketCAN, and pcapng
+/// blocks other than section headers, interface descriptions and enhanced packets.
+pub struct PcapReader<R, C> {
+    input: R,
+    big_endian: bool,
+    nanos: bool,
+    /// Interfaces of the current pcapng section, `None` for those that aren't
+    /// SocketCAN. Always empty for pcap.
+    interfaces: Vec<Option<Resolution>>,
+    pcapng: bool,
+    start: Option<u64>,
+    _clock: PhantomData<C>,
+}
+
+impl<R: Read, C: embedded_time::Clock> PcapReader<R, C> {
+    /// Open a capture, reading its header from `input`.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        input.read_exact(&mut header[..8])?;
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) == BLOCK_SECTION_HEADER
+        {
+            let mut reader = Self::with_input(input, false, false, true);
+            reader.read_section_header([header[4], header[5], header[6], header[7]])?;
+            return Ok(reader);
+        }
+        input.read_exact(&mut header[8..])?;
+
+        let magic = [header[0], header[1], header[2], header[3]];
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (MAGIC_MICROS, _) => (false, false),
+            (MAGIC_NANOS, _) => (false, true),
+            (_, MAGIC_MICROS) => (true, false),
+            (_, MAGIC_NANOS) => (true, true),
+            _ => return Err(invalid("not a pcap capture")),
+        };
+
+        let reader = Self::with_input(input, big_endian, nanos, false);
+        if reader.u32_at(&header, 20) != LINKTYPE_CAN_SOCKETCAN {
+            return Err(invalid("not a SocketCAN capture"));
+        }
+        Ok(reader)
+    }
+
+    fn with_input(input: R, big_endian: bool, nanos: bool, pcapng: bool) -> Self {
+        Self {
+            input,
+            big_endian,
+            nanos,
+            interfaces: Vec::new(),
+            pcapng,
+            start: None,
+            _clock: PhantomData,
+        }
+    }
+
+    fn u16_at(&self, bytes: &[u8], offset: usize) -> u16 {
+        let bytes = [bytes[offset], bytes[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32_at(&self, bytes: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// Next record and its capture time (µs), or `None` at the end of the capture.
+    fn read_record(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        if self.pcapng {
+            return self.read_packet_block();
+        }
+        let mut header = [0u8; 16];
+        if !self.read_header(&mut header)? {
+            return Ok(None);
+        }
+
+        let secs = self.u32_at(&header, 0) as u64;
+        let fraction = self.u32_at(&header, 4) as u64;
+        let micros = if self.nanos {
+            fraction / 1000
+        } else {
+            fraction
+        };
+        let len = self.u32_at(&header, 8) as usize;
+        if len > CANFD_MTU {
+            return Err(invalid("record too long"));
+        }
+
+        let mut packet = vec![0; len];
+        self.input.read_exact(&mut packet)?;
+        Ok(Some((secs * 1_000_000 + micros, packet)))
+    }
+
+    /// Fill `header`, or return `false` at the end of the capture.
+    fn read_header(&mut self, header: &mut [u8]) -> io::Result<bool> {
+        match self.input.read_exact(header) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn skip(&mut self, len: usize) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.input).take(len as u64), &mut io::sink())?;
+        if skipped < len as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Read the rest of a section header block, whose length (in a byte order not
+    /// known until now) has been read already. Starts a new list of interfaces.
+    fn read_section_header(&mut self, len: [u8; 4]) -> io::Result<()> {
+        let mut magic = [0u8; 4];
+        self.input.read_exact(&mut magic)?;
+        self.big_endian = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (BYTE_ORDER_MAGIC, _) => false,
+            (_, BYTE_ORDER_MAGIC) => true,
+            _ => return Err(invalid("not a pcapng capture")),
+        };
+        self.interfaces.clear();
+
+        // Version, section length and options aren't needed
+        let len = block_length(self.u32_at(&len, 0), 28)?;
+        self.skip(len - 12)
+    }
+
+    /// Next enhanced packet block of a SocketCAN interface, skipping any other block.
+    fn read_packet_block(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !self.read_header(&mut header)? {
+                return Ok(None);
+            }
+            let block_type = self.u32_at(&header, 0);
+            if block_type == BLOCK_SECTION_HEADER {
+                self.read_section_header([header[4], header[5], header[6], header[7]])?;
+                continue;
+            }
+
+            let len = self.u32_at(&header, 4);
+            match block_type {
+                BLOCK_INTERFACE_DESCRIPTION => {
+                    let len = block_length(len, 20)?;
+                    self.read_interface(len)?;
+                }
+                BLOCK_ENHANCED_PACKET => {
+                    let len = block_length(len, 32)?;
+                    if let Some(record) = self.read_packet(len)? {
+                        return Ok(Some(record));
+                    }
+                }
+                _ => {
+                    let len = block_length(len, 12)?;
+                    self.skip(len - 8)?;
+                }
+            }
+        }
+    }
+
+    fn read_interface(&mut self, len: usize) -> io::Result<()> {
+        let mut fixed = [0u8; 8];
+        self.input.read_exact(&mut fixed)?;
+        let link_type = self.u16_at(&fixed, 0) as u32;
+
+        let options_len = len - 20;
+        if options_len > MAX_OPTIONS {
+            return Err(invalid("interface options too long"));
+        }
+        let mut options = vec![0; options_len + 4];
+        self.input.read_exact(&mut options)?;
+
+        let mut resolution = Resolution::Decimal(6);
+        let mut offset = 0;
+        while offset + 4 <= options_len {
+            let code = self.u16_at(&options, offset);
+            let length = self.u16_at(&options, offset + 2) as usize;
+            if code == 0 {
+                break;
+            }
+            if code == OPTION_TSRESOL && length == 1 && offset + 4 < options_len {
+                let value = options[offset + 4];
+                resolution = if value & 0x80 == 0 {
+                    Resolution::Decimal(value)
+                } else {
+                    Resolution::Binary(value & 0x7F)
+                };
+            }
+            offset += 4 + length.div_ceil(4) * 4;
+        }
+
+        self.interfaces
+            .push((link_type == LINKTYPE_CAN_SOCKETCAN).then_some(resolution));
+        Ok(())
+    }
+
+    /// Read an enhanced packet block, or skip it if its interface isn't SocketCAN.
+    fn read_packet(&mut self, len: usize) -> io::Result<Option<(u64, Vec<u8>)>> {
+        let mut fixed = [0u8; 20];
+        self.input.read_exact(&mut fixed)?;
+        let resolution = match self.interfaces.get(self.u32_at(&fixed, 0) as usize) {
+            Some(Some(resolution)) => *resolution,
+            Some(None) => {
+                self.skip(len - 28)?;
+                return Ok(None);
+            }
+            None => return Err(invalid("packet of an undescribed interface")),
+        };
+        let units = (self.u32_at(&fixed, 4) as u64) << 32 | self.u32_at(&fixed, 8) as u64;
+
+        let captured = self.u32_at(&fixed, 12) as usize;
+        if captured > CANFD_MTU {
+            return Err(invalid("record too long"));
+        }
+        let padded = captured.div_ceil(4) * 4;
+        if 28 + padded + 4 > len {
+            return Err(invalid("block too short"));
+        }
+        let mut packet = vec![0; padded];
+        self.input.read_exact(&mut packet)?;
+        packet.truncate(captured);
+        // Options and the trailing length
+        self.skip(len - 28 - padded)?;
+
+        let micros = resolution
+            .micros(units)
+            .ok_or_else(|| invalid("timestamp out of range"))?;
+        Ok(Some((micros, packet)))
+    }
+
+    /// Decode a `can_frame`/`canfd_frame`, or `None` if it isn't for Cyphal.
+    fn decode(&mut self, time: u64, packet: &[u8]) -> io::Result<Option<AnyCanFrame<C>>>
+    where
+        C::T: TryFrom<u64>,
+    {
+        if packet.len() < 8 {
+            return Err(invalid("record too short"));
+        }
+        // `can_id` is in network byte order, whatever the capture's byte order
+        let can_id = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]);
+        if can_id & CAN_EFF_FLAG == 0 || can_id & (CAN_RTR_FLAG | CAN_ERR_FLAG) != 0 {
+            return Ok(None);
+        }
+        let id = ExtendedId::new(can_id & ExtendedId::MAX.as_raw()).unwrap();
+
+        let len = packet[4] as usize;
+        let fd = packet.len() == CANFD_MTU || packet[5] & CANFD_FDF != 0;
+        let data = packet
+            .get(8..8 + len)
+            .ok_or_else(|| invalid("payload length out of range"))?;
+        if (!fd && len > 8) || (fd && fd_dlc_to_len(fd_len_to_dlc(len)) != len) {
+            return Err(invalid("payload length out of range"));
+        }
+
+        let timestamp = replay_timestamp(&mut self.start, time)
+            .ok_or_else(|| invalid("timestamp out of range"))?;
+        if fd {
+            let mut frame = FdCanFrame::new(timestamp, id.as_raw());
+            frame.dlc = fd_len_to_dlc(len);
+            frame.payload.extend(data.iter().copied());
+            Ok(Some(AnyCanFrame::Fd(frame)))
+        } else {
+            let mut payload = ArrayVec::new();
+            payload.extend(data.iter().copied());
+            Ok(Some(AnyCanFrame::Classic(CanFrame {
+                timestamp,
+                id,
+                payload,
+            })))
+        }
+    }
+}
+
+impl<R, C> Iterator for PcapReader<R, C>
+where
+    R: Read,
+    C: embedded_time::Clock,
+    C::T: TryFrom<u64>,
+{
+    type Item = io::Result<AnyCanFrame<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (time, packet) = match self.read_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            match self.decode(time, &packet) {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Check a pcapng block's total length, which is at least `min` bytes and a
+/// multiple of 4.
+fn block_length(len: u32, min: usize) -> io::Result<usize> {
+    let len = len as usize;
+    if len < min || len % 4 != 0 {
+        return Err(invalid("invalid block length"));
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::testing::{message_transfer, TestNode, TestSessionManager};
+    use crate::time::TestClock;
+    use crate::{Subscription, TransferKind};
+
+    /// pcapng block in big-endian byte order, with `body` padded to 4 bytes.
+    fn big_endian_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padding = (4 - body.len() % 4) % 4;
+        let len = (12 + body.len() + padding) as u32;
+        let mut block = Vec::new();
+        block.extend(block_type.to_be_bytes());
+        block.extend(len.to_be_bytes());
+        block.extend(body);
+        block.extend(&[0; 3][..padding]);
+        block.extend(len.to_be_bytes());
+        block
+    }
+
+    #[test]
+    fn capture_format() {
+        let mut clock = TestClock::default();
+        clock.add_duration(&Milliseconds(2500u32)).unwrap();
+        let mut frame = FdCanFrame::<TestClock>::new(clock.try_now().unwrap(), 0x1060642A);
+        frame.payload.extend([1, 2, 3]);
+
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer.write_fd_frame(&frame).unwrap();
+        let capture = writer.into_inner().unwrap();
+
+        assert_eq!(capture.len(), 24 + 16 + CANFD_MTU);
+        assert_eq!(capture[0..4], [0xD4, 0xC3, 0xB2, 0xA1]);
+        assert_eq!(capture[20..24], [227, 0, 0, 0]);
+        // 2.5s
+        assert_eq!(capture[24..32], [2, 0, 0, 0, 0x20, 0xA1, 0x07, 0]);
+        assert_eq!(capture[40..46], [0x90, 0x60, 0x64, 0x2A, 3, CANFD_FDF]);
+        assert_eq!(capture[48..51], [1, 2, 3]);
+    }
+
+    #[test]
+    fn pcapng_format() {
+        let mut clock = TestClock::default();
+        clock.add_duration(&Milliseconds(2500u32)).unwrap();
+        let mut frame = FdCanFrame::<TestClock>::new(clock.try_now().unwrap(), 0x1060642A);
+        frame.payload.extend([1, 2, 3]);
+
+        let mut writer = PcapWriter::pcapng(Vec::new()).unwrap();
+        writer.write_fd_frame(&frame).unwrap();
+        let capture = writer.into_inner().unwrap();
+
+        assert_eq!(capture.len(), 28 + 20 + 32 + CANFD_MTU);
+        // Section header
+        assert_eq!(
+            capture[0..12],
+            [0x0A, 0x0D, 0x0D, 0x0A, 28, 0, 0, 0, 0x4D, 0x3C, 0x2B, 0x1A]
+        );
+        // Interface description
+        assert_eq!(capture[28..40], [1, 0, 0, 0, 20, 0, 0, 0, 227, 0, 0, 0]);
+        // Enhanced packet, 2.5s in µs on interface 0
+        let packet = &capture[48..];
+        assert_eq!(packet[0..8], [6, 0, 0, 0, 104, 0, 0, 0]);
+        assert_eq!(packet[8..20], [0, 0, 0, 0, 0, 0, 0, 0, 0xA0, 0x25, 0x26, 0]);
+        assert_eq!(packet[28..34], [0x90, 0x60, 0x64, 0x2A, 3, CANFD_FDF]);
+        assert_eq!(packet[100..104], [104, 0, 0, 0]);
+    }
+
+    #[test]
+    fn replay_into_node() {
+        for pcapng in [false, true] {
+            replay_into_node_with(pcapng);
+        }
+    }
+
+    fn replay_into_node_with(pcapng: bool) {
+        let mut clock = TestClock::default();
+        let publisher = TestNode::new(Some(41), TestSessionManager::new());
+        let mut subscriber = TestNode::new(Some(42), TestSessionManager::new());
+        subscriber
+            .sessions
+            .subscribe(Subscription::new(
+                TransferKind::Message,
+                100,
+                16,
+                Milliseconds(500),
+            ))
+            .unwrap();
+
+        let payload: Vec<u8> = (0..12).collect();
+        let mut writer = if pcapng {
+            PcapWriter::pcapng(Vec::new()).unwrap()
+        } else {
+            PcapWriter::new(Vec::new()).unwrap()
+        };
+        for transfer_id in 0..2 {
+            clock.add_duration(&Milliseconds(100u32)).unwrap();
+            let transfer = message_transfer(clock.try_now().unwrap(), 100, transfer_id, &payload);
+            let mut frames = publisher.transmit(&transfer).unwrap();
+            while let Some(frame) = frames.next() {
+                writer.write_frame(frame).unwrap();
+            }
+        }
+        let capture = writer.into_inner().unwrap();
+
+        let mut received = Vec::new();
+        for frame in PcapReader::<_, TestClock>::new(&capture[..]).unwrap() {
+            let frame = match frame.unwrap() {
+                AnyCanFrame::Classic(frame) => frame,
+                AnyCanFrame::Fd(_) => panic!("unexpected FD frame"),
+            };
+            if let Some(transfer) = subscriber.try_receive_frame(frame).unwrap() {
+                assert_eq!(transfer.payload, &payload[..]);
+                received.push(
+                    Milliseconds::<u32>::try_from(
+                        transfer.metadata.timestamp.duration_since_epoch(),
+                    )
+                    .unwrap(),
+                );
+            }
+        }
+        // Relative to the first frame in the capture
+        assert_eq!(received, [Milliseconds(0u32), Milliseconds(100u32)]);
+    }
+
+    #[test]
+    fn fd_round_trip() {
+        let clock = TestClock::default();
+        let mut frame = FdCanFrame::<TestClock>::new(clock.try_now().unwrap(), 0x1060642A);
+        frame.payload.extend(0..10);
+
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer.write_fd_frame(&frame).unwrap();
+        let capture = writer.into_inner().unwrap();
+
+        let mut reader = PcapReader::<_, TestClock>::new(&capture[..]).unwrap();
+        let decoded = match reader.next().unwrap().unwrap() {
+            AnyCanFrame::Fd(frame) => frame,
+            AnyCanFrame::Classic(_) => panic!("expected FD frame"),
+        };
+        assert_eq!(decoded.id, frame.id);
+        assert_eq!(decoded.dlc, 9);
+        assert_eq!(&decoded.payload[..10], &frame.payload[..]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn foreign_captures() {
+        // Big-endian, nanosecond capture holding a standard ID frame and an extended one
+        let mut capture = Vec::new();
+        capture.extend(MAGIC_NANOS.to_be_bytes());
+        capture.extend([
+            0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 227,
+        ]);
+        for can_id in [0x123, 0x1060642A | CAN_EFF_FLAG] {
+            capture.extend([0, 0, 0, 1, 0, 0, 0x03, 0xE8, 0, 0, 0, 16, 0, 0, 0, 16]);
+            capture.extend(can_id.to_be_bytes());
+            capture.extend([1, 0, 0, 0, 0xE0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+
+        let frames: Vec<_> = PcapReader::<_, TestClock>::new(&capture[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 1);
+        match &frames[0] {
+            AnyCanFrame::Classic(frame) => {
+                assert_eq!(frame.id.as_raw(), 0x1060642A);
+                assert_eq!(&frame.payload[..], [0xE0]);
+            }
+            AnyCanFrame::Fd(_) => panic!("expected classic frame"),
+        }
+
+        // Ethernet capture
+        capture[20..24].copy_from_slice(&1u32.to_be_bytes());
+        assert!(PcapReader::<_, TestClock>::new(&capture[..]).is_err());
+    }
+
+    #[test]
+    fn foreign_pcapng() {
+        // Big-endian section with an Ethernet interface and a nanosecond SocketCAN one
+        let mut capture = big_endian_block(
+            BLOCK_SECTION_HEADER,
+            &[
+                0x1A, 0x2B, 0x3C, 0x4D, 0, 1, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            ],
+        );
+        capture.extend(big_endian_block(1, &[0, 1, 0, 0, 0, 0, 0x05, 0xDC]));
+        capture.extend(big_endian_block(
+            1,
+            &[
+                0, 227, 0, 0, 0, 0, 0, 72, 0, 9, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0,
+            ],
+        ));
+        // Name resolution, which isn't needed
+        capture.extend(big_endian_block(4, &[0, 0, 0, 0]));
+
+        let packet = |interface: u32, nanos: u64, data: &[u8]| {
+            let mut body = Vec::new();
+            body.extend(interface.to_be_bytes());
+            body.extend(((nanos >> 32) as u32).to_be_bytes());
+            body.extend((nanos as u32).to_be_bytes());
+            body.extend((data.len() as u32).to_be_bytes());
+            body.extend((data.len() as u32).to_be_bytes());
+            body.extend(data);
+            big_endian_block(BLOCK_ENHANCED_PACKET, &body)
+        };
+        let mut frame = Vec::new();
+        frame.extend((0x1060642A | CAN_EFF_FLAG).to_be_bytes());
+        frame.extend([1, 0, 0, 0, 0xE0, 0, 0, 0, 0, 0, 0, 0]);
+        capture.extend(packet(0, 1_000_000_000, &[0xAA; 100]));
+        capture.extend(packet(1, 5_000_000_000, &frame));
+        capture.extend(packet(1, 5_002_000_000, &frame));
+
+        let frames: Vec<_> = PcapReader::<_, TestClock>::new(&capture[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        let times: Vec<_> = frames
+            .iter()
+            .map(|frame| match frame {
+                AnyCanFrame::Classic(frame) => {
+                    assert_eq!(frame.id.as_raw(), 0x1060642A);
+                    assert_eq!(&frame.payload[..], [0xE0]);
+                    Milliseconds::<u32>::try_from(frame.timestamp.duration_since_epoch()).unwrap()
+                }
+                AnyCanFrame::Fd(_) => panic!("expected classic frame"),
+            })
+            .collect();
+        assert_eq!(times, [Milliseconds(0u32), Milliseconds(2u32)]);
+
+        // A packet of an interface that was never described
+        capture.extend(packet(2, 0, &frame));
+        let mut reader = PcapReader::<_, TestClock>::new(&capture[..]).unwrap();
+        assert!(reader.nth(2).unwrap().is_err());
+    }
+}
@@ -0,0 +1,529 @@
+//! SLCAN (serial-line CAN, a.k.a. LAWICEL) support, for USB-serial CAN adapters.
+//!
+//! Frames are sent as ASCII lines terminated by `\r`: `T` (extended) or `D`
+//! (extended FD) followed by the 8 hex digit ID, a hex DLC digit, and the data in
+//! hex. The codec (`decode` and `encode`/`encode_fd`) has no dependencies on the
+//! byte stream, and `Slcan` drives an adapter over any `std::io` stream, e.g. a
+//! serial port, pseudo-terminal, or in-memory pipe.
+//!
+//! Standard ID (`t`/`d`) and remote frames aren't used by Cyphal, and are ignored.
+
+use embedded_hal::can::ExtendedId;
+
+use super::AnyCanFrame;
+use crate::time::Timestamp;
+use crate::transport::can::{fd_dlc_to_len, fd_len_to_dlc, CanFrame, FdCanFrame};
+
+/// Longest line `encode` produces: command, ID, DLC, 8 data bytes and `\r`.
+pub const MAX_LINE: usize = 1 + 8 + 1 + 2 * 8 + 1;
+/// Longest line `encode_fd` produces: command, ID, DLC, 64 data bytes and `\r`.
+pub const MAX_FD_LINE: usize = 1 + 8 + 1 + 2 * 64 + 1;
+
+/// Errors decoding an SLCAN line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlcanError {
+    /// Line is shorter or longer than its DLC says.
+    InvalidLength,
+    /// A character that should be a hex digit isn't.
+    InvalidHex,
+    /// DLC is out of range for the frame type.
+    InvalidDlc,
+    /// ID doesn't fit in 29 bits.
+    InvalidId,
+}
+
+/// Decode a single line, without its `\r` terminator, received at `timestamp`.
+///
+/// Lines that aren't extended data frames (acknowledgements, standard ID or remote
+/// frames) decode to `None`. Trailing adapter timestamps (4 hex digits) are ignored.
+pub fn decode<C: embedded_time::Clock>(
+    line: &[u8],
+    timestamp: Timestamp<C>,
+) -> Result<Option<AnyCanFrame<C>>, SlcanError> {
+    let fd = match line.first() {
+        Some(b'T') => false,
+        Some(b'D') | Some(b'B') => true,
+        _ => return Ok(None),
+    };
+    if line.len() < 10 {
+        return Err(SlcanError::InvalidLength);
+    }
+
+    let mut id = 0u32;
+    for &digit in &line[1..9] {
+        id = id << 4 | hex_value(digit)? as u32;
+    }
+    let id = ExtendedId::new(id).ok_or(SlcanError::InvalidId)?;
+
+    let dlc = hex_value(line[9])?;
+    let len = match (fd, dlc) {
+        (false, 0..=8) => dlc as usize,
+        (false, _) => return Err(SlcanError::InvalidDlc),
+        (true, _) => fd_dlc_to_len(dlc),
+    };
+
+    let data = &line[10..];
+    if data.len() != 2 * len && data.len() != 2 * len + 4 {
+        return Err(SlcanError::InvalidLength);
+    }
+    let mut bytes = [0u8; 64];
+    for (byte, pair) in bytes.iter_mut().zip(data[..2 * len].chunks(2)) {
+        *byte = hex_value(pair[0])? << 4 | hex_value(pair[1])?;
+    }
+
+    if fd {
+        let mut frame = FdCanFrame::new(timestamp, id.as_raw());
+        frame.dlc = dlc;
+        frame.payload.extend(bytes[..len].iter().copied());
+        Ok(Some(AnyCanFrame::Fd(frame)))
+    } else {
+        let mut frame = CanFrame {
+            timestamp,
+            id,
+            payload: arrayvec::ArrayVec::new(),
+        };
+        frame.payload.extend(bytes[..len].iter().copied());
+        Ok(Some(AnyCanFrame::Classic(frame)))
+    }
+}
+
+/// Encode a classic frame as a `T` line, including the `\r` terminator.
+pub fn encode<C: embedded_time::Clock>(frame: &CanFrame<C>) -> heapless::Vec<u8, MAX_LINE> {
+    let mut line = heapless::Vec::new();
+    encode_into(
+        &mut line,
+        b'T',
+        frame.id,
+        frame.payload.len() as u8,
+        &frame.payload,
+    );
+    line
+}
+
+/// Encode an FD frame as a `D` line, including the `\r` terminator.
+///
+/// The payload is zero-padded up to the next valid FD length.
+pub fn encode_fd<C: embedded_time::Clock>(frame: &FdCanFrame<C>) -> heapless::Vec<u8, MAX_FD_LINE> {
+    let mut line = heapless::Vec::new();
+    let dlc = fd_len_to_dlc(frame.payload.len());
+    let mut padded = [0u8; 64];
+    padded[..frame.payload.len()].copy_from_slice(&frame.payload);
+    encode_into(
+        &mut line,
+        b'D',
+        frame.id,
+        dlc,
+        &padded[..fd_dlc_to_len(dlc)],
+    );
+    line
+}
+
+fn encode_into<const N: usize>(
+    line: &mut heapless::Vec<u8, N>,
+    command: u8,
+    id: ExtendedId,
+    dlc: u8,
+    data: &[u8],
+) {
+    // Callers size N for the longest line they can produce
+    let _ = line.push(command);
+    for shift in (0..8).rev() {
+        let _ = line.push(hex_digit((id.as_raw() >> (shift * 4)) as u8));
+    }
+    let _ = line.push(hex_digit(dlc));
+    for byte in data {
+        let _ = line.push(hex_digit(byte >> 4));
+        let _ = line.push(hex_digit(*byte));
+    }
+    let _ = line.push(b'\r');
+}
+
+fn hex_value(digit: u8) -> Result<u8, SlcanError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(SlcanError::InvalidHex),
+    }
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789ABCDEF"[(value & 0xF) as usize]
+}
+
+#[cfg(feature = "std")]
+pub use adapter::{Bitrate, Slcan};
+
+#[cfg(feature = "std")]
+mod adapter {
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::media::CanMedia;
+
+    /// Longest line an adapter sends: `MAX_FD_LINE` with a 4 digit timestamp.
+    const MAX_RX_LINE: usize = MAX_FD_LINE + 4;
+
+    /// Standard SLCAN bitrates, set with the `S` command.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Bitrate {
+        Kbps10,
+        Kbps20,
+        Kbps50,
+        Kbps100,
+        Kbps125,
+        Kbps250,
+        Kbps500,
+        Kbps800,
+        Mbps1,
+    }
+
+    /// SLCAN adapter on a byte stream, timestamping received frames with `clock`.
+    ///
+    /// Reads from the stream may block or time out, whatever the stream does;
+    /// `receive` returns `None` if no complete line is available afterwards.
+    ///
+    /// A line longer than any frame is an `InvalidData` error, and the rest of it is
+    /// skipped up to the next terminator.
+    pub struct Slcan<S, C> {
+        stream: S,
+        clock: C,
+        rx: VecDeque<u8>,
+        skip_line: bool,
+    }
+
+    impl<S, C> Slcan<S, C>
+    where
+        S: Read + Write,
+        C: embedded_time::Clock,
+    {
+        /// Wrap a stream to an adapter that has already been opened.
+        pub fn new(stream: S, clock: C) -> Self {
+            Self {
+                stream,
+                clock,
+                rx: VecDeque::new(),
+                skip_line: false,
+            }
+        }
+
+        /// (Re)open the channel at `bitrate`.
+        pub fn open(&mut self, bitrate: Bitrate) -> io::Result<()> {
+            self.stream.write_all(b"C\r")?;
+            self.stream
+                .write_all(&[b'S', b'0' + bitrate as u8, b'\r'])?;
+            self.stream.write_all(b"O\r")?;
+            self.stream.flush()
+        }
+
+        /// Close the channel.
+        pub fn close(&mut self) -> io::Result<()> {
+            self.stream.write_all(b"C\r")?;
+            self.stream.flush()
+        }
+
+        /// Access the underlying stream.
+        pub fn stream(&mut self) -> &mut S {
+            &mut self.stream
+        }
+
+        /// Next frame from the adapter, of either kind.
+        pub fn receive_any(&mut self) -> io::Result<Option<AnyCanFrame<C>>> {
+            loop {
+                if let Some(end) = self.rx.iter().position(|&b| b == b'\r' || b == 0x07) {
+                    let line: Vec<u8> = self.rx.drain(..=end).collect();
+                    // BEL is the adapter refusing a command, nothing to decode
+                    if line[end] == 0x07 || self.skip_line {
+                        self.skip_line = false;
+                        continue;
+                    }
+                    let timestamp = self
+                        .clock
+                        .try_now()
+                        .map_err(|_| io::Error::other("clock error"))?;
+                    match decode(&line[..end], timestamp) {
+                        Ok(Some(frame)) => return Ok(Some(frame)),
+                        Ok(None) => continue,
+                        Err(err) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("{:?}", err),
+                            ))
+                        }
+                    }
+                }
+
+                // No terminator in sight, drop the line rather than buffer forever
+                if self.rx.len() > MAX_RX_LINE || (self.skip_line && !self.rx.is_empty()) {
+                    self.rx.clear();
+                    if !self.skip_line {
+                        self.skip_line = true;
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+                    }
+                }
+
+                let mut chunk = [0u8; 64];
+                match self.stream.read(&mut chunk) {
+                    Ok(0) => return Ok(None),
+                    Ok(n) => self.rx.extend(&chunk[..n]),
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        return Ok(None)
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+
+    /// Classic frames only, FD frames are dropped.
+    impl<S, C> CanMedia<CanFrame<C>> for Slcan<S, C>
+    where
+        S: Read + Write,
+        C: embedded_time::Clock,
+    {
+        type Error = io::Error;
+
+        fn receive(&mut self) -> io::Result<Option<CanFrame<C>>> {
+            loop {
+                match self.receive_any()? {
+                    Some(AnyCanFrame::Classic(frame)) => return Ok(Some(frame)),
+                    Some(AnyCanFrame::Fd(_)) => continue,
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        fn transmit(&mut self, frame: &CanFrame<C>) -> io::Result<()> {
+            self.stream.write_all(&encode(frame))?;
+            self.stream.flush()
+        }
+    }
+
+    /// Classic frames are received as FD frames too.
+    impl<S, C> CanMedia<FdCanFrame<C>> for Slcan<S, C>
+    where
+        S: Read + Write,
+        C: embedded_time::Clock,
+    {
+        type Error = io::Error;
+
+        fn receive(&mut self) -> io::Result<Option<FdCanFrame<C>>> {
+            Ok(self.receive_any()?.map(AnyCanFrame::into_fd))
+        }
+
+        fn transmit(&mut self, frame: &FdCanFrame<C>) -> io::Result<()> {
+            self.stream.write_all(&encode_fd(frame))?;
+            self.stream.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embedded_time::Clock;
+
+    use super::*;
+    use crate::time::TestClock;
+
+    fn decode_line(line: &[u8]) -> Result<Option<AnyCanFrame<TestClock>>, SlcanError> {
+        decode(line, TestClock::default().try_now().unwrap())
+    }
+
+    #[test]
+    fn decode_classic() {
+        let frame = match decode_line(b"T1067D52A3DEADBE").unwrap() {
+            Some(AnyCanFrame::Classic(frame)) => frame,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(frame.id.as_raw(), 0x1067D52A);
+        assert_eq!(&frame.payload[..], [0xDE, 0xAD, 0xBE]);
+
+        // Adapter timestamp suffix
+        assert!(decode_line(b"T1067D52A3DEADBE1234").unwrap().is_some());
+    }
+
+    #[test]
+    fn decode_ignored_and_invalid() {
+        // Standard ID, remote frame, transmit acknowledgement
+        assert!(decode_line(b"t1232AABB").unwrap().is_none());
+        assert!(decode_line(b"R1067D52A0").unwrap().is_none());
+        assert!(decode_line(b"Z").unwrap().is_none());
+
+        assert_eq!(
+            decode_line(b"T1067D52A3DEAD").unwrap_err(),
+            SlcanError::InvalidLength
+        );
+        assert_eq!(
+            decode_line(b"T1067D52A9").unwrap_err(),
+            SlcanError::InvalidDlc
+        );
+        assert_eq!(
+            decode_line(b"T1067D52A1XX").unwrap_err(),
+            SlcanError::InvalidHex
+        );
+        assert_eq!(
+            decode_line(b"T3067D52A0").unwrap_err(),
+            SlcanError::InvalidId
+        );
+    }
+
+    #[test]
+    fn classic_round_trip() {
+        let line = b"T1067D52A801020304050607FF\r";
+        let frame = match decode_line(&line[..line.len() - 1]).unwrap() {
+            Some(AnyCanFrame::Classic(frame)) => frame,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(&encode(&frame)[..], &line[..]);
+    }
+
+    #[test]
+    fn fd_round_trip() {
+        let clock = TestClock::default();
+        let mut frame = FdCanFrame::<TestClock>::new(clock.try_now().unwrap(), 0x1067D52A);
+        frame.payload.extend(0..10);
+
+        // Padded to 12 bytes, DLC 9
+        let line = encode_fd(&frame);
+        assert_eq!(line.len(), 1 + 8 + 1 + 24 + 1);
+        assert_eq!(&line[..10], b"D1067D52A9");
+
+        let decoded = match decode_line(&line[..line.len() - 1]).unwrap() {
+            Some(AnyCanFrame::Fd(frame)) => frame,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(decoded.id, frame.id);
+        assert_eq!(decoded.dlc, 9);
+        assert_eq!(&decoded.payload[..10], &frame.payload[..]);
+        assert_eq!(&decoded.payload[10..], [0, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    mod adapter {
+        use std::io::{self, Read, Write};
+        use std::vec::Vec;
+
+        use embedded_time::duration::Milliseconds;
+
+        use super::*;
+        use crate::media::CanMedia;
+        use crate::session::SessionManager;
+        use crate::testing::{message_transfer, TestNode, TestSessionManager};
+        use crate::{Subscription, TransferKind};
+
+        /// In-memory stand-in for a serial port.
+        #[derive(Default)]
+        struct Pipe {
+            rx: io::Cursor<Vec<u8>>,
+            tx: Vec<u8>,
+        }
+
+        impl Read for Pipe {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.rx.read(buf)
+            }
+        }
+
+        impl Write for Pipe {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.tx.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn open_sends_setup() {
+            let mut slcan = Slcan::new(Pipe::default(), TestClock::default());
+            slcan.open(Bitrate::Kbps500).unwrap();
+            assert_eq!(slcan.stream().tx, b"C\rS6\rO\r");
+        }
+
+        #[test]
+        fn node_over_slcan() {
+            let clock = TestClock::default();
+            let mut publisher = TestNode::new(Some(41), TestSessionManager::new());
+            let mut subscriber = TestNode::new(Some(42), TestSessionManager::new());
+            subscriber
+                .sessions
+                .subscribe(Subscription::new(
+                    TransferKind::Message,
+                    100,
+                    16,
+                    Milliseconds(500),
+                ))
+                .unwrap();
+
+            let mut sender = Slcan::new(Pipe::default(), clock.clone());
+            let payload = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+            let transfer = message_transfer(clock.try_now().unwrap(), 100, 0, &payload);
+            publisher.transmit_to(&transfer, &mut sender).unwrap();
+
+            // Adapter acknowledgements and errors are mixed in with the frames
+            let mut wire = b"z\r\x07".to_vec();
+            wire.extend(&sender.stream().tx);
+            let mut receiver = Slcan::new(
+                Pipe {
+                    rx: io::Cursor::new(wire),
+                    tx: Vec::new(),
+                },
+                clock,
+            );
+
+            let mut received = None;
+            for _ in 0..2 {
+                if let Some(transfer) = subscriber.receive_from(&mut receiver).unwrap() {
+                    received = Some(transfer.payload.to_vec());
+                }
+            }
+            assert_eq!(received.as_deref(), Some(&payload[..]));
+            assert!(subscriber.receive_from(&mut receiver).unwrap().is_none());
+        }
+
+        #[test]
+        fn overlong_line_skipped() {
+            let clock = TestClock::default();
+            let mut frame = FdCanFrame::new(clock.try_now().unwrap(), 0x1067D52A);
+            frame.payload.extend(0..64);
+            let line = encode_fd(&frame);
+
+            // The longest line, with an adapter timestamp, is still taken
+            let mut wire = line[..line.len() - 1].to_vec();
+            wire.extend(b"1234\r");
+            // Then garbage with no terminator until well past any frame
+            wire.extend(core::iter::repeat(b'D').take(3 * MAX_FD_LINE));
+
+            let mut slcan = Slcan::new(
+                Pipe {
+                    rx: io::Cursor::new(wire),
+                    tx: Vec::new(),
+                },
+                clock,
+            );
+            let received: FdCanFrame<_> = CanMedia::receive(&mut slcan).unwrap().unwrap();
+            assert_eq!(received.payload, frame.payload);
+
+            let err = CanMedia::<FdCanFrame<_>>::receive(&mut slcan).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            // The rest of the long line is skipped, and the next frame comes through
+            let mut wire = b"DDDD\r".to_vec();
+            wire.extend(&line);
+            slcan.stream().rx = io::Cursor::new(wire);
+            let received: FdCanFrame<_> = CanMedia::receive(&mut slcan).unwrap().unwrap();
+            assert_eq!(received.payload, frame.payload);
+            assert!(CanMedia::<FdCanFrame<_>>::receive(&mut slcan)
+                .unwrap()
+                .is_none());
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! SocketCAN media, for Linux CAN interfaces (including `vcan`).
+//!
+//! Frames are timestamped by the kernel on reception, which is far more accurate
+//! than reading a clock once the frame gets to userspace. Kernel timestamps are
+//! wall-clock time, so they are converted to the node's clock by how long ago the
+//! frame arrived.
+//!
+//! `SocketCan` carries classic frames. `SocketCanFd` opens the interface in FD mode
+//! and exchanges `FdCanFrame`s, classic frames included. There is no CAN FD
+//! transport yet, so those frames can be recorded or forwarded, but not received
+//! into transfers by a `Node`.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, SystemTime};
+
+use arrayvec::ArrayVec;
+use embedded_hal::can::ExtendedId;
+use embedded_time::duration::Microseconds;
+use socketcan::{
+    CanAnyFrame, CanDataFrame, CanFdFrame, CanFdSocket, CanSocket, EmbeddedFrame, Id, Socket,
+};
+
+use super::CanMedia;
+use crate::time::Timestamp;
+use crate::transport::can::{fd_len_to_dlc, CanFrame, FdCanFrame};
+
+// From linux/sockios.h, not exported by libc
+const SIOCGSTAMP: libc::c_ulong = 0x8906;
+
+/// Classic CAN interface.
+pub struct SocketCan<C: embedded_time::Clock> {
+    socket: CanSocket,
+    clock: C,
+}
+
+impl<C> SocketCan<C>
+where
+    C: embedded_time::Clock,
+    C::T: From<u32>,
+{
+    /// Open the interface (e.g. `"can0"`), timestamping frames with `clock`.
+    ///
+    /// Reads block until a frame arrives, see `set_read_timeout`.
+    pub fn open(interface: &str, clock: C) -> io::Result<Self> {
+        Ok(Self {
+            socket: CanSocket::open(interface)?,
+            clock,
+        })
+    }
+
+    /// Give up waiting for a frame after `timeout`, with `receive` returning `None`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+}
+
+impl<C> CanMedia<CanFrame<C>> for SocketCan<C>
+where
+    C: embedded_time::Clock,
+    C::T: From<u32>,
+{
+    type Error = io::Error;
+
+    fn receive(&mut self) -> io::Result<Option<CanFrame<C>>> {
+        let frame = match self.socket.read_frame() {
+            Ok(socketcan::CanFrame::Data(frame)) => frame,
+            // Remote and error frames aren't part of Cyphal
+            Ok(_) => return Ok(None),
+            Err(err) if is_timeout(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let id = match extended_id(&frame) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let mut payload = ArrayVec::new();
+        payload
+            .try_extend_from_slice(frame.data())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        Ok(Some(CanFrame {
+            timestamp: rx_timestamp(&self.socket, &self.clock)?,
+            id,
+            payload,
+        }))
+    }
+
+    fn transmit(&mut self, frame: &CanFrame<C>) -> io::Result<()> {
+        let id = socketcan::ExtendedId::new(frame.id.as_raw()).unwrap();
+        let frame = CanDataFrame::new(id, &frame.payload)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.socket.write_frame(&frame)
+    }
+}
+
+/// CAN FD interface.
+pub struct SocketCanFd<C: embedded_time::Clock> {
+    socket: CanFdSocket,
+    clock: C,
+}
+
+impl<C> SocketCanFd<C>
+where
+    C: embedded_time::Clock,
+    C::T: From<u32>,
+{
+    /// Open the interface (e.g. `"can0"`) in FD mode, timestamping frames with `clock`.
+    ///
+    /// Reads block until a frame arrives, see `set_read_timeout`.
+    pub fn open(interface: &str, clock: C) -> io::Result<Self> {
+        Ok(Self {
+            socket: CanFdSocket::open(interface)?,
+            clock,
+        })
+    }
+
+    /// Give up waiting for a frame after `timeout`, with `receive` returning `None`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+}
+
+impl<C> CanMedia<FdCanFrame<C>> for SocketCanFd<C>
+where
+    C: embedded_time::Clock,
+    C::T: From<u32>,
+{
+    type Error = io::Error;
+
+    fn receive(&mut self) -> io::Result<Option<FdCanFrame<C>>> {
+        let (id, data) = match self.socket.read_frame() {
+            Ok(CanAnyFrame::Normal(frame)) => (extended_id(&frame), frame.data().to_vec()),
+            Ok(CanAnyFrame::Fd(frame)) => (extended_id(&frame), frame.data().to_vec()),
+            Ok(_) => return Ok(None),
+            Err(err) if is_timeout(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let mut frame = FdCanFrame::new(rx_timestamp(&self.socket, &self.clock)?, id.as_raw());
+        frame
+            .payload
+            .try_extend_from_slice(&data)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        frame.dlc = fd_len_to_dlc(data.len());
+        Ok(Some(frame))
+    }
+
+    fn transmit(&mut self, frame: &FdCanFrame<C>) -> io::Result<()> {
+        let id = socketcan::ExtendedId::new(frame.id.as_raw()).unwrap();
+        let frame = CanFdFrame::new(id, &frame.payload)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.socket.write_frame(&frame)
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Cyphal only uses extended IDs, anything else is ignored.
+fn extended_id<F: EmbeddedFrame>(frame: &F) -> Option<ExtendedId> {
+    match frame.id() {
+        Id::Extended(id) => ExtendedId::new(id.as_raw()),
+        Id::Standard(_) => None,
+    }
+}
+
+/// Kernel receive timestamp of the last frame read from `socket`, on `clock`.
+fn rx_timestamp<S, C>(socket: &S, clock: &C) -> io::Result<Timestamp<C>>
+where
+    S: AsRawFd,
+    C: embedded_time::Clock,
+    C::T: From<u32>,
+{
+    let mut tv = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    // SAFETY: SIOCGSTAMP only writes a timeval into the pointer we pass
+    let ret = unsafe { libc::ioctl(socket.as_raw_fd(), SIOCGSTAMP as _, &mut tv) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let received =
+        SystemTime::UNIX_EPOCH + Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+
+    // Clocks may be stepped, in which case the frame is treated as having just arrived
+    let age = SystemTime::now()
+        .duration_since(received)
+        .unwrap_or_default();
+    let age = Microseconds(u32::try_from(age.as_micros()).unwrap_or(u32::MAX));
+
+    let now = clock
+        .try_now()
+        .map_err(|_| io::Error::other("clock error"))?;
+    Ok(now.checked_sub(age).unwrap_or(now))
+}
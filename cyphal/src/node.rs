@@ -10,11 +10,33 @@ use core::marker::PhantomData;
 
 use core::clone::Clone;
 
-use crate::session::SessionManager;
-use crate::transfer::{Transfer, RefTransfer};
-use crate::transport::Transport;
+use alloc::collections::BTreeSet;
+#[cfg(feature = "statistics")]
+use alloc::{string::String, vec::Vec};
+use embedded_time::duration::Milliseconds;
+
+use crate::handler::Handlers;
+use crate::internal::InternalRxFrame;
+use crate::media::{CanMedia, MediaError};
+use crate::serialization::{self, Buffer, Deserialize, Serialize};
+use crate::session::{
+    period_elapsed, SessionError, SessionManager, SubscriptionError,
+};
+#[cfg(target_has_atomic = "ptr")]
+use crate::session::LendingSessionManager;
+#[cfg(feature = "statistics")]
+use crate::statistics::{self, Statistics};
+use crate::time::{Duration, Timestamp};
+#[cfg(target_has_atomic = "ptr")]
+use crate::transfer::TransferLease;
+use crate::transfer::{RefTransfer, Transfer, TransferMetadata};
+use crate::transport::{Transport, TxQueue};
 use crate::types::*;
-use crate::{RxError, TxError};
+use crate::uavcan::node::port::{List, SubjectIdList, LIST_SUBJECT};
+use crate::{
+    DispatchError, Priority, RxError, RxErrorKind, StreamingIterator, Subscription, TransferKind,
+    TxError,
+};
 
 /// Node implementation. Generic across session managers and transport types.
 #[derive(Debug)]
@@ -27,6 +49,18 @@ pub struct Node<S: SessionManager<C>, T: Transport<C>, C: embedded_time::Clock>
     /// which took a closure. I can't decide which API is better.
     pub sessions: S,
 
+    /// Subjects this node publishes on, for the port list.
+    publishers: SubjectSet,
+    /// Port list as last published, or `None` if it never was.
+    port_list: Option<List>,
+    /// Whether the node's ports may have changed since the list was built.
+    port_list_changed: bool,
+    port_list_published: Option<Timestamp<C>>,
+    port_list_transfer_id: TransferId,
+
+    #[cfg(feature = "statistics")]
+    statistics: Statistics,
+
     /// Transport type
     transport: PhantomData<T>,
     _clock: PhantomData<C>,
@@ -42,6 +76,13 @@ where
         Self {
             id,
             sessions: session_manager,
+            publishers: SubjectSet::new(),
+            port_list: None,
+            port_list_changed: true,
+            port_list_published: None,
+            port_list_transfer_id: 0,
+            #[cfg(feature = "statistics")]
+            statistics: Statistics::default(),
             transport: PhantomData,
             _clock: PhantomData,
         }
@@ -60,17 +101,54 @@ where
 
     /// Attempts to receive frame. Returns error when frame is invalid, Some(Transfer) at the end of
     /// a transfer, and None if we haven't finished the transfer.
-    pub fn try_receive_frame(&mut self, frame: T::Frame) -> Result<Option<RefTransfer<C>>, RxError> {
-        let frame = T::rx_process_frame(&self.id, &frame)?;
+    pub fn try_receive_frame(&mut self, frame: T::Frame) -> Result<Option<RefTransfer<'_, C>>, RxError> {
+        let result = match T::rx_process_frame(&self.id, &frame) {
+            Ok(Some(frame)) => {
+                let error = session_error(&frame);
+                self.sessions.ingest(frame).map_err(error)
+            }
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+
+        #[cfg(feature = "statistics")]
+        self.statistics.rx.count(
+            &result
+                .as_ref()
+                .map(|transfer| transfer.as_ref().map(|transfer| transfer.payload.len()))
+                .map_err(|err| err.kind),
+        );
+        result
+    }
 
-        if let Some(frame) = frame {
-            match self.sessions.ingest(frame) {
-                Ok(frame) => Ok(frame),
-                Err(err) => Err(RxError::SessionError(err)),
+    /// Like `try_receive_frame`, but leases the completed transfer out of the session manager
+    /// instead of borrowing it, so it can be handed to another task while the node keeps
+    /// receiving. The transfer's session rejects new frames until the lease is dropped.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_receive_frame_leased(
+        &mut self,
+        frame: T::Frame,
+    ) -> Result<Option<TransferLease<C>>, RxError>
+    where
+        S: LendingSessionManager<C>,
+    {
+        let result = match T::rx_process_frame(&self.id, &frame) {
+            Ok(Some(frame)) => {
+                let error = session_error(&frame);
+                self.sessions.ingest_leased(frame).map_err(error)
             }
-        } else {
-            Ok(None)
-        }
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+
+        #[cfg(feature = "statistics")]
+        self.statistics.rx.count(
+            &result
+                .as_ref()
+                .map(|transfer| transfer.as_ref().map(|transfer| transfer.payload().len()))
+                .map_err(|err| err.kind),
+        );
+        result
     }
 
     // Create a series of frames to transmit.
@@ -81,7 +159,348 @@ where
     //
     // 1 and 3 provide the user with more options but also make it harder
     // to implement for the user.
+    //
+    // The node doesn't see these frames go out, so they aren't counted in its statistics.
     pub fn transmit<X: Transfer<'a, C>>(&self, transfer: &'a X) -> Result<T::FrameIter<'a>, TxError> {
-        T::transmit(transfer)
+        T::transmit(&self.id, transfer)
+    }
+
+    /// Receive the next frame from `media`, returning the transfer it completes, if any.
+    pub fn receive_from<M: CanMedia<T::Frame>>(
+        &mut self,
+        media: &mut M,
+    ) -> Result<Option<RefTransfer<'_, C>>, MediaError<M::Error>> {
+        match media.receive().map_err(MediaError::Media)? {
+            Some(frame) => self.try_receive_frame(frame).map_err(MediaError::Rx),
+            None => Ok(None),
+        }
+    }
+
+    /// Transmit a transfer, sending every frame through `media`.
+    pub fn transmit_to<'t, X, M>(
+        &mut self,
+        transfer: &'t X,
+        media: &mut M,
+    ) -> Result<(), MediaError<M::Error>>
+    where
+        X: Transfer<'t, C>,
+        M: CanMedia<T::Frame>,
+        C: 't,
+    {
+        let mut frames = T::transmit(&self.id, transfer).map_err(MediaError::Tx)?;
+        let mut count = 0;
+        while let Some(frame) = frames.next() {
+            media.transmit(frame).map_err(MediaError::Media)?;
+            count += 1;
+        }
+
+        self.count_transmitted(count, transfer.payload().len());
+        Ok(())
+    }
+
+    /// Subscribe to a data type, using its extent for the subscription.
+    pub fn subscribe<M: Deserialize>(
+        &mut self,
+        transfer_kind: TransferKind,
+        port_id: PortId,
+        timeout: Duration,
+    ) -> Result<(), SubscriptionError> {
+        self.sessions
+            .subscribe(Subscription::for_type::<M>(transfer_kind, port_id, timeout))?;
+        self.port_list_changed = true;
+        Ok(())
+    }
+
+    /// Remove a subscription.
+    pub fn unsubscribe(
+        &mut self,
+        transfer_kind: TransferKind,
+        port_id: PortId,
+    ) -> Result<(), SubscriptionError> {
+        // Subscriptions are matched on kind and port alone
+        self.sessions
+            .unsubscribe(Subscription::new(transfer_kind, port_id, 0, Milliseconds(0)))?;
+        self.port_list_changed = true;
+        Ok(())
+    }
+
+    /// Everything this node has received and transmitted.
+    ///
+    /// Per subscription counters are kept by the session manager, see
+    /// `SessionManager::subscription_statistics`.
+    #[cfg(feature = "statistics")]
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    #[cfg(feature = "statistics")]
+    pub fn reset_statistics(&mut self) {
+        self.statistics = Statistics::default();
+    }
+
+    /// The node's statistics, followed by each subscription's if the session manager
+    /// keeps them, as natural64 registers: `stat.rx.frames`, `stat.tx.bytes`,
+    /// `stat.message.7509.drop.timeout` and so on.
+    #[cfg(feature = "statistics")]
+    pub fn statistics_registers(&self) -> Vec<(String, u64)> {
+        let mut registers = Vec::new();
+        self.statistics.registers("stat", &mut registers);
+        for subscription in self.sessions.subscriptions() {
+            let counters = self
+                .sessions
+                .subscription_statistics(subscription.transfer_kind(), subscription.port_id());
+            if let Some(counters) = counters {
+                statistics::subscription_registers("stat", subscription, counters, &mut registers);
+            }
+        }
+        registers
+    }
+
+    /// Count a transfer sent, by the node or without going through its transmit functions.
+    pub(crate) fn count_transmitted(&mut self, frames: usize, len: usize) {
+        #[cfg(feature = "statistics")]
+        self.statistics.tx.count(frames, len);
+        #[cfg(not(feature = "statistics"))]
+        let _ = (frames, len);
+    }
+
+    /// Add a subject to the publishers in the port list.
+    ///
+    /// `publish` does this by itself, this is for publishing through `transmit`.
+    pub fn add_publisher(&mut self, subject: PortId) {
+        if self.publishers.insert(subject) {
+            self.port_list_changed = true;
+        }
+    }
+
+    /// Remove a subject from the publishers in the port list.
+    pub fn remove_publisher(&mut self, subject: PortId) {
+        if self.publishers.remove(subject) {
+            self.port_list_changed = true;
+        }
+    }
+
+    /// The ports this node uses, as published in `uavcan.node.port.List`.
+    ///
+    /// Subscribers, clients and servers come from the subscriptions to messages,
+    /// responses and requests, and publishers from the subjects published on.
+    pub fn port_list(&self) -> List {
+        let mut subscribers = BTreeSet::new();
+        let mut list = List::default();
+        for subscription in self.sessions.subscriptions() {
+            let ports = match subscription.transfer_kind() {
+                TransferKind::Message => &mut subscribers,
+                TransferKind::Response => &mut list.clients,
+                TransferKind::Request => &mut list.servers,
+            };
+            ports.insert(subscription.port_id());
+        }
+        list.publishers = SubjectIdList::Ids(self.publishers.iter().collect());
+        list.subscribers = SubjectIdList::Ids(subscribers);
+        list
+    }
+
+    /// Publish the port list if it's due, handing every frame to `send`. Returns
+    /// whether it was published.
+    ///
+    /// The list is due when the node's ports have changed since it was last published,
+    /// and every `List::MAX_PUBLICATION_PERIOD` regardless. Call this regularly, e.g.
+    /// along with the heartbeat. Anonymous nodes don't publish it.
+    ///
+    /// The list is only rebuilt when the ports change through the node, or when the
+    /// period is up. Subscriptions made directly on `sessions` are therefore only
+    /// published with the next periodic list.
+    pub fn publish_port_list<F>(&mut self, now: Timestamp<C>, send: F) -> Result<bool, TxError>
+    where
+        F: FnMut(&T::Frame),
+    {
+        if self.id.is_none() {
+            return Ok(false);
+        }
+        // The list includes itself
+        self.add_publisher(LIST_SUBJECT);
+
+        // A clock whose counter can't hold the period never gets there, so it only
+        // publishes on changes
+        let period = Milliseconds(List::MAX_PUBLICATION_PERIOD);
+        let period_up = self.port_list_published.is_none()
+            || period_elapsed(period, now, self.port_list_published);
+        if !period_up && !self.port_list_changed {
+            return Ok(false);
+        }
+
+        let list = self.port_list();
+        self.port_list_changed = false;
+        if !period_up && self.port_list.as_ref() == Some(&list) {
+            return Ok(false);
+        }
+
+        let transfer_id = self.port_list_transfer_id;
+        self.publish(now, Priority::Optional, LIST_SUBJECT, transfer_id, &list, send)?;
+        let next_transfer_id = (transfer_id as usize + 1) % T::TRANSFER_ID_MODULO;
+        self.port_list_transfer_id = next_transfer_id as TransferId;
+        self.port_list = Some(list);
+        self.port_list_published = Some(now);
+        Ok(true)
+    }
+
+    /// Serialize and publish a message, handing every resulting frame to `send`.
+    ///
+    /// The message is serialized into `M::Buffer` on the stack, so this never allocates.
+    /// Frames are passed out through a closure because they borrow from that buffer.
+    pub fn publish<M, F>(
+        &mut self,
+        timestamp: Timestamp<C>,
+        priority: Priority,
+        subject: PortId,
+        transfer_id: TransferId,
+        message: &M,
+        mut send: F,
+    ) -> Result<(), TxError>
+    where
+        M: Serialize,
+        F: FnMut(&T::Frame),
+    {
+        self.add_publisher(subject);
+
+        let mut buffer = serialization::buffer_for::<M>();
+        let len = message.serialize(buffer.as_mut());
+
+        let transfer = RefTransfer {
+            metadata: TransferMetadata {
+                timestamp,
+                priority,
+                transfer_kind: TransferKind::Message,
+                port_id: subject,
+                remote_node_id: None,
+                transfer_id,
+            },
+            payload: &buffer.as_ref()[..len],
+        };
+
+        let mut frames = T::transmit(&self.id, &transfer)?;
+        let mut count = 0;
+        while let Some(frame) = frames.next() {
+            send(frame);
+            count += 1;
+        }
+
+        self.count_transmitted(count, len);
+        Ok(())
+    }
+
+    /// Receive a frame and route any completed transfer to its handler.
+    ///
+    /// If a request handler produces a response, the response frames are pushed onto
+    /// `tx_queue`, to be sent whenever the application services its driver.
+    pub fn dispatch_frame<H, Q>(
+        &mut self,
+        frame: T::Frame,
+        handlers: &mut H,
+        tx_queue: &mut Q,
+    ) -> Result<(), DispatchError>
+    where
+        H: Handlers<C>,
+        Q: TxQueue<T::Frame>,
+        T::Frame: Clone,
+    {
+        let node_id = self.id;
+        let transfer = match self.try_receive_frame(frame) {
+            Ok(Some(transfer)) => transfer,
+            Ok(None) => return Ok(()),
+            Err(err) => return Err(DispatchError::Rx(err)),
+        };
+
+        let mut buffer = H::ResponseBuffer::zeroed();
+        let len = match handlers.dispatch(&transfer, buffer.as_mut()) {
+            Some(len) if transfer.metadata.transfer_kind == TransferKind::Request => len,
+            _ => return Ok(()),
+        };
+        // The length comes from the application's handler, so it can't be trusted
+        if len > buffer.as_ref().len() {
+            return Err(DispatchError::Tx(TxError::PayloadOutOfBounds));
+        }
+
+        let response = RefTransfer {
+            metadata: TransferMetadata {
+                timestamp: transfer.metadata.timestamp,
+                priority: transfer.metadata.priority,
+                transfer_kind: TransferKind::Response,
+                port_id: transfer.metadata.port_id,
+                remote_node_id: transfer.metadata.remote_node_id,
+                transfer_id: transfer.metadata.transfer_id,
+            },
+            payload: &buffer.as_ref()[..len],
+        };
+
+        let mut frames = T::transmit(&node_id, &response).map_err(DispatchError::Tx)?;
+        let mut count = 0;
+        while let Some(frame) = frames.next() {
+            tx_queue
+                .push(frame.clone())
+                .map_err(|_| DispatchError::QueueFull)?;
+            count += 1;
+        }
+
+        self.count_transmitted(count, len);
+        Ok(())
+    }
+}
+
+/// Turns the session manager's errors about `frame` into errors about its transfer.
+fn session_error<C: embedded_time::Clock>(
+    frame: &InternalRxFrame<C>,
+) -> impl Fn(SessionError) -> RxError {
+    // Copied out, as the session manager takes the frame
+    let (transfer_kind, port_id, source_node_id, transfer_id) = (
+        frame.transfer_kind,
+        frame.port_id,
+        frame.source_node_id,
+        frame.transfer_id,
+    );
+    move |err| RxError {
+        kind: RxErrorKind::Session(err),
+        transfer_kind: Some(transfer_kind),
+        port_id: Some(port_id),
+        source_node_id,
+        transfer_id: Some(transfer_id),
+    }
+}
+
+/// Set of subject IDs, one bit each, so that recording publishers never allocates.
+#[derive(Clone, Debug)]
+struct SubjectSet([u32; SubjectSet::WORDS]);
+
+impl SubjectSet {
+    const WORDS: usize = (MAX_SUBJECT_ID as usize + 1) / 32;
+
+    fn new() -> Self {
+        Self([0; Self::WORDS])
+    }
+
+    /// Add `subject`, returning whether it's new. Invalid subject IDs are ignored.
+    fn insert(&mut self, subject: PortId) -> bool {
+        let new = !self.contains(subject) && subject <= MAX_SUBJECT_ID;
+        if new {
+            self.0[subject as usize / 32] |= 1 << (subject % 32);
+        }
+        new
+    }
+
+    /// Remove `subject`, returning whether it was there.
+    fn remove(&mut self, subject: PortId) -> bool {
+        let present = self.contains(subject);
+        if present {
+            self.0[subject as usize / 32] &= !(1 << (subject % 32));
+        }
+        present
+    }
+
+    fn contains(&self, subject: PortId) -> bool {
+        subject <= MAX_SUBJECT_ID && self.0[subject as usize / 32] & (1 << (subject % 32)) != 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = PortId> + '_ {
+        (0..=MAX_SUBJECT_ID).filter(move |subject| self.contains(*subject))
     }
 }
@@ -0,0 +1,87 @@
+//! Serialization traits connecting DSDL data types to transfers.
+//!
+//! Whatever generates the data types (nunavut, a derive macro, or a human) implements
+//! these, and the `Node` uses them to publish and receive values directly instead of
+//! passing raw byte slices around. The sizes are associated constants so buffers can
+//! live on the stack, which keeps the typed API usable without a heap.
+
+use core::marker::PhantomData;
+
+/// Errors possible when deserializing a received payload.
+#[derive(Copy, Clone, Debug)]
+pub enum DeserializeError {
+    /// A length prefix was larger than the capacity of the target array.
+    InvalidLength,
+    /// A field contained a value the type can't represent (e.g. a bad union tag).
+    InvalidValue,
+}
+
+/// Fixed-size scratch storage for a single serialized value.
+///
+/// This is implemented for byte arrays, so a type only needs to declare
+/// `type Buffer = [u8; N]`.
+pub trait Buffer: AsRef<[u8]> + AsMut<[u8]> {
+    /// Length of the buffer, in bytes.
+    const LEN: usize;
+
+    /// Create a zero-filled buffer.
+    fn zeroed() -> Self;
+}
+
+impl<const N: usize> Buffer for [u8; N] {
+    const LEN: usize = N;
+
+    fn zeroed() -> Self {
+        [0; N]
+    }
+}
+
+/// A data type that can be serialized into a transfer payload.
+pub trait Serialize {
+    /// Largest possible serialized size of the type, in bytes.
+    const MAX_SIZE: usize;
+
+    /// Stack buffer used when transmitting the type. Should be `[u8; MAX_SIZE]`.
+    ///
+    /// This is a separate type because array lengths can't (yet) be derived from
+    /// an associated constant of a generic type. A buffer shorter than `MAX_SIZE` fails
+    /// to compile wherever the type is sent.
+    type Buffer: Buffer;
+
+    /// Serialize into `buffer`, returning the number of bytes written.
+    ///
+    /// `buffer` is always at least `MAX_SIZE` bytes long.
+    fn serialize(&self, buffer: &mut [u8]) -> usize;
+}
+
+/// A data type that can be deserialized from a transfer payload.
+pub trait Deserialize: Sized {
+    /// Extent of the type, i.e. the most data a subscriber ever needs to keep.
+    ///
+    /// This is used as the subscription extent, so anything past it is
+    /// dropped before it ever reaches `deserialize`.
+    const EXTENT: usize;
+
+    /// Deserialize from a received payload.
+    ///
+    /// Per the specification, a payload that is shorter than expected must be
+    /// treated as if it were padded with zeros (implicit zero extension).
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError>;
+}
+
+/// Compile time check that `M::Buffer` holds `M::MAX_SIZE` bytes.
+struct BufferFits<M>(PhantomData<M>);
+
+impl<M: Serialize> BufferFits<M> {
+    const OK: () = assert!(
+        <M::Buffer as Buffer>::LEN >= M::MAX_SIZE,
+        "Serialize::Buffer is shorter than Serialize::MAX_SIZE"
+    );
+}
+
+/// Zero-filled buffer to serialize `M` into, which fails to compile if it's too short.
+pub(crate) fn buffer_for<M: Serialize>() -> M::Buffer {
+    #[allow(clippy::let_unit_value)]
+    let () = BufferFits::<M>::OK;
+    M::Buffer::zeroed()
+}
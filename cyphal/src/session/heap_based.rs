@@ -6,8 +6,11 @@
 use embedded_time::Clock;
 
 use crate::session::*;
+use crate::transport::Transport;
 use crate::types::NodeId;
 use crate::transfer::RefTransfer;
+#[cfg(target_has_atomic = "ptr")]
+use crate::transfer::TransferLease;
 
 use alloc::{collections::BTreeMap, vec::Vec};
 
@@ -20,8 +23,13 @@ where
 {
     // Timestamp of first frame
     pub timestamp: Option<Timestamp<C>>,
-    pub payload: Vec<u8>,
+    // Shared with a TransferLease while the last transfer is leased out
+    pub payload: SessionPayload,
     pub transfer_id: TransferId,
+    // Interface the session is currently receiving from
+    pub iface: usize,
+    // Whether the transfer with transfer_id has been completed
+    pub done: bool,
 
     pub md: T,
 }
@@ -31,18 +39,29 @@ where
     T: crate::transport::SessionMetadata<C>,
     C: Clock,
 {
-    pub fn new(transfer_id: TransferId, known_max_payload_size: Option<usize>) -> Self {
+    pub fn new(
+        transfer_id: TransferId,
+        known_max_payload_size: Option<usize>,
+        iface: usize,
+    ) -> Self {
         Self {
             timestamp: None,
-            payload: Vec::with_capacity(known_max_payload_size.unwrap_or(10)),
+            payload: SessionPayload::with_capacity(known_max_payload_size.unwrap_or(10)),
             transfer_id,
+            iface,
+            done: false,
             md: T::new(),
         }
     }
 
+    pub fn is_leased(&self) -> bool {
+        self.payload.is_leased()
+    }
+
     pub fn reset(&mut self) {
-        self.payload.clear();
+        self.payload.make_mut().clear();
         self.timestamp = None;
+        self.done = false;
         self.md = T::new()
     }
 
@@ -59,7 +78,10 @@ where
     C: Clock,
 {
     sub: crate::Subscription,
-    sessions: BTreeMap<NodeId, Session<T, C>>,
+    #[cfg(feature = "statistics")]
+    statistics: RxStatistics,
+    // Keyed by source node, anonymous transfers under `None`
+    sessions: BTreeMap<Option<NodeId>, Session<T, C>>,
 }
 
 impl<T, C> Subscription<T, C>
@@ -70,29 +92,78 @@ where
     pub fn new(sub: crate::Subscription) -> Self {
         Self {
             sub,
+            #[cfg(feature = "statistics")]
+            statistics: RxStatistics::default(),
             sessions: BTreeMap::new(),
         }
     }
 
-    /// Update subscription with incoming frame
-    fn update<'a>(&'a mut self, frame: InternalRxFrame<C>) -> Result<Option<RefTransfer<'a, C>>, SessionError> {
-        // TODO maybe some of the logic here can be skipped with anon transfers.
-        let session_id = frame.source_node_id.unwrap();
-
+    /// Update subscription with incoming frame, returning the session if the transfer is complete.
+    fn update(&mut self, frame: &InternalRxFrame<C>) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let session_id = frame.source_node_id;
         let extent = self.sub.extent;
+
+        // Anonymous transfers come from nodes that can't be told apart, so each one
+        // starts afresh instead of being checked against the last
+        if session_id.is_none() && frame.start_of_transfer {
+            self.sessions.remove(&session_id);
+        }
+
         let session = self.sessions.get_mut(&session_id);
         match session {
             // error if session not exists and not start of transfer
             None if !frame.start_of_transfer => return Err(SessionError::NewSessionNoStart),
             // create new session if not exists (start of transfer)
             None => {
-                self.sessions
-                    .insert(session_id, Session::new(frame.transfer_id, Some(extent)));
+                self.sessions.insert(
+                    session_id,
+                    Session::new(frame.transfer_id, Some(extent), frame.iface),
+                );
+            }
+            // session can't be touched until the lease is dropped
+            Some(session) if session.is_leased() => return Err(SessionError::Leased),
+            // frames from another interface are redundant copies, unless the session's
+            // interface has been quiet for a full timeout, in which case we switch over.
+            // a session without a timestamp was reset because it timed out already
+            Some(session) if session.iface != frame.iface => {
+                if !frame.start_of_transfer
+                    || (session.timestamp.is_some()
+                        && !timestamp_expired(
+                            self.sub.transfer_id_timeout,
+                            frame.timestamp,
+                            session.timestamp,
+                        ))
+                {
+                    return Ok(None);
+                }
+                session.reset_to_new_transfer_id(frame.transfer_id);
+                session.iface = frame.iface;
             }
-            // session already exists and check for wrong transfer_id
-            Some(session) if session.transfer_id != frame.transfer_id => {
+            // new transfers can't repeat or predate the last one within the transfer-ID timeout
+            Some(session) if frame.start_of_transfer => {
+                if (session.done || session.transfer_id != frame.transfer_id)
+                    && transfer_id_stale(
+                        self.sub.transfer_id_timeout,
+                        <T::Transport as Transport<C>>::TRANSFER_ID_MODULO,
+                        session.transfer_id,
+                        frame.transfer_id,
+                        frame.timestamp,
+                        session.timestamp,
+                    )
+                {
+                    return Err(SessionError::InvalidTransferId);
+                }
                 session.reset_to_new_transfer_id(frame.transfer_id);
             }
+            // continuation frames have to belong to the transfer being reassembled, which
+            // a session that timed out no longer has
+            Some(session)
+                if session.done
+                    || session.transfer_id != frame.transfer_id
+                    || session.timestamp.is_none() =>
+            {
+                return Err(SessionError::NewSessionNoStart);
+            }
             // session already exists and check for timeout
             Some(session)
                 if timestamp_expired(self.sub.timeout, frame.timestamp, session.timestamp) =>
@@ -106,42 +177,57 @@ where
         self.accept_frame(session_id, frame)
     }
 
-    fn accept_frame<'a>(
-        &'a mut self,
-        session: NodeId,
-        frame: InternalRxFrame<C>,
-    ) -> Result<Option<RefTransfer<'a, C>>, SessionError> {
-        let mut session = self.sessions.get_mut(&session).unwrap();
+    /// `update`, counting the frame in the subscription's statistics and `totals`.
+    #[cfg(feature = "statistics")]
+    fn counted_update(
+        &mut self,
+        frame: &InternalRxFrame<C>,
+        totals: &mut RxStatistics,
+    ) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let result = self
+            .update(frame)
+            .map(|session| session.map(|session| session.payload.len()));
+        for statistics in [&mut self.statistics, totals] {
+            statistics.count(&result.map_err(crate::RxErrorKind::Session));
+        }
+
+        match result? {
+            Some(_) => Ok(self.sessions.get_mut(&frame.source_node_id)),
+            None => Ok(None),
+        }
+    }
+
+    fn accept_frame(
+        &mut self,
+        session: Option<NodeId>,
+        frame: &InternalRxFrame<C>,
+    ) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let session = self.sessions.get_mut(&session).unwrap();
 
         if frame.start_of_transfer {
             session.timestamp = Some(frame.timestamp);
         }
 
-        if let Some(len) = session.md.update(&frame) {
+        if let Some(len) = session.md.update(frame) {
             // Truncate payload if subscription extent is less than the incoming data
-            // TODO not working.
-            let payload_to_copy = if session.payload.len() + len > self.sub.extent {
-                session.payload.len() + len - self.sub.extent
-            } else {
-                len
-            };
-            session.payload.extend(&frame.payload[0..payload_to_copy]);
+            let payload_to_copy =
+                core::cmp::min(len, self.sub.extent.saturating_sub(session.payload.len()));
+            session.payload.make_mut().extend(&frame.payload[0..payload_to_copy]);
 
             if frame.end_of_transfer {
-                if session.md.is_valid(&frame) {
-                    Ok(Some(RefTransfer::from_frame(
-                        frame,
-                        session.timestamp.unwrap(),
-                        &session.payload,
-                    )))
+                if session.md.is_valid(frame) {
+                    let len = session.md.payload_len(frame, session.payload.len());
+                    session.payload.make_mut().truncate(len);
+                    session.done = true;
+                    Ok(Some(session))
                 } else {
-                    Err(SessionError::BadMetadata)
+                    Err(SessionError::CrcMismatch)
                 }
             } else {
                 Ok(None)
             }
         } else {
-            Err(SessionError::BadMetadata)
+            Err(SessionError::ToggleMismatch)
         }
     }
 }
@@ -155,6 +241,8 @@ where
     C: Clock,
 {
     subscriptions: Vec<Subscription<T, C>>,
+    #[cfg(feature = "statistics")]
+    statistics: RxStatistics,
 }
 
 impl<T, C> HeapSessionManager<T, C>
@@ -167,6 +255,8 @@ where
     pub fn new() -> Self {
         Self {
             subscriptions: Vec::new(),
+            #[cfg(feature = "statistics")]
+            statistics: RxStatistics::default(),
         }
     }
 
@@ -181,7 +271,13 @@ where
             .position(|s| s.sub == subscription)
         {
             Some(pos) => {
+                #[cfg(feature = "statistics")]
+                let statistics = self.subscriptions[pos].statistics;
                 self.subscriptions[pos] = Subscription::new(subscription);
+                #[cfg(feature = "statistics")]
+                {
+                    self.subscriptions[pos].statistics = statistics;
+                }
                 Ok(())
             }
             None => Err(SubscriptionError::SubscriptionDoesNotExist),
@@ -197,6 +293,8 @@ where
     fn default() -> Self {
         Self {
             subscriptions: Default::default(),
+            #[cfg(feature = "statistics")]
+            statistics: Default::default(),
         }
     }
 }
@@ -232,14 +330,49 @@ where
         }
     }
 
+    fn subscriptions(&self) -> impl Iterator<Item = &crate::Subscription> {
+        self.subscriptions.iter().map(|s| &s.sub)
+    }
+
+    #[cfg(feature = "statistics")]
+    fn statistics(&self) -> Option<&RxStatistics> {
+        Some(&self.statistics)
+    }
+
+    #[cfg(feature = "statistics")]
+    fn subscription_statistics(
+        &self,
+        transfer_kind: crate::TransferKind,
+        port_id: PortId,
+    ) -> Option<&RxStatistics> {
+        self.subscriptions
+            .iter()
+            .find(|s| s.sub.transfer_kind == transfer_kind && s.sub.port_id == port_id)
+            .map(|s| &s.statistics)
+    }
+
     fn ingest<'a>(&'a mut self, frame: InternalRxFrame<C>) -> Result<Option<RefTransfer<'a, C>>, SessionError> {
         match self
             .subscriptions
             .iter_mut()
             .find(|sub| Self::matches_sub(&sub.sub, &frame))
         {
-            Some(subscription) => subscription.update(frame),
-            None => Ok(None),
+            Some(subscription) => {
+                #[cfg(feature = "statistics")]
+                let session = subscription.counted_update(&frame, &mut self.statistics)?;
+                #[cfg(not(feature = "statistics"))]
+                let session = subscription.update(&frame)?;
+                Ok(session.map(|session| {
+                    RefTransfer::from_frame(frame, session.timestamp.unwrap(), &session.payload)
+                }))
+            }
+            None => {
+                #[cfg(feature = "statistics")]
+                self.statistics.count(&Err(crate::RxErrorKind::Session(
+                    SessionError::NoSubscription,
+                )));
+                Ok(None)
+            }
         }
     }
 
@@ -247,11 +380,53 @@ where
         for sub in &mut self.subscriptions {
             let extent = sub.sub.extent;
             for session in sub.sessions.values_mut() {
-                if timestamp_expired(sub.sub.timeout, timestamp, session.timestamp) {
+                if session.is_leased() {
+                    continue;
+                }
+                // Completed transfers are kept for duplicate detection until the
+                // transfer-ID timeout, which can be longer than the session timeout
+                let timeout = if session.done {
+                    sub.sub.transfer_id_timeout
+                } else {
+                    sub.sub.timeout
+                };
+                if timestamp_expired(timeout, timestamp, session.timestamp) {
                     let transfer_id = session.transfer_id;
-                    *session = Session::new(transfer_id, Some(extent));
+                    *session = Session::new(transfer_id, Some(extent), session.iface);
                 }
             }
         }
     }
 }
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, C> LendingSessionManager<C> for HeapSessionManager<T, C>
+where
+    T: crate::transport::SessionMetadata<C>,
+    C: Clock,
+{
+    fn ingest_leased(&mut self, frame: InternalRxFrame<C>) -> Result<Option<TransferLease<C>>, SessionError> {
+        match self
+            .subscriptions
+            .iter_mut()
+            .find(|sub| Self::matches_sub(&sub.sub, &frame))
+        {
+            Some(subscription) => {
+                #[cfg(feature = "statistics")]
+                let session = subscription.counted_update(&frame, &mut self.statistics)?;
+                #[cfg(not(feature = "statistics"))]
+                let session = subscription.update(&frame)?;
+                Ok(session.map(|session| {
+                    TransferLease::from_frame(frame, session.timestamp.unwrap(), session.payload.lease())
+                }))
+            }
+            None => {
+                #[cfg(feature = "statistics")]
+                self.statistics.count(&Err(crate::RxErrorKind::Session(
+                    SessionError::NoSubscription,
+                )));
+                Ok(None)
+            }
+        }
+    }
+}
@@ -7,9 +7,18 @@
 //! user can also implement their own session to fit their individual needs
 //! using the SessionManager trait.
 
-use crate::internal::InternalRxFrame;
+use core::fmt;
+
+#[cfg(feature = "statistics")]
+use crate::statistics::RxStatistics;
 use crate::time::Timestamp;
 use crate::transfer::RefTransfer;
+#[cfg(target_has_atomic = "ptr")]
+use crate::transfer::TransferLease;
+
+use alloc::vec::Vec;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
 use crate::types::*;
 
 #[cfg(not(feature = "std"))]
@@ -23,20 +32,45 @@ pub use heap_based::HeapSessionManager;
 #[cfg(feature = "std")]
 pub use std_vec::StdVecSessionManager;
 
+/// Frames as session managers receive them, for implementing `SessionManager`.
+pub use crate::internal::InternalRxFrame;
+
 use embedded_time::fixed_point::FixedPoint;
 
 /// Session-related errors, caused by reception errors.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SessionError {
     OutOfSpace,
     NoSubscription,
     Timeout,
     NewSessionNoStart,
     InvalidTransferId,
-    // TODO come up with a way to return more specific errors
-    BadMetadata,
+    /// The session's last transfer is still leased out.
+    Leased,
+    /// The frame didn't follow on from the previous one, e.g. its toggle bit was wrong.
+    ToggleMismatch,
+    /// The reassembled transfer failed its integrity check.
+    CrcMismatch,
 }
 
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SessionError::OutOfSpace => "session manager out of space",
+            SessionError::NoSubscription => "no subscription for the port",
+            SessionError::Timeout => "session timed out partway through a transfer",
+            SessionError::NewSessionNoStart => "frame without the start of its transfer",
+            SessionError::InvalidTransferId => "duplicate or out of order transfer ID",
+            SessionError::Leased => "session's last transfer is still leased out",
+            SessionError::ToggleMismatch => "toggle bit mismatch",
+            SessionError::CrcMismatch => "transfer CRC mismatch",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SessionError {}
+
 /// Errors caused when adding a subscription.
 #[derive(Copy, Clone, Debug)]
 pub enum SubscriptionError {
@@ -55,7 +89,7 @@ pub enum SubscriptionError {
 /// your own.
 pub trait SessionManager<C: embedded_time::Clock> {
     /// Process incoming frame.
-    fn ingest<'a>(&mut self, frame: InternalRxFrame<C>) -> Result<Option<RefTransfer<C>>, SessionError>;
+    fn ingest<'a>(&'a mut self, frame: InternalRxFrame<C>) -> Result<Option<RefTransfer<'a, C>>, SessionError>;
 
     /// Housekeeping function called to clean up timed-out sessions.
     fn update_sessions(&mut self, timestamp: Timestamp<C>);
@@ -81,10 +115,102 @@ pub trait SessionManager<C: embedded_time::Clock> {
 
     /// Removes a subscription from the list.
     fn unsubscribe(&mut self, subscription: crate::Subscription) -> Result<(), SubscriptionError>;
+
+    /// Iterate over the current subscriptions.
+    fn subscriptions(&self) -> impl Iterator<Item = &crate::Subscription>;
+
+    /// Reception statistics over all subscriptions, if this session manager keeps them.
+    #[cfg(feature = "statistics")]
+    fn statistics(&self) -> Option<&RxStatistics> {
+        None
+    }
+
+    /// Reception statistics of a subscription, if this session manager keeps them.
+    #[cfg(feature = "statistics")]
+    fn subscription_statistics(
+        &self,
+        _transfer_kind: crate::TransferKind,
+        _port_id: PortId,
+    ) -> Option<&RxStatistics> {
+        None
+    }
 }
 
-fn timestamp_expired<C: embedded_time::Clock, D>(
+/// Session manager that can lend completed transfers out instead of borrowing them.
+///
+/// This allows transfers to be processed in another task without copying them,
+/// while the node keeps receiving on other sessions. Leases are reference counted
+/// atomically, so this is only available on targets with atomic pointers.
+#[cfg(target_has_atomic = "ptr")]
+pub trait LendingSessionManager<C: embedded_time::Clock>: SessionManager<C> {
+    /// Process incoming frame, leasing out the transfer once it is complete.
+    ///
+    /// Until the lease is dropped, further frames for the same session must be
+    /// rejected with `SessionError::Leased`.
+    fn ingest_leased(&mut self, frame: InternalRxFrame<C>) -> Result<Option<TransferLease<C>>, SessionError>;
+}
+
+/// Payload buffer of a session, shared with a `TransferLease` while it's leased out.
+///
+/// Targets without atomic pointers can't lease transfers, so there it's a plain `Vec`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SessionPayload {
+    #[cfg(target_has_atomic = "ptr")]
+    buffer: Arc<Vec<u8>>,
+    #[cfg(not(target_has_atomic = "ptr"))]
+    buffer: Vec<u8>,
+}
+
+impl SessionPayload {
+    #[cfg(not(feature = "std"))]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity).into(),
+        }
+    }
+
+    /// Whether a lease still holds the buffer.
+    pub fn is_leased(&self) -> bool {
+        #[cfg(target_has_atomic = "ptr")]
+        return Arc::strong_count(&self.buffer) > 1;
+        #[cfg(not(target_has_atomic = "ptr"))]
+        return false;
+    }
+
+    /// The buffer, to add to. Not to be called while it's leased out, as that copies it.
+    pub fn make_mut(&mut self) -> &mut Vec<u8> {
+        #[cfg(target_has_atomic = "ptr")]
+        return Arc::make_mut(&mut self.buffer);
+        #[cfg(not(target_has_atomic = "ptr"))]
+        return &mut self.buffer;
+    }
+
+    /// Share the buffer with a lease.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn lease(&self) -> Arc<Vec<u8>> {
+        self.buffer.clone()
+    }
+}
+
+impl core::ops::Deref for SessionPayload {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+/// Checks whether a transfer starting at `now` with `transfer_id` repeats or predates
+/// the previous transfer of the session, which started at `then` with `previous`.
+///
+/// Once the transfer-ID timeout has passed any transfer ID is accepted again (e.g. the
+/// remote node restarted). Before that, only IDs up to half the modulo ahead are new,
+/// with the distance computed modulo the transport's transfer-ID range.
+fn transfer_id_stale<C: embedded_time::Clock, D>(
     timeout: D,
+    modulo: usize,
+    previous: TransferId,
+    transfer_id: TransferId,
     now: Timestamp<C>,
     then: Option<Timestamp<C>>,
 ) -> bool
@@ -92,11 +218,59 @@ where
     D: embedded_time::duration::Duration + FixedPoint,
     <C as embedded_time::Clock>::T: From<<D as FixedPoint>::T>,
 {
-    if let Some(then) = then {
-        if now - then > timeout.to_generic(C::SCALING_FACTOR).unwrap() {
-            return true;
-        }
+    if then.is_none() || timestamp_expired(timeout, now, then) {
+        return false;
     }
 
-    false
+    let distance = (transfer_id as usize + modulo - previous as usize % modulo) % modulo;
+    distance == 0 || distance > modulo / 2
+}
+
+pub(crate) fn timestamp_expired<C: embedded_time::Clock, D>(
+    timeout: D,
+    now: Timestamp<C>,
+    then: Option<Timestamp<C>>,
+) -> bool
+where
+    D: embedded_time::duration::Duration + FixedPoint,
+    <C as embedded_time::Clock>::T: From<<D as FixedPoint>::T>,
+{
+    matches!(elapsed_ticks(timeout, now, then), Some((elapsed, timeout)) if elapsed > timeout)
+}
+
+/// Like `timestamp_expired`, but also true once exactly `period` has passed.
+pub(crate) fn period_elapsed<C: embedded_time::Clock, D>(
+    period: D,
+    now: Timestamp<C>,
+    then: Option<Timestamp<C>>,
+) -> bool
+where
+    D: embedded_time::duration::Duration + FixedPoint,
+    <C as embedded_time::Clock>::T: From<<D as FixedPoint>::T>,
+{
+    matches!(elapsed_ticks(period, now, then), Some((elapsed, period)) if elapsed >= period)
+}
+
+/// Clock ticks from `then` to `now`, and in `duration`. `None` if there's no `then`,
+/// it's after `now`, or `duration` doesn't fit the clock's counter.
+fn elapsed_ticks<C: embedded_time::Clock, D>(
+    duration: D,
+    now: Timestamp<C>,
+    then: Option<Timestamp<C>>,
+) -> Option<(C::T, C::T)>
+where
+    D: embedded_time::duration::Duration + FixedPoint,
+    <C as embedded_time::Clock>::T: From<<D as FixedPoint>::T>,
+{
+    // Longer than the clock's counter can hold, so it can't have passed
+    let duration = duration.to_generic::<C::T>(C::SCALING_FACTOR).ok()?;
+
+    // Instants compare modulo the counter's range, so the counter wrapping in between
+    // is fine as long as less than half the range has passed, see `MonotonicClock`.
+    // Frames from different interfaces can arrive slightly out of order,
+    // so a timestamp earlier than the session's one is not an underflow.
+    // Both durations are in the clock's ticks, so compare those: comparing `Generic`
+    // durations rounds them down to whole seconds first.
+    let elapsed = now.checked_duration_since(&then?)?;
+    Some((elapsed.integer(), duration.integer()))
 }
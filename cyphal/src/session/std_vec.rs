@@ -5,6 +5,8 @@
 
 use crate::session::*;
 use crate::time::Timestamp;
+use crate::transfer::{RefTransfer, TransferLease};
+use crate::transport::Transport;
 use crate::types::NodeId;
 
 use std::collections::HashMap;
@@ -19,8 +21,13 @@ where
 {
     // Timestamp of first frame
     pub timestamp: Option<Timestamp<C>>,
-    pub payload: Vec<u8>,
+    // Shared with a TransferLease while the last transfer is leased out
+    pub payload: SessionPayload,
     pub transfer_id: TransferId,
+    // Interface the session is currently receiving from
+    pub iface: usize,
+    // Whether the transfer with transfer_id has been completed
+    pub done: bool,
 
     pub md: T,
 }
@@ -30,14 +37,20 @@ where
     T: crate::transport::SessionMetadata<C>,
     C: embedded_time::Clock,
 {
-    pub fn new(transfer_id: TransferId) -> Self {
+    pub fn new(transfer_id: TransferId, iface: usize) -> Self {
         Self {
             timestamp: None,
-            payload: Vec::new(),
+            payload: SessionPayload::default(),
             transfer_id,
+            iface,
+            done: false,
             md: T::new(),
         }
     }
+
+    pub fn is_leased(&self) -> bool {
+        self.payload.is_leased()
+    }
 }
 
 /// Internal subscription object. Contains hash map of sessions.
@@ -47,7 +60,10 @@ where
     C: embedded_time::Clock,
 {
     sub: crate::Subscription,
-    sessions: HashMap<NodeId, Session<T, C>>,
+    #[cfg(feature = "statistics")]
+    statistics: RxStatistics,
+    // Keyed by source node, anonymous transfers under `None`
+    sessions: HashMap<Option<NodeId>, Session<T, C>>,
 }
 
 impl<T, C> Subscription<T, C>
@@ -58,28 +74,81 @@ where
     pub fn new(sub: crate::Subscription) -> Self {
         Self {
             sub,
+            #[cfg(feature = "statistics")]
+            statistics: RxStatistics::default(),
             sessions: HashMap::new(),
         }
     }
 
-    /// Update subscription with incoming frame
-    fn update(&mut self, frame: InternalRxFrame<C>) -> Result<Option<Transfer<C>>, SessionError> {
-        // TODO maybe some of the logic here can be skipped with anon transfers.
-        let session = frame.source_node_id.unwrap();
+    /// Update subscription with incoming frame, returning the session if the transfer is complete.
+    fn update(
+        &mut self,
+        frame: &InternalRxFrame<C>,
+    ) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let session = frame.source_node_id;
+        // Anonymous transfers come from nodes that can't be told apart, so each one
+        // starts afresh instead of being checked against the last
+        if session.is_none() && frame.start_of_transfer {
+            self.sessions.remove(&session);
+        }
+
         // Create default session if it doesn't exist
         if let std::collections::hash_map::Entry::Vacant(e) = self.sessions.entry(session) {
             if !frame.start_of_transfer {
                 return Err(SessionError::NewSessionNoStart);
             }
-            e.insert(Session::new(frame.transfer_id));
+            e.insert(Session::new(frame.transfer_id, frame.iface));
+        }
+
+        // Session can't be touched until the lease is dropped
+        if self.sessions[&session].is_leased() {
+            return Err(SessionError::Leased);
         }
 
-        if self.sessions[&session].transfer_id != frame.transfer_id {
-            // Create new session
-            self.sessions.entry(session).and_modify(|s| {
-                *s = Session::new(frame.transfer_id);
-            });
+        if self.sessions[&session].iface != frame.iface {
+            // Frames from another interface are redundant copies, unless the session's
+            // interface has been quiet for a full timeout, in which case we switch over.
+            // A session without a timestamp was reset because it timed out already.
+            let current = &self.sessions[&session];
+            if !frame.start_of_transfer
+                || (current.timestamp.is_some()
+                    && !timestamp_expired(
+                        self.sub.transfer_id_timeout,
+                        frame.timestamp,
+                        current.timestamp,
+                    ))
+            {
+                return Ok(None);
+            }
+            self.sessions
+                .insert(session, Session::new(frame.transfer_id, frame.iface));
+        } else if frame.start_of_transfer {
+            // New transfers can't repeat or predate the last one within the transfer-ID timeout
+            let current = &self.sessions[&session];
+            if (current.done || current.transfer_id != frame.transfer_id)
+                && transfer_id_stale(
+                    self.sub.transfer_id_timeout,
+                    <T::Transport as Transport<C>>::TRANSFER_ID_MODULO,
+                    current.transfer_id,
+                    frame.transfer_id,
+                    frame.timestamp,
+                    current.timestamp,
+                )
+            {
+                return Err(SessionError::InvalidTransferId);
+            }
+            self.sessions
+                .insert(session, Session::new(frame.transfer_id, frame.iface));
         } else {
+            // Continuation frames have to belong to the transfer being reassembled, which
+            // a session that timed out no longer has
+            let current = &self.sessions[&session];
+            if current.done
+                || current.transfer_id != frame.transfer_id
+                || current.timestamp.is_none()
+            {
+                return Err(SessionError::NewSessionNoStart);
+            }
             // Check for session expiration
             if timestamp_expired(
                 self.sub.timeout,
@@ -88,7 +157,7 @@ where
             ) {
                 let transfer_id = self.sessions[&session].transfer_id;
                 self.sessions.entry(session).and_modify(|s| {
-                    *s = Session::new(transfer_id);
+                    *s = Session::new(transfer_id, frame.iface);
                 });
                 return Err(SessionError::Timeout);
             }
@@ -97,41 +166,57 @@ where
         self.accept_frame(session, frame)
     }
 
+    /// `update`, counting the frame in the subscription's statistics and `totals`.
+    #[cfg(feature = "statistics")]
+    fn counted_update(
+        &mut self,
+        frame: &InternalRxFrame<C>,
+        totals: &mut RxStatistics,
+    ) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let result = self
+            .update(frame)
+            .map(|session| session.map(|session| session.payload.len()));
+        for statistics in [&mut self.statistics, totals] {
+            statistics.count(&result.map_err(crate::RxErrorKind::Session));
+        }
+
+        match result? {
+            Some(_) => Ok(self.sessions.get_mut(&frame.source_node_id)),
+            None => Ok(None),
+        }
+    }
+
     fn accept_frame(
         &mut self,
-        session: NodeId,
-        frame: InternalRxFrame<C>,
-    ) -> Result<Option<Transfer<C>>, SessionError> {
-        let mut session = self.sessions.get_mut(&session).unwrap();
+        session: Option<NodeId>,
+        frame: &InternalRxFrame<C>,
+    ) -> Result<Option<&mut Session<T, C>>, SessionError> {
+        let session = self.sessions.get_mut(&session).unwrap();
 
         if frame.start_of_transfer {
             session.timestamp = Some(frame.timestamp);
         }
 
-        if let Some(len) = session.md.update(&frame) {
+        if let Some(len) = session.md.update(frame) {
             // Truncate payload if subscription extent is less than the incoming data
-            let payload_to_copy = if session.payload.len() + len > self.sub.extent {
-                session.payload.len() + len - self.sub.extent
-            } else {
-                len
-            };
-            session.payload.extend(&frame.payload[0..payload_to_copy]);
+            let payload_to_copy =
+                core::cmp::min(len, self.sub.extent.saturating_sub(session.payload.len()));
+            session.payload.make_mut().extend(&frame.payload[0..payload_to_copy]);
 
             if frame.end_of_transfer {
-                if session.md.is_valid(&frame) {
-                    Ok(Some(Transfer::from_frame(
-                        frame,
-                        session.timestamp.unwrap(),
-                        &session.payload,
-                    )))
+                if session.md.is_valid(frame) {
+                    let len = session.md.payload_len(frame, session.payload.len());
+                    session.payload.make_mut().truncate(len);
+                    session.done = true;
+                    Ok(Some(session))
                 } else {
-                    Err(SessionError::BadMetadata)
+                    Err(SessionError::CrcMismatch)
                 }
             } else {
                 Ok(None)
             }
         } else {
-            Err(SessionError::BadMetadata)
+            Err(SessionError::ToggleMismatch)
         }
     }
 }
@@ -145,6 +230,8 @@ where
     C: embedded_time::Clock,
 {
     subscriptions: Vec<Subscription<T, C>>,
+    #[cfg(feature = "statistics")]
+    statistics: RxStatistics,
 }
 
 impl<T, C> StdVecSessionManager<T, C>
@@ -155,6 +242,8 @@ where
     pub fn new() -> Self {
         Self {
             subscriptions: Vec::new(),
+            #[cfg(feature = "statistics")]
+            statistics: RxStatistics::default(),
         }
     }
 
@@ -169,7 +258,13 @@ where
             .position(|s| s.sub == subscription)
         {
             Some(pos) => {
+                #[cfg(feature = "statistics")]
+                let statistics = self.subscriptions[pos].statistics;
                 self.subscriptions[pos] = Subscription::new(subscription);
+                #[cfg(feature = "statistics")]
+                {
+                    self.subscriptions[pos].statistics = statistics;
+                }
                 Ok(())
             }
             None => Err(SubscriptionError::SubscriptionDoesNotExist),
@@ -217,25 +312,111 @@ where
         }
     }
 
-    fn ingest(&mut self, frame: InternalRxFrame<C>) -> Result<Option<Transfer<C>>, SessionError> {
+    fn subscriptions(&self) -> impl Iterator<Item = &crate::Subscription> {
+        self.subscriptions.iter().map(|s| &s.sub)
+    }
+
+    #[cfg(feature = "statistics")]
+    fn statistics(&self) -> Option<&RxStatistics> {
+        Some(&self.statistics)
+    }
+
+    #[cfg(feature = "statistics")]
+    fn subscription_statistics(
+        &self,
+        transfer_kind: crate::TransferKind,
+        port_id: PortId,
+    ) -> Option<&RxStatistics> {
+        self.subscriptions
+            .iter()
+            .find(|s| s.sub.transfer_kind == transfer_kind && s.sub.port_id == port_id)
+            .map(|s| &s.statistics)
+    }
+
+    fn ingest(
+        &mut self,
+        frame: InternalRxFrame<C>,
+    ) -> Result<Option<RefTransfer<'_, C>>, SessionError> {
         match self
             .subscriptions
             .iter_mut()
             .find(|sub| Self::matches_sub(&sub.sub, &frame))
         {
-            Some(subscription) => subscription.update(frame),
-            None => Ok(None),
+            Some(subscription) => {
+                #[cfg(feature = "statistics")]
+                let session = subscription.counted_update(&frame, &mut self.statistics)?;
+                #[cfg(not(feature = "statistics"))]
+                let session = subscription.update(&frame)?;
+                Ok(session.map(|session| {
+                    RefTransfer::from_frame(frame, session.timestamp.unwrap(), &session.payload)
+                }))
+            }
+            None => {
+                #[cfg(feature = "statistics")]
+                self.statistics.count(&Err(crate::RxErrorKind::Session(
+                    SessionError::NoSubscription,
+                )));
+                Ok(None)
+            }
         }
     }
 
     fn update_sessions(&mut self, timestamp: Timestamp<C>) {
         for sub in &mut self.subscriptions {
             for session in sub.sessions.values_mut() {
-                if timestamp_expired(sub.sub.timeout, timestamp, session.timestamp) {
+                if session.is_leased() {
+                    continue;
+                }
+                // Completed transfers are kept for duplicate detection until the
+                // transfer-ID timeout, which can be longer than the session timeout
+                let timeout = if session.done {
+                    sub.sub.transfer_id_timeout
+                } else {
+                    sub.sub.timeout
+                };
+                if timestamp_expired(timeout, timestamp, session.timestamp) {
                     let transfer_id = session.transfer_id;
-                    *session = Session::new(transfer_id);
+                    *session = Session::new(transfer_id, session.iface);
                 }
             }
         }
     }
 }
+
+impl<T, C> LendingSessionManager<C> for StdVecSessionManager<T, C>
+where
+    T: crate::transport::SessionMetadata<C>,
+    C: embedded_time::Clock,
+{
+    fn ingest_leased(
+        &mut self,
+        frame: InternalRxFrame<C>,
+    ) -> Result<Option<TransferLease<C>>, SessionError> {
+        match self
+            .subscriptions
+            .iter_mut()
+            .find(|sub| Self::matches_sub(&sub.sub, &frame))
+        {
+            Some(subscription) => {
+                #[cfg(feature = "statistics")]
+                let session = subscription.counted_update(&frame, &mut self.statistics)?;
+                #[cfg(not(feature = "statistics"))]
+                let session = subscription.update(&frame)?;
+                Ok(session.map(|session| {
+                    TransferLease::from_frame(
+                        frame,
+                        session.timestamp.unwrap(),
+                        session.payload.lease(),
+                    )
+                }))
+            }
+            None => {
+                #[cfg(feature = "statistics")]
+                self.statistics.count(&Err(crate::RxErrorKind::Session(
+                    SessionError::NoSubscription,
+                )));
+                Ok(None)
+            }
+        }
+    }
+}
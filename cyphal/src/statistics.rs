@@ -0,0 +1,223 @@
+//! Reception and transmission statistics.
+//!
+//! `Node` counts every frame and transfer it receives and transmits, along with the
+//! frames it had to drop and why. The session managers in this crate also keep
+//! reception counters for each subscription and over all of them, available through
+//! `SessionManager::statistics` and `SessionManager::subscription_statistics`.
+//!
+//! Counters can be read directly, or listed as registers (e.g. `stat.rx.frames`) with
+//! `Node::statistics_registers` for an application that serves registers to expose.
+//!
+//! Only available with the `statistics` feature, so that nodes that don't need the
+//! counters don't spend memory and cycles keeping them.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::session::SessionError;
+use crate::RxErrorKind;
+
+/// Frames dropped, counted by the error they were dropped with.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DropCounts {
+    pub transfer_start_missing_toggle: u64,
+    pub anon_not_single_frame: u64,
+    pub non_last_under_utilization: u64,
+    pub frame_empty: u64,
+    pub invalid_can_id: u64,
+    pub new_session_no_start: u64,
+    /// Sessions that timed out partway through a transfer.
+    pub timeout: u64,
+    /// Duplicate or out of order transfers.
+    pub invalid_transfer_id: u64,
+    pub out_of_space: u64,
+    /// Frames for ports nobody subscribed to. Only counted by session managers, as
+    /// these aren't errors to the node.
+    pub no_subscription: u64,
+    pub leased: u64,
+    pub toggle_mismatch: u64,
+    /// Transfers that failed their CRC.
+    pub crc_mismatch: u64,
+}
+
+impl DropCounts {
+    /// Count a frame dropped with `err`.
+    pub(crate) fn count(&mut self, err: &RxErrorKind) {
+        let counter = match err {
+            RxErrorKind::TransferStartMissingToggle => &mut self.transfer_start_missing_toggle,
+            RxErrorKind::AnonNotSingleFrame => &mut self.anon_not_single_frame,
+            RxErrorKind::NonLastUnderUtilization => &mut self.non_last_under_utilization,
+            RxErrorKind::FrameEmpty => &mut self.frame_empty,
+            RxErrorKind::InvalidCanId => &mut self.invalid_can_id,
+            RxErrorKind::Session(SessionError::NewSessionNoStart) => &mut self.new_session_no_start,
+            RxErrorKind::Session(SessionError::Timeout) => &mut self.timeout,
+            RxErrorKind::Session(SessionError::InvalidTransferId) => &mut self.invalid_transfer_id,
+            RxErrorKind::Session(SessionError::OutOfSpace) => &mut self.out_of_space,
+            RxErrorKind::Session(SessionError::NoSubscription) => &mut self.no_subscription,
+            RxErrorKind::Session(SessionError::Leased) => &mut self.leased,
+            RxErrorKind::Session(SessionError::ToggleMismatch) => &mut self.toggle_mismatch,
+            RxErrorKind::Session(SessionError::CrcMismatch) => &mut self.crc_mismatch,
+        };
+        *counter += 1;
+    }
+
+    /// All frames dropped.
+    pub fn total(&self) -> u64 {
+        self.counters().iter().map(|(_, count)| count).sum()
+    }
+
+    fn counters(&self) -> [(&'static str, u64); 13] {
+        [
+            (
+                "transfer_start_missing_toggle",
+                self.transfer_start_missing_toggle,
+            ),
+            ("anon_not_single_frame", self.anon_not_single_frame),
+            (
+                "non_last_under_utilization",
+                self.non_last_under_utilization,
+            ),
+            ("frame_empty", self.frame_empty),
+            ("invalid_can_id", self.invalid_can_id),
+            ("new_session_no_start", self.new_session_no_start),
+            ("timeout", self.timeout),
+            ("invalid_transfer_id", self.invalid_transfer_id),
+            ("out_of_space", self.out_of_space),
+            ("no_subscription", self.no_subscription),
+            ("leased", self.leased),
+            ("toggle_mismatch", self.toggle_mismatch),
+            ("crc_mismatch", self.crc_mismatch),
+        ]
+    }
+}
+
+/// Reception counters.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RxStatistics {
+    /// Every frame received, including the ones dropped.
+    pub frames: u64,
+    /// Transfers completed.
+    pub transfers: u64,
+    /// Payload bytes of the completed transfers, after truncation to the extent.
+    pub bytes: u64,
+    pub drops: DropCounts,
+}
+
+impl RxStatistics {
+    /// Count a received frame, which either completed a transfer of `len` bytes,
+    /// didn't complete one, or was dropped.
+    pub(crate) fn count(&mut self, result: &Result<Option<usize>, RxErrorKind>) {
+        self.frames += 1;
+        match result {
+            Ok(Some(len)) => {
+                self.transfers += 1;
+                self.bytes += *len as u64;
+            }
+            Ok(None) => {}
+            Err(err) => self.drops.count(err),
+        }
+    }
+
+    fn registers(&self, prefix: &str, registers: &mut Vec<(String, u64)>) {
+        registers.push((format!("{}.frames", prefix), self.frames));
+        registers.push((format!("{}.transfers", prefix), self.transfers));
+        registers.push((format!("{}.bytes", prefix), self.bytes));
+        for (name, count) in self.drops.counters() {
+            registers.push((format!("{}.drop.{}", prefix, name), count));
+        }
+    }
+}
+
+/// Transmission counters.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TxStatistics {
+    pub frames: u64,
+    pub transfers: u64,
+    /// Payload bytes of the transfers.
+    pub bytes: u64,
+}
+
+impl TxStatistics {
+    /// Count a transfer of `len` bytes, sent in `frames` frames.
+    pub(crate) fn count(&mut self, frames: usize, len: usize) {
+        self.frames += frames as u64;
+        self.transfers += 1;
+        self.bytes += len as u64;
+    }
+}
+
+/// Everything a `Node` has received and transmitted.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Statistics {
+    pub rx: RxStatistics,
+    pub tx: TxStatistics,
+}
+
+impl Statistics {
+    /// The counters as `(name, value)` pairs, named `<prefix>.rx.frames`,
+    /// `<prefix>.rx.drop.timeout`, `<prefix>.tx.bytes` and so on.
+    pub(crate) fn registers(&self, prefix: &str, registers: &mut Vec<(String, u64)>) {
+        self.rx.registers(&format!("{}.rx", prefix), registers);
+        registers.push((format!("{}.tx.frames", prefix), self.tx.frames));
+        registers.push((format!("{}.tx.transfers", prefix), self.tx.transfers));
+        registers.push((format!("{}.tx.bytes", prefix), self.tx.bytes));
+    }
+}
+
+/// Register names of the counters of a subscription, e.g. `stat.message.7509`.
+pub(crate) fn subscription_registers(
+    prefix: &str,
+    subscription: &crate::Subscription,
+    statistics: &RxStatistics,
+    registers: &mut Vec<(String, u64)>,
+) {
+    statistics.registers(
+        &format!(
+            "{}.{}.{}",
+            prefix,
+            subscription.transfer_kind(),
+            subscription.port_id()
+        ),
+        registers,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counting() {
+        let mut statistics = RxStatistics::default();
+        statistics.count(&Ok(None));
+        statistics.count(&Ok(Some(12)));
+        statistics.count(&Err(SessionError::Timeout.into()));
+        statistics.count(&Err(RxErrorKind::Session(SessionError::Timeout)));
+        statistics.count(&Err(RxErrorKind::Session(SessionError::CrcMismatch)));
+        statistics.count(&Err(RxErrorKind::FrameEmpty));
+
+        assert_eq!(statistics.frames, 6);
+        assert_eq!(statistics.transfers, 1);
+        assert_eq!(statistics.bytes, 12);
+        assert_eq!(statistics.drops.timeout, 2);
+        assert_eq!(statistics.drops.crc_mismatch, 1);
+        assert_eq!(statistics.drops.frame_empty, 1);
+        assert_eq!(statistics.drops.total(), 4);
+    }
+
+    #[test]
+    fn register_names() {
+        let mut statistics = Statistics::default();
+        statistics.rx.count(&Err(RxErrorKind::InvalidCanId));
+        statistics.tx.count(3, 20);
+
+        let mut registers = Vec::new();
+        statistics.registers("stat", &mut registers);
+        assert_eq!(registers.len(), 3 + 13 + 3);
+        assert!(registers.contains(&("stat.rx.frames".into(), 1)));
+        assert!(registers.contains(&("stat.rx.drop.invalid_can_id".into(), 1)));
+        assert!(registers.contains(&("stat.tx.frames".into(), 3)));
+        assert!(registers.contains(&("stat.tx.bytes".into(), 20)));
+    }
+}
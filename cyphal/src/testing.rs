@@ -0,0 +1,228 @@
+//! Helpers for testing nodes, for use in other crates' tests with the `testing`
+//! feature.
+//!
+//! `TestClock` only moves when told to, so timeouts can be tested without waiting.
+//! `CanFrameBuilder` makes CAN frames by hand, e.g. to feed a node malformed or out of
+//! order frames, and `can_frames` splits a transfer into the frames a node would send.
+//! The assertions compare received frames and transfers field by field, failing at
+//! the caller's line. `transport::can::sim` goes further and simulates whole buses.
+//!
+//! `TestNode` is a Classic CAN node on `TestClock`, using `TestSessionManager`:
+//! `StdVecSessionManager` with the `std` feature and `HeapSessionManager` without.
+
+use alloc::vec::Vec;
+
+use arrayvec::ArrayVec;
+use embedded_hal::can::ExtendedId;
+use embedded_time::Clock;
+
+#[cfg(not(feature = "std"))]
+pub use crate::session::HeapSessionManager as TestSessionManager;
+#[cfg(feature = "std")]
+pub use crate::session::StdVecSessionManager as TestSessionManager;
+pub use crate::time::TestClock;
+
+use crate::internal::InternalRxFrame;
+use crate::time::Timestamp;
+use crate::transfer::{RefTransfer, Transfer, TransferMetadata};
+use crate::transport::can::{
+    Can, CanFrame, CanIter, CanMessageId, CanMetadata, CanServiceId, TailByte,
+};
+use crate::types::*;
+use crate::{Node, Priority, StreamingIterator, TransferKind};
+
+/// Node on Classic CAN, timed by `TestClock`.
+pub type TestNode = Node<TestSessionManager<CanMetadata, TestClock>, Can, TestClock>;
+
+/// Builder of single CAN frames, tail byte included.
+///
+/// Frames start and end a transfer with the toggle bit set and transfer ID 0, i.e.
+/// are single frame transfers, unless told otherwise.
+///
+/// # Example
+/// ```
+/// use cyphal::testing::{CanFrameBuilder, TestClock};
+/// use cyphal::Priority;
+/// use embedded_time::Clock;
+///
+/// let clock = TestClock::default();
+/// let frame = CanFrameBuilder::message(Priority::Nominal, 7509, Some(42))
+///     .transfer_id(3)
+///     .build(clock.try_now().unwrap(), &[1, 2, 3]);
+/// assert_eq!(frame.payload.len(), 4);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct CanFrameBuilder {
+    id: ExtendedId,
+    start: bool,
+    end: bool,
+    toggle: bool,
+    transfer_id: TransferId,
+}
+
+impl CanFrameBuilder {
+    /// Frame of a message, anonymous if `source` is `None`.
+    pub fn message(priority: Priority, subject: PortId, source: Option<NodeId>) -> Self {
+        Self::with_id(CanMessageId::new(priority, subject, source))
+    }
+
+    /// Frame of a request from `source` to `destination`.
+    pub fn request(
+        priority: Priority,
+        service: PortId,
+        source: NodeId,
+        destination: NodeId,
+    ) -> Self {
+        Self::with_id(CanServiceId::new(
+            priority,
+            true,
+            service,
+            destination,
+            source,
+        ))
+    }
+
+    /// Frame of a response from `source` to `destination`.
+    pub fn response(
+        priority: Priority,
+        service: PortId,
+        source: NodeId,
+        destination: NodeId,
+    ) -> Self {
+        Self::with_id(CanServiceId::new(
+            priority,
+            false,
+            service,
+            destination,
+            source,
+        ))
+    }
+
+    /// Frame with any CAN ID, valid or not.
+    pub fn with_id(id: ExtendedId) -> Self {
+        Self {
+            id,
+            start: true,
+            end: true,
+            toggle: true,
+            transfer_id: 0,
+        }
+    }
+
+    pub fn transfer_id(mut self, transfer_id: TransferId) -> Self {
+        self.transfer_id = transfer_id;
+        self
+    }
+
+    /// Set the start of transfer, end of transfer and toggle bits of the tail byte.
+    pub fn tail(mut self, start: bool, end: bool, toggle: bool) -> Self {
+        self.start = start;
+        self.end = end;
+        self.toggle = toggle;
+        self
+    }
+
+    /// Frame carrying `data` and the tail byte.
+    ///
+    /// # Panics
+    /// If `data` is longer than 7 bytes.
+    pub fn build<C: Clock>(&self, timestamp: Timestamp<C>, data: &[u8]) -> CanFrame<C> {
+        let mut payload = ArrayVec::<[u8; 8]>::new();
+        payload.extend(data.iter().copied());
+        payload.push(TailByte::new(self.start, self.end, self.toggle, self.transfer_id).0);
+        CanFrame {
+            timestamp,
+            id: self.id,
+            payload,
+        }
+    }
+}
+
+/// Message transfer on `subject` at nominal priority.
+pub fn message_transfer<C: Clock>(
+    timestamp: Timestamp<C>,
+    subject: PortId,
+    transfer_id: TransferId,
+    payload: &[u8],
+) -> RefTransfer<'_, C> {
+    RefTransfer {
+        metadata: TransferMetadata {
+            timestamp,
+            priority: Priority::Nominal,
+            transfer_kind: TransferKind::Message,
+            port_id: subject,
+            remote_node_id: None,
+            transfer_id,
+        },
+        payload,
+    }
+}
+
+/// Every frame `source` would send `transfer` in.
+///
+/// # Panics
+/// If the transfer can't be sent, e.g. an anonymous one that doesn't fit in a frame.
+pub fn can_frames<C: Clock + Clone>(
+    transfer: &RefTransfer<C>,
+    source: Option<NodeId>,
+) -> Vec<CanFrame<C>> {
+    let mut iter = CanIter::new(transfer, source).expect("transfer can't be sent");
+    let mut frames = Vec::new();
+    while let Some(frame) = iter.next() {
+        frames.push(frame.clone());
+    }
+    frames
+}
+
+/// Assert that `frame` is the same as `expected`, timestamps aside.
+///
+/// `expected` is easiest made with `InternalRxFrame::as_message` or
+/// `InternalRxFrame::as_service`.
+#[track_caller]
+pub fn assert_frame_eq<C: Clock>(frame: &InternalRxFrame<C>, expected: &InternalRxFrame<C>) {
+    assert_eq!(frame.priority, expected.priority, "priority");
+    assert_eq!(frame.transfer_kind, expected.transfer_kind, "transfer kind");
+    assert_eq!(frame.port_id, expected.port_id, "port ID");
+    assert_eq!(frame.source_node_id, expected.source_node_id, "source");
+    assert_eq!(
+        frame.destination_node_id, expected.destination_node_id,
+        "destination"
+    );
+    assert_eq!(frame.transfer_id, expected.transfer_id, "transfer ID");
+    assert_eq!(frame.is_svc, expected.is_svc, "service");
+    assert_eq!(
+        frame.start_of_transfer, expected.start_of_transfer,
+        "start of transfer"
+    );
+    assert_eq!(
+        frame.end_of_transfer, expected.end_of_transfer,
+        "end of transfer"
+    );
+    assert_eq!(frame.iface, expected.iface, "interface");
+    assert_eq!(frame.payload, expected.payload, "payload");
+}
+
+/// Assert that `transfer` has the same metadata and payload as `expected`, timestamps
+/// aside.
+#[track_caller]
+pub fn assert_transfer_eq<'a, C: Clock + 'a, T: Transfer<'a, C>>(
+    transfer: &'a T,
+    expected: &RefTransfer<C>,
+) {
+    let metadata = transfer.metadata();
+    assert_eq!(metadata.priority, expected.metadata.priority, "priority");
+    assert_eq!(
+        metadata.transfer_kind, expected.metadata.transfer_kind,
+        "transfer kind"
+    );
+    assert_eq!(metadata.port_id, expected.metadata.port_id, "port ID");
+    assert_eq!(
+        metadata.remote_node_id, expected.metadata.remote_node_id,
+        "remote node ID"
+    );
+    assert_eq!(
+        metadata.transfer_id, expected.metadata.transfer_id,
+        "transfer ID"
+    );
+    assert_eq!(transfer.payload(), expected.payload, "payload");
+}
@@ -1,13 +1,15 @@
 pub type Timestamp<C> = embedded_time::Instant<C>;
 pub type Duration = embedded_time::duration::Milliseconds;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 pub use test_clock::TestClock;
 
 #[cfg(feature = "std")]
 pub use std_clock::StdClock;
 
-#[cfg(test)]
+pub use monotonic_clock::MonotonicClock;
+
+#[cfg(any(test, feature = "testing"))]
 mod test_clock {
     use core::{
         cell::RefCell,
@@ -19,12 +21,12 @@ mod test_clock {
     use alloc::rc::Rc;
 
     use embedded_time::{
-        duration::{Duration, Microseconds, Milliseconds},
-        fixed_point::FixedPoint,
-        Clock, ConversionError, TimeInt,
+        duration::Duration, fixed_point::FixedPoint, Clock, ConversionError, TimeInt,
     };
+    #[cfg(test)]
+    use embedded_time::duration::{Microseconds, Milliseconds};
 
-    /// A Clock for test cases
+    /// A Clock for test cases, which only moves when told to
     ///
     /// Contains a intern tick counter and a fraction which is set to 1 / 1,000,000 (secs) = resolution of one microsecond.
     /// Clock wraps if intern type overflows! (intern type = u32 -> ~71min)
@@ -41,15 +43,19 @@ mod test_clock {
         ///
         /// In particular 1 tick = 1 microsecond.
         pub fn add_ticks(&mut self, ticks: TickType) {
-            self.0.deref().borrow_mut().wrapping_add(&ticks);
+            let mut tick_count = self.0.deref().borrow_mut();
+            *tick_count = tick_count.wrapping_add(&ticks);
         }
 
         /// Add a duration to the clock.
         ///
         /// # Example
         /// ```
-        /// let clock = TestClock::default();
-        /// clock.add_duration(&Milliseconds::new(1243u32));
+        /// use cyphal::time::TestClock;
+        /// use embedded_time::duration::Milliseconds;
+        ///
+        /// let mut clock = TestClock::default();
+        /// clock.add_duration(&Milliseconds::new(1243u32)).unwrap();
         /// ```
         pub fn add_duration<D: Duration + FixedPoint>(
             &mut self,
@@ -143,6 +149,159 @@ mod test_clock {
     }
 }
 
+mod monotonic_clock {
+    use core::{cell::Cell, convert::TryFrom};
+
+    use embedded_time::{
+        clock,
+        duration::{Generic, Microseconds},
+        fixed_point::FixedPoint,
+        Clock, Instant,
+    };
+    use num_traits::Bounded;
+
+    /// A clock counting microseconds in 64 bits on top of another clock, e.g. a 32-bit
+    /// millisecond counter, so that timestamps don't wrap for half a million years.
+    ///
+    /// Timestamps of other clocks compare modulo their counter's range, so timeouts
+    /// are only told apart correctly while less than half the range has passed: just
+    /// under 25 days for a 32-bit millisecond counter, 36 minutes for 32-bit
+    /// microseconds. Session managers forget expired sessions in `update_sessions`, so
+    /// calling that often enough is one way around it, and running the node on this
+    /// clock is the other.
+    ///
+    /// The underlying counter's wraps are counted whenever the time is read, so this
+    /// clock has to be read at least once per wrap of it, e.g. every 49 days for a
+    /// 32-bit millisecond counter. A wrap missed in between goes uncounted and the time
+    /// jumps back. Each clone keeps its own count from where the original was, so each
+    /// clone in use has to be read that often too.
+    #[derive(Debug, Clone)]
+    pub struct MonotonicClock<C: Clock> {
+        clock: C,
+        /// Last tick count read, and the wraps counted so far.
+        state: Cell<(u64, u64)>,
+    }
+
+    impl<C: Clock> MonotonicClock<C> {
+        pub fn new(clock: C) -> Self {
+            Self {
+                clock,
+                state: Cell::new((0, 0)),
+            }
+        }
+
+        /// The underlying clock.
+        pub fn inner(&self) -> &C {
+            &self.clock
+        }
+    }
+
+    impl<C: Clock> Clock for MonotonicClock<C>
+    where
+        C::T: Into<u64>,
+    {
+        type T = u64;
+
+        const SCALING_FACTOR: embedded_time::rate::Fraction =
+            embedded_time::rate::Fraction::new(1, 1_000_000);
+
+        fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
+            let ticks: u64 = self.clock.try_now()?.duration_since_epoch().integer().into();
+            let (last, mut wraps) = self.state.get();
+            if ticks < last {
+                wraps += 1;
+            }
+            self.state.set((ticks, wraps));
+
+            // A 64-bit counter's range wraps to 0, but it never gets to wrap anyway
+            let range = <C::T as Bounded>::max_value().into().wrapping_add(1);
+            let ticks = wraps.wrapping_mul(range).wrapping_add(ticks);
+            let micros = Microseconds::<u64>::try_from(Generic::new(ticks, C::SCALING_FACTOR))
+                .map_err(|_| clock::Error::Unspecified)?;
+            Ok(Instant::new(micros.integer()))
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use core::cell::Cell;
+
+        use embedded_time::{Clock, Instant};
+
+        use super::MonotonicClock;
+        use crate::time::TestClock;
+
+        /// 32-bit millisecond counter, like a timer interrupt would keep.
+        #[derive(Debug, Default)]
+        struct MillisClock(Cell<u32>);
+
+        impl Clock for MillisClock {
+            type T = u32;
+
+            const SCALING_FACTOR: embedded_time::rate::Fraction =
+                embedded_time::rate::Fraction::new(1, 1000);
+
+            fn try_now(&self) -> Result<Instant<Self>, embedded_time::clock::Error> {
+                Ok(Instant::new(self.0.get()))
+            }
+        }
+
+        fn micros<C: Clock>(clock: &MonotonicClock<C>) -> u64
+        where
+            C::T: Into<u64>,
+        {
+            clock.try_now().unwrap().duration_since_epoch().integer()
+        }
+
+        #[test]
+        fn counts_wraps() {
+            let mut test_clock = TestClock::default();
+            let clock = MonotonicClock::new(test_clock.to_owned());
+            test_clock.add_ticks(u32::MAX - 9);
+            assert_eq!(micros(&clock), u32::MAX as u64 - 9);
+
+            test_clock.add_ticks(20);
+            assert_eq!(micros(&clock), (1 << 32) + 10);
+            test_clock.add_ticks(u32::MAX);
+            assert_eq!(micros(&clock), (2 << 32) + 9);
+        }
+
+        #[test]
+        fn scales_to_micros() {
+            let clock = MonotonicClock::new(MillisClock::default());
+            clock.inner().0.set(1234);
+            assert_eq!(micros(&clock), 1_234_000);
+
+            // Wrapping after 49.7 days
+            clock.inner().0.set(u32::MAX);
+            assert_eq!(micros(&clock), u32::MAX as u64 * 1000);
+            clock.inner().0.set(5);
+            assert_eq!(micros(&clock), ((1 << 32) + 5) * 1000);
+        }
+
+        #[test]
+        fn clones_count_separately() {
+            let mut test_clock = TestClock::default();
+            let clock = MonotonicClock::new(test_clock.to_owned());
+            let clone = clock.clone();
+            for _ in 0..4 {
+                test_clock.add_ticks(1 << 31);
+                micros(&clock);
+            }
+            assert_eq!(micros(&clock), 2 << 32);
+
+            // The clone wasn't read in between, so it missed both wraps
+            assert_eq!(micros(&clone), 0);
+        }
+
+        #[test]
+        fn is_send() {
+            fn send<T: Send>(_: T) {}
+            send(MonotonicClock::new(MillisClock::default()));
+        }
+    }
+}
+
 #[cfg(feature = "std")]
 mod std_clock {
     use core::{cell::RefCell, convert::TryInto};
@@ -0,0 +1,616 @@
+//! Tracking the other nodes on the network through their heartbeats.
+//!
+//! `NodeTracker` keeps a table of every node it has heard a heartbeat from, with
+//! its latest status, and reports changes as `NodeEvent`s: nodes appearing, going
+//! quiet, restarting (their uptime went backwards) and changing health. It can also
+//! fetch `GetInfo` from every new node. The tracker doesn't own a `Node`; completed
+//! transfers are handed to it, and it hands back requests to send.
+
+use alloc::collections::BTreeMap;
+
+use embedded_time::duration::Milliseconds;
+
+use crate::serialization::Deserialize;
+use crate::session::{timestamp_expired, SessionManager, SubscriptionError};
+use crate::time::{Duration, Timestamp};
+use crate::transfer::{RefTransfer, TransferMetadata};
+use crate::transport::Transport;
+use crate::types::*;
+use crate::uavcan::node::{
+    GetInfoResponse, Health, Heartbeat, Mode, GET_INFO_SERVICE, HEARTBEAT_SUBJECT,
+};
+use crate::{Priority, Subscription, TransferKind};
+
+/// Change in the state of the network.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NodeEvent {
+    /// First heartbeat from a node, or the first since it disappeared.
+    Appeared(NodeId),
+    /// No heartbeat from a node within the offline timeout.
+    Disappeared(NodeId),
+    /// A node's uptime went backwards.
+    Restarted(NodeId),
+    HealthChanged {
+        node_id: NodeId,
+        from: Health,
+        to: Health,
+    },
+    /// A node answered `GetInfo`, see `NodeStatus::info`.
+    InfoReceived(NodeId),
+}
+
+/// What's known about a node.
+#[derive(Clone, Debug)]
+pub struct NodeStatus<C: embedded_time::Clock> {
+    /// When the latest heartbeat was received.
+    pub last_seen: Timestamp<C>,
+    /// Seconds since the node started, as of its latest heartbeat.
+    pub uptime: u32,
+    pub health: Health,
+    pub mode: Mode,
+    pub vendor_specific_status_code: u8,
+    /// The node's answer to `GetInfo`, if it has been asked and answered since it
+    /// last (re)started.
+    pub info: Option<GetInfoResponse>,
+    info_requested: Option<Timestamp<C>>,
+    /// Transfer ID of the `GetInfo` request still waiting for its response.
+    info_pending: Option<TransferId>,
+    info_transfer_id: TransferId,
+}
+
+/// Table of the nodes on the network, kept up to date from their heartbeats.
+///
+/// Up to `E` events are kept until they're taken with `next_event`. Any more are
+/// dropped, and counted in `events_dropped`.
+pub struct NodeTracker<C: embedded_time::Clock, const E: usize = 16> {
+    nodes: BTreeMap<NodeId, NodeStatus<C>>,
+    events: heapless::Deque<NodeEvent, E>,
+    events_dropped: usize,
+    offline_timeout: Duration,
+    query_info: bool,
+}
+
+impl<C: embedded_time::Clock> NodeTracker<C> {
+    /// Create a tracker which considers nodes offline after the standard
+    /// `Heartbeat::OFFLINE_TIMEOUT`, and doesn't query `GetInfo`.
+    ///
+    /// Use `Default` for a tracker keeping some other number of events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C: embedded_time::Clock, const E: usize> NodeTracker<C, E> {
+    /// Set how long a node can go without a heartbeat before it's considered gone.
+    pub fn with_offline_timeout(mut self, timeout: Duration) -> Self {
+        self.offline_timeout = timeout;
+        self
+    }
+
+    /// Request `GetInfo` from every node that appears or restarts, see `info_request`.
+    pub fn with_info_requests(mut self) -> Self {
+        self.query_info = true;
+        self
+    }
+
+    /// Subscribe to heartbeats, and to `GetInfo` responses if they're requested.
+    pub fn subscribe<S: SessionManager<C>>(
+        &self,
+        sessions: &mut S,
+    ) -> Result<(), SubscriptionError> {
+        sessions.subscribe(Subscription::for_type::<Heartbeat>(
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            self.offline_timeout,
+        ))?;
+        if self.query_info {
+            sessions.subscribe(Subscription::for_type::<GetInfoResponse>(
+                TransferKind::Response,
+                GET_INFO_SERVICE,
+                self.offline_timeout,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Take in a received transfer. Returns whether it was a heartbeat or `GetInfo`
+    /// response meant for the tracker, malformed or not.
+    pub fn handle(&mut self, transfer: &RefTransfer<C>) -> bool {
+        let metadata = &transfer.metadata;
+        let node_id = match (metadata.transfer_kind, metadata.port_id) {
+            (TransferKind::Message, HEARTBEAT_SUBJECT) => match metadata.remote_node_id {
+                Some(node_id) => node_id,
+                // Anonymous nodes can't be told apart
+                None => return true,
+            },
+            (TransferKind::Response, GET_INFO_SERVICE) => {
+                if let Some(node_id) = metadata.remote_node_id {
+                    self.handle_info(node_id, metadata.transfer_id, transfer.payload);
+                }
+                return true;
+            }
+            _ => return false,
+        };
+        let heartbeat = match Heartbeat::deserialize(transfer.payload) {
+            Ok(heartbeat) => heartbeat,
+            Err(_) => return true,
+        };
+
+        let status = match self.nodes.get_mut(&node_id) {
+            Some(status) => status,
+            None => {
+                self.nodes.insert(
+                    node_id,
+                    NodeStatus {
+                        last_seen: metadata.timestamp,
+                        uptime: heartbeat.uptime,
+                        health: heartbeat.health,
+                        mode: heartbeat.mode,
+                        vendor_specific_status_code: heartbeat.vendor_specific_status_code,
+                        info: None,
+                        info_requested: None,
+                        info_pending: None,
+                        info_transfer_id: 0,
+                    },
+                );
+                self.push_event(NodeEvent::Appeared(node_id));
+                return true;
+            }
+        };
+
+        let restarted = heartbeat.uptime < status.uptime;
+        if restarted {
+            status.info = None;
+            status.info_requested = None;
+            status.info_pending = None;
+        }
+        let health = status.health;
+        status.last_seen = metadata.timestamp;
+        status.uptime = heartbeat.uptime;
+        status.health = heartbeat.health;
+        status.mode = heartbeat.mode;
+        status.vendor_specific_status_code = heartbeat.vendor_specific_status_code;
+
+        if restarted {
+            self.push_event(NodeEvent::Restarted(node_id));
+        }
+        if heartbeat.health != health {
+            self.push_event(NodeEvent::HealthChanged {
+                node_id,
+                from: health,
+                to: heartbeat.health,
+            });
+        }
+        true
+    }
+
+    /// Take in a `GetInfo` response, if it answers the request still outstanding.
+    /// Late and duplicate responses are dropped.
+    fn handle_info(&mut self, node_id: NodeId, transfer_id: TransferId, payload: &[u8]) {
+        let status = match self.nodes.get_mut(&node_id) {
+            Some(status) => status,
+            None => return,
+        };
+        if status.info_pending != Some(transfer_id) {
+            return;
+        }
+        if let Ok(info) = GetInfoResponse::deserialize(payload) {
+            status.info = Some(info);
+            status.info_pending = None;
+            self.push_event(NodeEvent::InfoReceived(node_id));
+        }
+    }
+
+    fn push_event(&mut self, event: NodeEvent) {
+        if self.events.push_back(event).is_err() {
+            self.events_dropped = self.events_dropped.wrapping_add(1);
+        }
+    }
+
+    /// Drop nodes that haven't sent a heartbeat within the offline timeout.
+    ///
+    /// Call this periodically, e.g. once a second, with the current time.
+    pub fn update(&mut self, now: Timestamp<C>) {
+        let timeout = self.offline_timeout;
+        let events = &mut self.events;
+        let events_dropped = &mut self.events_dropped;
+        self.nodes.retain(|node_id, status| {
+            let expired = timestamp_expired(timeout, now, Some(status.last_seen));
+            if expired && events.push_back(NodeEvent::Disappeared(*node_id)).is_err() {
+                *events_dropped = events_dropped.wrapping_add(1);
+            }
+            !expired
+        });
+    }
+
+    /// Next event that happened, oldest first.
+    pub fn next_event(&mut self) -> Option<NodeEvent> {
+        self.events.pop_front()
+    }
+
+    /// Events dropped because `E` of them were already waiting for `next_event`.
+    pub fn events_dropped(&self) -> usize {
+        self.events_dropped
+    }
+
+    /// `GetInfo` request to send over transport `T`, if info requests are on and a node
+    /// still hasn't answered one. A node is asked again if it hasn't answered within
+    /// the offline timeout.
+    pub fn info_request<T: Transport<C>>(
+        &mut self,
+        now: Timestamp<C>,
+    ) -> Option<RefTransfer<'static, C>> {
+        if !self.query_info {
+            return None;
+        }
+        let timeout = self.offline_timeout;
+        let (node_id, status) = self.nodes.iter_mut().find(|(_, status)| {
+            status.info.is_none()
+                && (status.info_requested.is_none()
+                    || timestamp_expired(timeout, now, status.info_requested))
+        })?;
+
+        let transfer_id = status.info_transfer_id;
+        let next_transfer_id = (transfer_id as usize + 1) % T::TRANSFER_ID_MODULO;
+        status.info_transfer_id = next_transfer_id as TransferId;
+        status.info_requested = Some(now);
+        status.info_pending = Some(transfer_id);
+        Some(RefTransfer {
+            metadata: TransferMetadata {
+                timestamp: now,
+                priority: Priority::Nominal,
+                transfer_kind: TransferKind::Request,
+                port_id: GET_INFO_SERVICE,
+                remote_node_id: Some(*node_id),
+                transfer_id,
+            },
+            payload: &[],
+        })
+    }
+
+    /// Status of a node, if it's online.
+    pub fn get(&self, node_id: NodeId) -> Option<&NodeStatus<C>> {
+        self.nodes.get(&node_id)
+    }
+
+    /// Every online node, in order of node ID.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &NodeStatus<C>)> {
+        self.nodes
+            .iter()
+            .map(|(node_id, status)| (*node_id, status))
+    }
+}
+
+impl<C: embedded_time::Clock, const E: usize> Default for NodeTracker<C, E> {
+    fn default() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            events: heapless::Deque::new(),
+            events_dropped: 0,
+            offline_timeout: Milliseconds(Heartbeat::OFFLINE_TIMEOUT),
+            query_info: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::collections::VecDeque;
+
+    use embedded_time::Clock;
+
+    use super::*;
+    use crate::serialization::{Buffer, Serialize};
+    use crate::testing::{TestNode, TestSessionManager};
+    use crate::time::TestClock;
+    use crate::transport::can::{Can, CanFrame};
+
+    fn heartbeat(uptime: u32, health: Health) -> [u8; 7] {
+        let mut buffer = <Heartbeat as Serialize>::Buffer::zeroed();
+        Heartbeat {
+            uptime,
+            health,
+            mode: Mode::Operational,
+            vendor_specific_status_code: 0,
+        }
+        .serialize(&mut buffer);
+        buffer
+    }
+
+    fn transfer<'a>(
+        clock: &TestClock,
+        kind: TransferKind,
+        port_id: PortId,
+        source: NodeId,
+        payload: &'a [u8],
+    ) -> RefTransfer<'a, TestClock> {
+        RefTransfer {
+            metadata: TransferMetadata {
+                timestamp: clock.try_now().unwrap(),
+                priority: Priority::Nominal,
+                transfer_kind: kind,
+                port_id,
+                remote_node_id: Some(source),
+                transfer_id: 0,
+            },
+            payload,
+        }
+    }
+
+    fn events(tracker: &mut NodeTracker<TestClock>) -> alloc::vec::Vec<NodeEvent> {
+        core::iter::from_fn(|| tracker.next_event()).collect()
+    }
+
+    #[test]
+    fn node_lifecycle() {
+        let mut clock = TestClock::default();
+        let mut tracker = NodeTracker::new();
+        let beat = |clock: &TestClock, tracker: &mut NodeTracker<_>, node, uptime, health| {
+            let payload = heartbeat(uptime, health);
+            let transfer = transfer(
+                clock,
+                TransferKind::Message,
+                HEARTBEAT_SUBJECT,
+                node,
+                &payload,
+            );
+            assert!(tracker.handle(&transfer));
+        };
+
+        beat(&clock, &mut tracker, 10, 5, Health::Nominal);
+        beat(&clock, &mut tracker, 11, 0, Health::Nominal);
+        assert_eq!(
+            events(&mut tracker),
+            [NodeEvent::Appeared(10), NodeEvent::Appeared(11)]
+        );
+
+        clock.add_duration(&Milliseconds(1000u32)).unwrap();
+        beat(&clock, &mut tracker, 10, 6, Health::Caution);
+        beat(&clock, &mut tracker, 11, 1, Health::Nominal);
+        assert_eq!(
+            events(&mut tracker),
+            [NodeEvent::HealthChanged {
+                node_id: 10,
+                from: Health::Nominal,
+                to: Health::Caution,
+            }]
+        );
+        let status = tracker.get(10).unwrap();
+        assert_eq!(status.uptime, 6);
+        assert_eq!(status.health, Health::Caution);
+
+        // Uptime going backwards is a restart
+        clock.add_duration(&Milliseconds(1000u32)).unwrap();
+        beat(&clock, &mut tracker, 10, 0, Health::Caution);
+        assert_eq!(events(&mut tracker), [NodeEvent::Restarted(10)]);
+
+        // Node 11 has been quiet for 3.5 s now, node 10 for 2.5 s
+        clock.add_duration(&Milliseconds(2500u32)).unwrap();
+        tracker.update(clock.try_now().unwrap());
+        assert_eq!(events(&mut tracker), [NodeEvent::Disappeared(11)]);
+        assert!(tracker.get(11).is_none());
+        assert_eq!(
+            tracker
+                .nodes()
+                .map(|(node_id, _)| node_id)
+                .collect::<alloc::vec::Vec<_>>(),
+            [10]
+        );
+
+        // And back again
+        beat(&clock, &mut tracker, 11, 5, Health::Warning);
+        assert_eq!(events(&mut tracker), [NodeEvent::Appeared(11)]);
+
+        // Other transfers are left alone, anonymous and malformed heartbeats are dropped
+        let payload = [0; 7];
+        assert!(!tracker.handle(&transfer(&clock, TransferKind::Message, 100, 10, &payload)));
+        let mut anonymous = transfer(
+            &clock,
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            10,
+            &payload,
+        );
+        anonymous.metadata.remote_node_id = None;
+        assert!(tracker.handle(&anonymous));
+        let invalid_mode = [0, 0, 0, 0, 0, 7];
+        assert!(tracker.handle(&transfer(
+            &clock,
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            12,
+            &invalid_mode
+        )));
+        assert!(events(&mut tracker).is_empty());
+    }
+
+    #[test]
+    fn info_requests() {
+        let mut clock = TestClock::default();
+        let mut tracker = NodeTracker::new().with_info_requests();
+        let payload = heartbeat(5, Health::Nominal);
+        tracker.handle(&transfer(
+            &clock,
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            10,
+            &payload,
+        ));
+
+        let request = tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .unwrap();
+        assert_eq!(request.metadata.transfer_kind, TransferKind::Request);
+        assert_eq!(request.metadata.port_id, GET_INFO_SERVICE);
+        assert_eq!(request.metadata.remote_node_id, Some(10));
+        assert_eq!(request.metadata.transfer_id, 0);
+        assert!(request.payload.is_empty());
+
+        // Not asked again until the request times out
+        assert!(tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .is_none());
+        clock.add_duration(&Milliseconds(3500u32)).unwrap();
+        let request = tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .unwrap();
+        assert_eq!(request.metadata.transfer_id, 1);
+
+        let info = GetInfoResponse {
+            name: heapless::Vec::from_slice(b"org.example.node").unwrap(),
+            ..Default::default()
+        };
+        let mut buffer = <GetInfoResponse as Serialize>::Buffer::zeroed();
+        let len = info.serialize(&mut buffer);
+        let mut response = transfer(
+            &clock,
+            TransferKind::Response,
+            GET_INFO_SERVICE,
+            10,
+            &buffer[..len],
+        );
+
+        // A late response to the first request doesn't answer the second one
+        assert!(tracker.handle(&response));
+        assert_eq!(events(&mut tracker), [NodeEvent::Appeared(10)]);
+        assert!(tracker.get(10).unwrap().info.is_none());
+
+        response.metadata.transfer_id = 1;
+        assert!(tracker.handle(&response));
+        assert_eq!(events(&mut tracker), [NodeEvent::InfoReceived(10)]);
+        assert_eq!(tracker.get(10).unwrap().info.as_ref(), Some(&info));
+        assert!(tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .is_none());
+
+        // Nor is a duplicate taken once the request has been answered
+        assert!(tracker.handle(&response));
+        assert!(events(&mut tracker).is_empty());
+
+        // Restarting forgets the info, so it's fetched again
+        let payload = heartbeat(0, Health::Nominal);
+        tracker.handle(&transfer(
+            &clock,
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            10,
+            &payload,
+        ));
+        assert!(tracker.get(10).unwrap().info.is_none());
+        let request = tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .unwrap();
+        assert_eq!(request.metadata.transfer_id, 2);
+
+        // Responses from unknown nodes are ignored
+        let response = transfer(
+            &clock,
+            TransferKind::Response,
+            GET_INFO_SERVICE,
+            20,
+            &buffer[..len],
+        );
+        assert!(tracker.handle(&response));
+        assert!(tracker.get(20).is_none());
+
+        // A response to a request made before the restart is dropped too
+        let mut response = transfer(
+            &clock,
+            TransferKind::Response,
+            GET_INFO_SERVICE,
+            10,
+            &buffer[..len],
+        );
+        response.metadata.transfer_id = 1;
+        assert!(tracker.handle(&response));
+        assert!(tracker.get(10).unwrap().info.is_none());
+        response.metadata.transfer_id = 2;
+        assert!(tracker.handle(&response));
+        assert_eq!(tracker.get(10).unwrap().info.as_ref(), Some(&info));
+
+        // Without info requests turned on, nothing is asked
+        let mut tracker = NodeTracker::new();
+        tracker.handle(&transfer(
+            &clock,
+            TransferKind::Message,
+            HEARTBEAT_SUBJECT,
+            10,
+            &payload,
+        ));
+        assert!(tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn events_bounded() {
+        let clock = TestClock::default();
+        let mut tracker = NodeTracker::<TestClock, 2>::default();
+        let payload = heartbeat(0, Health::Nominal);
+        for node in 10..13 {
+            tracker.handle(&transfer(
+                &clock,
+                TransferKind::Message,
+                HEARTBEAT_SUBJECT,
+                node,
+                &payload,
+            ));
+        }
+
+        // The third node is still tracked, only its event is lost
+        assert_eq!(tracker.events_dropped(), 1);
+        assert!(tracker.get(12).is_some());
+        assert_eq!(tracker.next_event(), Some(NodeEvent::Appeared(10)));
+        assert_eq!(tracker.next_event(), Some(NodeEvent::Appeared(11)));
+        assert_eq!(tracker.next_event(), None);
+    }
+
+    #[test]
+    fn heartbeats_through_node() {
+        let clock = TestClock::default();
+        let tracker_node = TestNode::new(Some(1), TestSessionManager::new());
+        let mut tracker = NodeTracker::new().with_info_requests();
+        let mut node = tracker_node;
+        tracker.subscribe(&mut node.sessions).unwrap();
+        assert!(matches!(
+            tracker.subscribe(&mut node.sessions),
+            Err(SubscriptionError::SubscriptionExists)
+        ));
+
+        let mut publisher = TestNode::new(Some(42), TestSessionManager::new());
+        let mut bus: VecDeque<CanFrame<TestClock>> = VecDeque::new();
+        let heartbeat = Heartbeat {
+            uptime: 100,
+            health: Health::Advisory,
+            mode: Mode::Initialization,
+            vendor_specific_status_code: 3,
+        };
+        publisher
+            .publish(
+                clock.try_now().unwrap(),
+                Priority::Nominal,
+                HEARTBEAT_SUBJECT,
+                0,
+                &heartbeat,
+                |frame| bus.push_back(frame.clone()),
+            )
+            .unwrap();
+
+        while let Some(transfer) = node.receive_from(&mut bus).unwrap() {
+            assert!(tracker.handle(&transfer));
+        }
+        assert_eq!(tracker.next_event(), Some(NodeEvent::Appeared(42)));
+        let status = tracker.get(42).unwrap();
+        assert_eq!(status.uptime, 100);
+        assert_eq!(status.health, Health::Advisory);
+        assert_eq!(status.mode, Mode::Initialization);
+        assert_eq!(status.vendor_specific_status_code, 3);
+
+        // The GetInfo request goes out through the node like any other transfer
+        let request = tracker
+            .info_request::<Can>(clock.try_now().unwrap())
+            .unwrap();
+        node.transmit_to(&request, &mut bus).unwrap();
+        assert_eq!(bus.len(), 1);
+    }
+}
@@ -2,7 +2,12 @@
 //! which boils down to some metadata to uniquely identify it, as well
 //! as a serialized buffer of data, which encodes DSDL-based data.
 
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
 use crate::internal::InternalRxFrame;
+use crate::serialization::{Deserialize, DeserializeError};
 use crate::time::Timestamp;
 use crate::types::*;
 use crate::Priority;
@@ -15,6 +20,16 @@ pub enum TransferKind {
     Request,
 }
 
+impl core::fmt::Display for TransferKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            TransferKind::Message => "message",
+            TransferKind::Response => "response",
+            TransferKind::Request => "request",
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct TransferMetadata<C: embedded_time::Clock> {
     // for tx -> transmission_timeout
@@ -28,7 +43,12 @@ pub struct TransferMetadata<C: embedded_time::Clock> {
 
 pub trait Transfer<'a, C: embedded_time::Clock> {
     fn metadata(&'a self) -> &'a TransferMetadata<C>;
-    fn payload(&self) -> &'a [u8];
+    fn payload(&'a self) -> &'a [u8];
+
+    /// Deserialize the payload as a data type.
+    fn deserialize<M: Deserialize>(&'a self) -> Result<M, DeserializeError> {
+        M::deserialize(self.payload())
+    }
 }
 
 /// Application representation of a UAVCAN transfer.
@@ -60,23 +80,148 @@ impl<'a, C: embedded_time::Clock> RefTransfer<'a, C> {
             payload,
         }
     }
+
+    /// Copy the payload into owned storage, so the transfer no longer borrows the
+    /// session manager. Returns `None` if the payload doesn't fit.
+    pub fn into_owned<B: PayloadStorage>(self) -> Option<OwnedTransfer<C, B>> {
+        self.into_owned_with(B::copy_from)
+    }
+
+    /// Copy the payload into storage provided by `alloc`, e.g. a block from a memory pool.
+    ///
+    /// `alloc` is given the payload and should return storage containing a copy of it,
+    /// or `None` if it can't.
+    pub fn into_owned_with<B, F>(self, alloc: F) -> Option<OwnedTransfer<C, B>>
+    where
+        B: AsRef<[u8]>,
+        F: FnOnce(&[u8]) -> Option<B>,
+    {
+        Some(OwnedTransfer {
+            payload: alloc(self.payload)?,
+            metadata: self.metadata,
+        })
+    }
 }
 
 impl<'a, C: embedded_time::Clock> Transfer<'a, C> for RefTransfer<'a, C> {
-    fn metadata(&'a self) -> &'a TransferMetadata<C> { &self.metadata }
-    fn payload(&self) -> &'a [u8] { self.payload }
+    fn metadata(&'a self) -> &'a TransferMetadata<C> {
+        &self.metadata
+    }
+    fn payload(&'a self) -> &'a [u8] {
+        self.payload
+    }
+}
+
+/// Storage a received payload can be copied into.
+///
+/// Implemented for `Vec<u8>` and `heapless::Vec<u8, N>`. Implement it for pool
+/// blocks or other custom buffers to use them with `RefTransfer::into_owned`.
+pub trait PayloadStorage: AsRef<[u8]> + Sized {
+    /// Create storage holding a copy of `payload`, or `None` if it doesn't fit.
+    fn copy_from(payload: &[u8]) -> Option<Self>;
+}
+
+impl PayloadStorage for Vec<u8> {
+    fn copy_from(payload: &[u8]) -> Option<Self> {
+        Some(payload.to_vec())
+    }
+}
+
+impl<const N: usize> PayloadStorage for heapless::Vec<u8, N> {
+    fn copy_from(payload: &[u8]) -> Option<Self> {
+        heapless::Vec::from_slice(payload).ok()
+    }
+}
+
+/// Transfer which owns its payload, stored in `B`.
+///
+/// Unlike `RefTransfer`, this can be kept around (e.g. in a queue for later processing)
+/// while the node keeps receiving.
+#[derive(Debug)]
+pub struct OwnedTransfer<C: embedded_time::Clock, B: AsRef<[u8]>> {
+    pub metadata: TransferMetadata<C>,
+    pub payload: B,
+}
+
+impl<'a, C: embedded_time::Clock> From<RefTransfer<'a, C>> for OwnedTransfer<C, Vec<u8>> {
+    fn from(transfer: RefTransfer<'a, C>) -> Self {
+        Self {
+            payload: transfer.payload.to_vec(),
+            metadata: transfer.metadata,
+        }
+    }
+}
+
+impl<'a, C: embedded_time::Clock, B: AsRef<[u8]>> Transfer<'a, C> for OwnedTransfer<C, B> {
+    fn metadata(&'a self) -> &'a TransferMetadata<C> {
+        &self.metadata
+    }
+    fn payload(&'a self) -> &'a [u8] {
+        self.payload.as_ref()
+    }
 }
 
+/// Completed transfer lent out by a session manager.
+///
+/// The payload stays in the session's own buffer, so nothing is copied, but unlike
+/// `RefTransfer` the lease doesn't borrow the session manager and can be sent to
+/// another task. The session it came from is busy until the lease is dropped, and
+/// rejects any further frames with `SessionError::Leased`.
+///
+/// Only available on targets with atomic pointers, which the shared buffer needs.
+#[cfg(target_has_atomic = "ptr")]
+#[derive(Debug)]
+pub struct TransferLease<C: embedded_time::Clock> {
+    pub metadata: TransferMetadata<C>,
+    payload: Arc<Vec<u8>>,
+}
 
-/// Experimental extra transfer type to 
+#[cfg(target_has_atomic = "ptr")]
+impl<C: embedded_time::Clock> TransferLease<C> {
+    /// Lease out a session buffer. The session counts as busy for as long as `payload`
+    /// is shared, which can be checked with `Arc::strong_count`.
+    pub fn from_frame(
+        frame: InternalRxFrame<C>,
+        timestamp: Timestamp<C>,
+        payload: Arc<Vec<u8>>,
+    ) -> Self {
+        Self {
+            metadata: TransferMetadata {
+                timestamp,
+                priority: frame.priority,
+                transfer_kind: frame.transfer_kind,
+                port_id: frame.port_id,
+                remote_node_id: frame.source_node_id,
+                transfer_id: frame.transfer_id,
+            },
+            payload,
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<'a, C: embedded_time::Clock> Transfer<'a, C> for TransferLease<C> {
+    fn metadata(&'a self) -> &'a TransferMetadata<C> {
+        &self.metadata
+    }
+    fn payload(&'a self) -> &'a [u8] {
+        &self.payload
+    }
+}
+
+/// Experimental extra transfer type to
+#[deprecated(note = "unsound, use `TransferLease` from `Node::try_receive_frame_leased` instead")]
 pub struct ManagedTransfer<C: embedded_time::Clock> {
     pub metadata: TransferMetadata<C>,
     payload: *mut [u8],
     callback: alloc::boxed::Box<dyn Fn()>,
 }
 
+#[allow(deprecated)]
 impl<C: embedded_time::Clock> ManagedTransfer<C> {
-    /// SAFETY: any caller *must* ensure that the payload object lives at least until
+    /// # Safety
+    ///
+    /// Any caller *must* ensure that the payload object lives at least until
     /// callback has returned. This is *very* hard to enforce, because synchronization
     /// primitives will always exit their critical sections before the callback returns,
     /// leaving a small gap. Please don't use this.
@@ -86,7 +231,13 @@ impl<C: embedded_time::Clock> ManagedTransfer<C> {
     /// return to the task calling the closure. This may be true for any async stuff running in the
     /// same executor, but I would have to spend time to verify that.
     // TODO probably shouldn't need 'static here
-    pub unsafe fn from_ref_transfer(transfer: RefTransfer<C>, callback: alloc::boxed::Box<dyn Fn()>) -> Self {
+    #[deprecated(
+        note = "unsound, use `TransferLease` from `Node::try_receive_frame_leased` instead"
+    )]
+    pub unsafe fn from_ref_transfer(
+        transfer: RefTransfer<'_, C>,
+        callback: alloc::boxed::Box<dyn Fn()>,
+    ) -> Self {
         ManagedTransfer {
             metadata: TransferMetadata {
                 timestamp: transfer.metadata.timestamp,
@@ -97,19 +248,23 @@ impl<C: embedded_time::Clock> ManagedTransfer<C> {
                 transfer_id: transfer.metadata.transfer_id,
             },
             // Unsafe bit, "upgrading lifetime"
-            payload: core::mem::transmute(transfer.payload),
-            callback: callback,
+            payload: transfer.payload as *const [u8] as *mut [u8],
+            callback,
         }
     }
 }
 
+#[allow(deprecated)]
 impl<'a, C: embedded_time::Clock> Transfer<'a, C> for ManagedTransfer<C> {
-    fn metadata(&'a self) -> &'a TransferMetadata<C> { &self.metadata }
-    fn payload(&self) -> &'a [u8] {
-        unsafe { core::mem::transmute(self.payload) }
+    fn metadata(&'a self) -> &'a TransferMetadata<C> {
+        &self.metadata
+    }
+    fn payload(&'a self) -> &'a [u8] {
+        unsafe { &*self.payload }
     }
 }
 
+#[allow(deprecated)]
 impl<C: embedded_time::Clock> Drop for ManagedTransfer<C> {
     fn drop(&mut self) {
         (self.callback)();
@@ -6,12 +6,13 @@ use embedded_time::Clock;
 use num_traits::FromPrimitive;
 
 use super::bitfields::*;
+use super::FdCanFrame;
 use crate::crc16::Crc16;
 use crate::internal::InternalRxFrame;
 use crate::time::Timestamp;
 use crate::transport::Transport;
 use crate::StreamingIterator;
-use crate::{NodeId, Priority, RxError, TransferKind, TxError};
+use crate::{NodeId, Priority, RxError, RxErrorKind, TransferKind, TxError};
 use crate::transfer::Transfer;
 
 /// Unit struct for declaring transport type
@@ -32,7 +33,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for FdCan {
         // NOTE: libcanard specifies this as only for multi-frame transfers but uses
         // this logic.
         if frame.payload.is_empty() {
-            return Err(RxError::FrameEmpty);
+            return Err(RxErrorKind::FrameEmpty.into());
         }
 
         // Pull tail byte from payload
@@ -40,11 +41,11 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for FdCan {
 
         // Protocol version states SOT must have toggle set
         if tail_byte.start_of_transfer() && !tail_byte.toggle() {
-            return Err(RxError::TransferStartMissingToggle);
+            return Err(RxErrorKind::TransferStartMissingToggle.into());
         }
         // Non-last frames must use the MTU fully
         if !tail_byte.end_of_transfer() && frame.payload.len() < <Self as Transport<C>>::MTU_SIZE {
-            return Err(RxError::NonLastUnderUtilization);
+            return Err(RxErrorKind::NonLastUnderUtilization.into());
         }
 
         if CanServiceId(frame.id.as_raw()).is_svc() {
@@ -53,7 +54,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for FdCan {
 
             // Ignore invalid frames
             if !id.valid() {
-                return Err(RxError::InvalidCanId);
+                return Err(RxErrorKind::InvalidCanId.into());
             }
 
             // Ignore frames not meant for us
@@ -87,7 +88,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for FdCan {
             let source_node_id = if id.is_anon() {
                 // Anonymous transfers can only be single-frame transfers
                 if !(tail_byte.start_of_transfer() && tail_byte.end_of_transfer()) {
-                    return Err(RxError::AnonNotSingleFrame);
+                    return Err(RxErrorKind::AnonNotSingleFrame.into());
                 }
 
                 None
@@ -96,7 +97,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for FdCan {
             };
 
             if !id.valid() {
-                return Err(RxError::InvalidCanId);
+                return Err(RxErrorKind::InvalidCanId.into());
             }
 
             return Ok(Some(InternalRxFrame::as_message(
@@ -365,26 +366,3 @@ impl<'a, C: Clock, X: Transfer<'a, C>> StreamingIterator for FdCanIter<'a, C, X>
         (frames, Some(frames))
     }
 }
-
-// TODO convert to embedded-hal PR type
-/// Extended CAN FD frame (the only one supported by UAVCAN/CAN)
-#[derive(Clone, Debug)]
-pub struct FdCanFrame<C: embedded_time::Clock> {
-    pub timestamp: Timestamp<C>,
-    pub id: ExtendedId,
-    // Seperate here because there are extra semantics around CAN-FD DLC
-    pub dlc: u8,
-    pub payload: ArrayVec<[u8; 64]>,
-}
-
-impl<C: embedded_time::Clock> FdCanFrame<C> {
-    fn new(timestamp: Timestamp<C>, id: u32) -> Self {
-        Self {
-            timestamp,
-            // TODO get rid of this expect, it probably isn't necessary, just added quickly
-            id: ExtendedId::new(id).expect("invalid ID"),
-            dlc: 0,
-            payload: ArrayVec::<[u8; 64]>::new(),
-        }
-    }
-}
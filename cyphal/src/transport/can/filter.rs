@@ -0,0 +1,273 @@
+//! Hardware acceptance filter configuration.
+//!
+//! CAN controllers can drop frames a node isn't interested in before they reach
+//! the CPU, by matching the extended ID against a handful of mask/ID pairs.
+//! `make_filters` builds those pairs from the subscriptions of a session manager.
+//!
+//! Controllers only have a few filter banks, so when there are more subscriptions
+//! than banks, filters are merged pairwise, each time picking the pair whose merge
+//! keeps the most mask bits. A merged filter lets some extra frames through, which
+//! the session manager then discards as usual.
+
+use alloc::vec::Vec;
+
+use super::bitfields::{CanMessageId, CanServiceId};
+use crate::session::SessionManager;
+use crate::types::*;
+use crate::TransferKind;
+
+/// Extended ID acceptance filter. A frame is accepted if `frame_id & mask == id & mask`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CanFilter {
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl CanFilter {
+    /// Filter accepting messages on `subject`, optionally including anonymous ones.
+    pub fn for_subject(subject: PortId, anonymous: bool) -> Self {
+        let mut id = CanMessageId(0);
+        id.set_subject_id(subject);
+
+        let mut mask = CanMessageId(0);
+        mask.set_svc(true);
+        mask.set_anon(!anonymous);
+        mask.set_rsvd0(true);
+        mask.set_rsvd3(true);
+        mask.set_subject_id(0x1FFF);
+
+        Self::new(id.0, mask.0)
+    }
+
+    /// Filter accepting requests or responses of `service` addressed to `local_node_id`.
+    ///
+    /// # Panics
+    ///
+    /// If `transfer_kind` is `TransferKind::Message`.
+    pub fn for_service(
+        transfer_kind: TransferKind,
+        service: PortId,
+        local_node_id: NodeId,
+    ) -> Self {
+        assert!(transfer_kind != TransferKind::Message, "not a service");
+
+        let mut id = CanServiceId(0);
+        id.set_svc(true);
+        id.set_req(transfer_kind == TransferKind::Request);
+        id.set_service_id(service);
+        id.set_destination_id(local_node_id);
+
+        let mut mask = CanServiceId(0);
+        mask.set_svc(true);
+        mask.set_req(true);
+        mask.set_rsvd0(true);
+        mask.set_service_id(0x1FF);
+        mask.set_destination_id(0x7F);
+
+        Self::new(id.0, mask.0)
+    }
+
+    /// Filter accepting every service transfer addressed to `local_node_id`.
+    pub fn for_services(local_node_id: NodeId) -> Self {
+        let mut id = CanServiceId(0);
+        id.set_svc(true);
+        id.set_destination_id(local_node_id);
+
+        let mut mask = CanServiceId(0);
+        mask.set_svc(true);
+        mask.set_rsvd0(true);
+        mask.set_destination_id(0x7F);
+
+        Self::new(id.0, mask.0)
+    }
+
+    fn new(id: u32, mask: u32) -> Self {
+        Self {
+            id: id & mask,
+            mask,
+        }
+    }
+
+    /// Smallest filter accepting everything both filters accept.
+    pub fn merge(&self, other: &Self) -> Self {
+        Self::new(self.id, self.mask & other.mask & !(self.id ^ other.id))
+    }
+
+    /// Does this filter let a frame with the extended ID `id` through?
+    pub fn accepts(&self, id: u32) -> bool {
+        id & self.mask == self.id
+    }
+}
+
+/// Compile time check that there's room for at least one filter.
+struct AtLeastOne<const N: usize>;
+
+impl<const N: usize> AtLeastOne<N> {
+    const OK: () = assert!(N > 0, "make_filters needs room for at least one filter");
+}
+
+/// Generate at most `N` acceptance filters covering every subscription of `sessions`.
+///
+/// Service subscriptions only match transfers addressed to `node_id`, and are skipped
+/// for anonymous nodes, as those can't take part in service exchanges. `anonymous`
+/// controls whether anonymous messages on subscribed subjects are let through.
+///
+/// `N` must be at least 1, as no filters at all would accept nothing. `N = 0` fails
+/// to compile.
+pub fn make_filters<C, S, const N: usize>(
+    sessions: &S,
+    node_id: Option<NodeId>,
+    anonymous: bool,
+) -> heapless::Vec<CanFilter, N>
+where
+    C: embedded_time::Clock,
+    S: SessionManager<C>,
+{
+    #[allow(clippy::let_unit_value)]
+    let () = AtLeastOne::<N>::OK;
+
+    let mut filters: Vec<CanFilter> = sessions
+        .subscriptions()
+        .filter_map(|sub| match sub.transfer_kind() {
+            TransferKind::Message => Some(CanFilter::for_subject(sub.port_id(), anonymous)),
+            kind => node_id.map(|node_id| CanFilter::for_service(kind, sub.port_id(), node_id)),
+        })
+        .collect();
+
+    while filters.len() > N {
+        // Merge the pair that loses the fewest mask bits, i.e. lets the fewest extra frames in
+        let mut best = (0, 1);
+        let mut best_rank = 0;
+        for i in 0..filters.len() {
+            for j in i + 1..filters.len() {
+                let rank = filters[i].merge(&filters[j]).mask.count_ones();
+                if rank > best_rank {
+                    best = (i, j);
+                    best_rank = rank;
+                }
+            }
+        }
+
+        let (i, j) = best;
+        filters[i] = filters[i].merge(&filters[j]);
+        filters.swap_remove(j);
+    }
+
+    filters.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use embedded_time::duration::Milliseconds;
+
+    use super::*;
+    use crate::testing::TestSessionManager;
+    use crate::time::TestClock;
+    use crate::transport::can::CanMetadata;
+    use crate::{Priority, Subscription};
+
+    fn message_id(subject: PortId, source: Option<NodeId>) -> u32 {
+        CanMessageId::new(Priority::Nominal, subject, source).as_raw()
+    }
+
+    fn service_id(is_request: bool, service: PortId, destination: NodeId) -> u32 {
+        CanServiceId::new(Priority::Fast, is_request, service, destination, 12).as_raw()
+    }
+
+    fn make_sessions(
+        subscriptions: &[(TransferKind, PortId)],
+    ) -> TestSessionManager<CanMetadata, TestClock> {
+        let mut sessions = TestSessionManager::new();
+        for &(kind, port) in subscriptions {
+            sessions
+                .subscribe(Subscription::new(kind, port, 8, Milliseconds(500)))
+                .unwrap();
+        }
+        sessions
+    }
+
+    #[test]
+    fn subject_filter() {
+        let filter = CanFilter::for_subject(7509, false);
+        assert!(filter.accepts(message_id(7509, Some(1))));
+        assert!(filter.accepts(message_id(7509, Some(127))));
+        assert!(!filter.accepts(message_id(7509, None)));
+        assert!(!filter.accepts(message_id(7508, Some(1))));
+        assert!(!filter.accepts(service_id(true, 7509 & 0x1FF, 1)));
+
+        let filter = CanFilter::for_subject(7509, true);
+        assert!(filter.accepts(message_id(7509, None)));
+    }
+
+    #[test]
+    fn service_filter() {
+        let filter = CanFilter::for_service(TransferKind::Request, 430, 42);
+        assert!(filter.accepts(service_id(true, 430, 42)));
+        assert!(!filter.accepts(service_id(false, 430, 42)));
+        assert!(!filter.accepts(service_id(true, 430, 43)));
+        assert!(!filter.accepts(service_id(true, 431, 42)));
+        assert!(!filter.accepts(message_id(430, Some(42))));
+
+        let filter = CanFilter::for_services(42);
+        assert!(filter.accepts(service_id(true, 430, 42)));
+        assert!(filter.accepts(service_id(false, 7, 42)));
+        assert!(!filter.accepts(service_id(false, 7, 41)));
+    }
+
+    #[test]
+    fn one_filter_per_subscription() {
+        let sessions = make_sessions(&[
+            (TransferKind::Message, 100),
+            (TransferKind::Request, 430),
+            (TransferKind::Response, 430),
+        ]);
+
+        let filters = make_filters::<_, _, 8>(&sessions, Some(42), false);
+        assert_eq!(
+            filters.as_slice(),
+            [
+                CanFilter::for_subject(100, false),
+                CanFilter::for_service(TransferKind::Request, 430, 42),
+                CanFilter::for_service(TransferKind::Response, 430, 42),
+            ]
+        );
+
+        // Anonymous nodes can't receive service transfers
+        let filters = make_filters::<_, _, 8>(&sessions, None, false);
+        assert_eq!(filters.as_slice(), [CanFilter::for_subject(100, false)]);
+    }
+
+    #[test]
+    fn filters_merged_to_fit() {
+        let sessions = make_sessions(&[
+            (TransferKind::Message, 100),
+            (TransferKind::Message, 101),
+            (TransferKind::Message, 5000),
+            (TransferKind::Request, 430),
+            (TransferKind::Response, 430),
+        ]);
+
+        let filters = make_filters::<_, _, 3>(&sessions, Some(42), true);
+        assert_eq!(filters.len(), 3);
+        // Closest filters are merged first
+        assert!(filters.contains(&CanFilter::for_subject(5000, true)));
+        let services = CanFilter::for_service(TransferKind::Request, 430, 42)
+            .merge(&CanFilter::for_service(TransferKind::Response, 430, 42));
+        assert!(filters.contains(&services));
+
+        let accepted = |id| filters.iter().any(|f| f.accepts(id));
+        for subject in [100, 101, 5000] {
+            assert!(accepted(message_id(subject, Some(3))));
+            assert!(accepted(message_id(subject, None)));
+        }
+        assert!(accepted(service_id(true, 430, 42)));
+        assert!(accepted(service_id(false, 430, 42)));
+        assert!(!accepted(message_id(200, Some(3))));
+        assert!(!accepted(service_id(true, 430, 41)));
+
+        let filters = make_filters::<_, _, 1>(&sessions, Some(42), true);
+        assert_eq!(filters.len(), 1);
+        assert!(filters[0].accepts(message_id(5000, Some(3))));
+        assert!(filters[0].accepts(service_id(true, 430, 42)));
+    }
+}
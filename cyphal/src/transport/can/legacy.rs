@@ -5,12 +5,11 @@
 //! follow the conventions here.
 //!
 //! Provides a unit struct to create a Node for CAN. This implements the common
-//! transmit function that *must* be implemented by any transport. This can't be a
-//! trait in stable unfortunately because it would require GATs, which won't be stable
-//! for quite a while... :(.
+//! transmit function that *must* be implemented by any transport, through the
+//! `Transport` trait.
 
 use arrayvec::ArrayVec;
-use embedded_hal::can::ExtendedId;
+use embedded_hal::can::{ExtendedId, Id};
 use embedded_time::Clock;
 use num_traits::FromPrimitive;
 
@@ -20,7 +19,7 @@ use crate::internal::InternalRxFrame;
 use crate::time::Timestamp;
 use crate::transport::Transport;
 use crate::StreamingIterator;
-use crate::{NodeId, Priority, RxError, TransferKind, TxError};
+use crate::{NodeId, Priority, RxError, RxErrorKind, TransferKind, TxError};
 use crate::transfer::{Transfer, TransferMetadata};
 
 /// Unit struct for declaring transport type
@@ -32,6 +31,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
     type FrameIter<'a> = CanIter<'a, C>;
 
     const MTU_SIZE: usize = 8;
+    const TRANSFER_ID_MODULO: usize = 32;
 
     fn rx_process_frame<'a>(
         node_id: &Option<NodeId>,
@@ -41,7 +41,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
         // NOTE: libcanard specifies this as only for multi-frame transfers but uses
         // this logic.
         if frame.payload.is_empty() {
-            return Err(RxError::FrameEmpty);
+            return Err(RxErrorKind::FrameEmpty.into());
         }
 
         // Pull tail byte from payload
@@ -49,11 +49,11 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
 
         // Protocol version states SOT must have toggle set
         if tail_byte.start_of_transfer() && !tail_byte.toggle() {
-            return Err(RxError::TransferStartMissingToggle);
+            return Err(frame_error(RxErrorKind::TransferStartMissingToggle, frame));
         }
         // Non-last frames must use the MTU fully
         if !tail_byte.end_of_transfer() && frame.payload.len() < <Self as Transport<C>>::MTU_SIZE {
-            return Err(RxError::NonLastUnderUtilization);
+            return Err(frame_error(RxErrorKind::NonLastUnderUtilization, frame));
         }
 
         if CanServiceId(frame.id.as_raw()).is_svc() {
@@ -62,7 +62,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
 
             // Ignore invalid frames
             if !id.valid() {
-                return Err(RxError::InvalidCanId);
+                return Err(frame_error(RxErrorKind::InvalidCanId, frame));
             }
 
             // Ignore frames not meant for us
@@ -76,7 +76,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
                 TransferKind::Response
             };
 
-            return Ok(Some(InternalRxFrame::as_service(
+            Ok(Some(InternalRxFrame::as_service(
                 frame.timestamp,
                 Priority::from_u8(id.priority()).unwrap(),
                 transfer_kind,
@@ -87,7 +87,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
                 tail_byte.start_of_transfer(),
                 tail_byte.end_of_transfer(),
                 &frame.payload,
-            )));
+            )))
         } else {
             // Handle messages
             let id = CanMessageId(frame.id.as_raw());
@@ -96,7 +96,7 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
             let source_node_id = if id.is_anon() {
                 // Anonymous transfers can only be single-frame transfers
                 if !(tail_byte.start_of_transfer() && tail_byte.end_of_transfer()) {
-                    return Err(RxError::AnonNotSingleFrame);
+                    return Err(frame_error(RxErrorKind::AnonNotSingleFrame, frame));
                 }
 
                 None
@@ -105,10 +105,10 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
             };
 
             if !id.valid() {
-                return Err(RxError::InvalidCanId);
+                return Err(frame_error(RxErrorKind::InvalidCanId, frame));
             }
 
-            return Ok(Some(InternalRxFrame::as_message(
+            Ok(Some(InternalRxFrame::as_message(
                 frame.timestamp,
                 Priority::from_u8(id.priority()).unwrap(),
                 id.subject_id(),
@@ -117,15 +117,43 @@ impl<C: embedded_time::Clock + 'static> Transport<C> for Can {
                 tail_byte.start_of_transfer(),
                 tail_byte.end_of_transfer(),
                 &frame.payload,
-            )));
+            )))
         }
     }
 
     fn transmit<'a, X: Transfer<'a, C>>(
+        node_id: &Option<NodeId>,
         transfer: &'a X,
     ) -> Result<Self::FrameIter<'a>, TxError> {
-        CanIter::new(transfer, Some(1))
+        CanIter::new(transfer, *node_id)
+    }
+}
+
+/// Error about `frame`, with the transfer ID from its tail byte and, if its ID is valid,
+/// the port and source from that.
+fn frame_error<C: Clock>(kind: RxErrorKind, frame: &CanFrame<C>) -> RxError {
+    let mut error = RxError::new(kind);
+    error.transfer_id = frame.payload.last().map(|tail| TailByte(*tail).transfer_id());
+    if kind == RxErrorKind::InvalidCanId {
+        return error;
     }
+
+    let service_id = CanServiceId(frame.id.as_raw());
+    if service_id.is_svc() {
+        error.transfer_kind = Some(if service_id.is_req() {
+            TransferKind::Request
+        } else {
+            TransferKind::Response
+        });
+        error.port_id = Some(service_id.service_id());
+        error.source_node_id = Some(service_id.source_id());
+    } else {
+        let message_id = CanMessageId(frame.id.as_raw());
+        error.transfer_kind = Some(TransferKind::Message);
+        error.port_id = Some(message_id.subject_id());
+        error.source_node_id = (!message_id.is_anon()).then(|| message_id.source_id());
+    }
+    error
 }
 
 /// Iterator type to transmit a transfer.
@@ -224,7 +252,12 @@ impl<'a, C: Clock> StreamingIterator for CanIter<'a, C> {
             return;
         }
 
-        let is_end = bytes_left <= 7;
+        // Multi-frame transfers only end once the CRC fits in as well
+        let is_end = if self.is_start {
+            bytes_left <= 7
+        } else {
+            bytes_left + self.crc_left as usize <= 7
+        };
         let copy_len = core::cmp::min(bytes_left, 7);
 
         // TODO enough to use the transfer timestamp, or need actual timestamp
@@ -266,7 +299,7 @@ impl<'a, C: Clock> StreamingIterator for CanIter<'a, C> {
                     if 7 - bytes_left >= 2 {
                         // Iter doesn't work. Internal type is &u8 but extend
                         // expects u8
-                        frame.payload.extend(crc.into_iter());
+                        frame.payload.extend(crc);
                         self.crc_left = 0;
                     } else {
                         // SAFETY: only written if we have enough space
@@ -323,7 +356,6 @@ impl<'a, C: Clock> StreamingIterator for CanIter<'a, C> {
     }
 }
 
-// TODO convert to embedded-hal PR type
 /// Extended CAN frame (the only one supported by UAVCAN/CAN)
 #[derive(Clone, Debug)]
 pub struct CanFrame<C: embedded_time::Clock> {
@@ -343,57 +375,46 @@ impl<C: embedded_time::Clock> CanFrame<C> {
     }
 }
 
-/// Keeps track of toggle bit and CRC during frame processing.
-#[derive(Debug)]
-pub struct CanMetadata {
-    toggle: bool,
-    crc: Crc16,
-}
+/// Frames built through this trait have a zero timestamp, as it has no notion of time.
+/// Set `timestamp` afterwards if it matters.
+impl<C: embedded_time::Clock> embedded_hal::can::Frame for CanFrame<C> {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let id = match id.into() {
+            Id::Extended(id) => id,
+            Id::Standard(_) => return None,
+        };
 
-impl<C: embedded_time::Clock> crate::transport::SessionMetadata<C> for CanMetadata {
-    fn new() -> Self {
-        Self {
-            // Toggle starts off true, but we compare against the opposite value.
-            toggle: false,
-            crc: Crc16::init(),
-        }
+        let mut payload = ArrayVec::new();
+        payload.try_extend_from_slice(data).ok()?;
+        Some(Self {
+            timestamp: Timestamp::new(C::T::from(0)),
+            id,
+            payload,
+        })
     }
 
-    fn update(&mut self, frame: &crate::internal::InternalRxFrame<C>) -> Option<usize> {
-        // Single frame transfers don't need to be validated
-        if frame.start_of_transfer && frame.end_of_transfer {
-            // Still need to truncate tail byte
-            return Some(frame.payload.len() - 1);
-        }
-
-        // CRC all but the tail byte
-        self.crc.digest(&frame.payload[0..frame.payload.len() - 1]);
-        self.toggle = !self.toggle;
+    /// Remote frames aren't used by Cyphal, so this always fails.
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
 
-        let tail = TailByte(frame.payload[frame.payload.len() - 1]);
+    fn is_extended(&self) -> bool {
+        true
+    }
 
-        if tail.toggle() == self.toggle {
-            if tail.end_of_transfer() {
-                // Exclude CRC from data
-                Some(frame.payload.len() - 3)
-            } else {
-                // Just truncate tail byte
-                Some(frame.payload.len() - 1)
-            }
-        } else {
-            None
-        }
+    fn is_remote_frame(&self) -> bool {
+        false
     }
 
-    fn is_valid(&self, frame: &crate::internal::InternalRxFrame<C>) -> bool {
-        if frame.start_of_transfer && frame.end_of_transfer {
-            return true;
-        }
+    fn id(&self) -> Id {
+        Id::Extended(self.id)
+    }
 
-        if self.crc.get_crc() == 0x0000u16 {
-            return true;
-        }
+    fn dlc(&self) -> usize {
+        self.payload.len()
+    }
 
-        false
+    fn data(&self) -> &[u8] {
+        &self.payload
     }
 }
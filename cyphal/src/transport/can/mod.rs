@@ -5,42 +5,52 @@
 //! follow the conventions here.
 //!
 //! Provides a unit struct to create a Node for CAN. This implements the common
-//! transmit function that *must* be implemented by any transport. This can't be a
-//! trait in stable unfortunately because it would require GATs, which won't be stable
-//! for quite a while... :(.
+//! transmit function that *must* be implemented by any transport, through the
+//! `Transport` trait.
+
+use arrayvec::ArrayVec;
+use embedded_hal::can::ExtendedId;
 
 use crate::crc16::Crc16;
+use crate::time::Timestamp;
 
 mod bitfields;
+pub mod filter;
 // TODO temp uncomment
 //mod fd;
 mod legacy;
 
+#[cfg(test)]
+mod proptests;
+#[cfg(any(test, feature = "testing"))]
+pub mod sim;
 #[cfg(test)]
 mod tests;
 
 // Exports
-pub use bitfields::{CanMessageId, CanServiceId};
+pub use bitfields::{CanMessageId, CanServiceId, TailByte};
 // TODO temp uncomment
 //pub use fd::*;
 pub use legacy::*;
 
-
-use bitfields::TailByte;
-
 /// Keeps track of toggle bit and CRC during frame processing.
 #[derive(Debug)]
 pub struct CanMetadata {
     toggle: bool,
     crc: Crc16,
+    /// Bytes received so far, CRC included.
+    len: usize,
 }
 
-impl<C: embedded_time::Clock> crate::transport::SessionMetadata<C> for CanMetadata {
+impl<C: embedded_time::Clock + 'static> crate::transport::SessionMetadata<C> for CanMetadata {
+    type Transport = Can;
+
     fn new() -> Self {
         Self {
             // Toggle starts off true, but we compare against the opposite value.
             toggle: false,
             crc: Crc16::init(),
+            len: 0,
         }
     }
 
@@ -51,23 +61,21 @@ impl<C: embedded_time::Clock> crate::transport::SessionMetadata<C> for CanMetada
             return Some(frame.payload.len() - 1);
         }
 
-        // CRC all but the tail byte
-        self.crc.digest(&frame.payload[0..frame.payload.len() - 1]);
-        self.toggle = !self.toggle;
-
+        // A repeated toggle bit is a duplicated frame, e.g. retransmitted after a lost
+        // ACK. Only that frame is dropped, if data went missing the CRC catches it.
         let tail = TailByte(frame.payload[frame.payload.len() - 1]);
-
         if tail.toggle() == self.toggle {
-            if tail.end_of_transfer() {
-                // Exclude CRC from data
-                Some(frame.payload.len() - 3)
-            } else {
-                // Just truncate tail byte
-                Some(frame.payload.len() - 1)
-            }
-        } else {
-            None
+            return None;
         }
+        self.toggle = tail.toggle();
+
+        // CRC all but the tail byte
+        let data = &frame.payload[0..frame.payload.len() - 1];
+        self.crc.digest(data);
+        // Just truncate tail byte. The CRC can be split over the last two frames, so
+        // it's only trimmed off once the transfer is complete.
+        self.len += data.len();
+        Some(data.len())
     }
 
     fn is_valid(&self, frame: &crate::internal::InternalRxFrame<C>) -> bool {
@@ -81,4 +89,68 @@ impl<C: embedded_time::Clock> crate::transport::SessionMetadata<C> for CanMetada
 
         false
     }
+
+    fn payload_len(&self, frame: &crate::internal::InternalRxFrame<C>, stored: usize) -> usize {
+        if frame.start_of_transfer && frame.end_of_transfer {
+            return stored;
+        }
+
+        // Only the part of the CRC that wasn't truncated to the extent was stored
+        let truncated = self.len - stored;
+        stored.saturating_sub(2usize.saturating_sub(truncated))
+    }
+}
+
+// TODO convert to embedded-hal PR type
+/// Extended CAN FD frame (the only one supported by UAVCAN/CAN)
+///
+/// The FD transport in `fd.rs` is still a WIP and isn't built, so these can't be
+/// turned into transfers yet. Media drivers can already exchange them.
+#[derive(Clone, Debug)]
+pub struct FdCanFrame<C: embedded_time::Clock> {
+    pub timestamp: Timestamp<C>,
+    pub id: ExtendedId,
+    // Seperate here because there are extra semantics around CAN-FD DLC
+    pub dlc: u8,
+    pub payload: ArrayVec<[u8; 64]>,
+}
+
+impl<C: embedded_time::Clock> FdCanFrame<C> {
+    pub fn new(timestamp: Timestamp<C>, id: u32) -> Self {
+        Self {
+            timestamp,
+            // TODO get rid of this expect, it probably isn't necessary, just added quickly
+            id: ExtendedId::new(id).expect("invalid ID"),
+            dlc: 0,
+            payload: ArrayVec::<[u8; 64]>::new(),
+        }
+    }
+}
+
+/// Payload length of a CAN FD frame with the given DLC code.
+pub fn fd_dlc_to_len(dlc: u8) -> usize {
+    match dlc {
+        0..=8 => dlc as usize,
+        9 => 12,
+        10 => 16,
+        11 => 20,
+        12 => 24,
+        13 => 32,
+        14 => 48,
+        _ => 64,
+    }
+}
+
+/// Smallest CAN FD DLC code that fits `len` bytes of payload.
+pub fn fd_len_to_dlc(len: usize) -> u8 {
+    match len {
+        0..=8 => len as u8,
+        9..=12 => 9,
+        13..=16 => 10,
+        17..=20 => 11,
+        21..=24 => 12,
+        25..=32 => 13,
+        33..=48 => 14,
+        _ => 15,
+    }
 }
@@ -0,0 +1,181 @@
+//! Property tests of CAN transfers: random transfers are split into frames with
+//! `CanIter`, reassembled through `Can::rx_process_frame` and a session manager, and
+//! have to come out unchanged. Corrupted or incomplete transfers must never come out.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use embedded_time::duration::Milliseconds;
+use embedded_time::Clock;
+use num_traits::FromPrimitive;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+use super::{Can, CanFrame, CanIter, CanMetadata};
+use crate::session::SessionManager;
+use crate::testing::TestSessionManager;
+use crate::time::TestClock;
+use crate::transfer::{RefTransfer, TransferMetadata};
+use crate::transport::Transport;
+use crate::types::*;
+use crate::{Priority, StreamingIterator, Subscription, TransferKind};
+
+const SENDER: NodeId = 41;
+const RECEIVER: NodeId = 42;
+const MAX_PAYLOAD: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+struct TestTransfer {
+    kind: TransferKind,
+    priority: Priority,
+    port: PortId,
+    /// Sender of a message, `None` if anonymous.
+    source: Option<NodeId>,
+    transfer_id: TransferId,
+    payload: Vec<u8>,
+}
+
+fn payload() -> impl Strategy<Value = Vec<u8>> {
+    // Single frames, a few frames, and long transfers
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..=7),
+        prop::collection::vec(any::<u8>(), 8..64),
+        prop::collection::vec(any::<u8>(), 64..MAX_PAYLOAD),
+    ]
+}
+
+prop_compose! {
+    fn transfer()(
+        kind in prop_oneof![
+            Just(TransferKind::Message),
+            Just(TransferKind::Request),
+            Just(TransferKind::Response),
+        ],
+        priority in 0..8u8,
+        port in any::<PortId>(),
+        anonymous in any::<bool>(),
+        transfer_id in 0..32u8,
+        payload in payload(),
+    ) -> TestTransfer {
+        let port = match kind {
+            TransferKind::Message => port % 8192,
+            _ => port % 512,
+        };
+        // Only single frame messages can be anonymous
+        let anonymous = anonymous && kind == TransferKind::Message && payload.len() <= 7;
+        TestTransfer {
+            kind,
+            priority: Priority::from_u8(priority).unwrap(),
+            port,
+            source: if anonymous { None } else { Some(SENDER) },
+            transfer_id,
+            payload,
+        }
+    }
+}
+
+/// Transfers protected by a CRC. Single frames have no integrity checks of their
+/// own, only the bus's.
+fn multi_frame_transfer() -> impl Strategy<Value = TestTransfer> {
+    transfer().prop_filter("single frame", |transfer| transfer.payload.len() > 7)
+}
+
+fn segment(transfer: &TestTransfer) -> Vec<CanFrame<TestClock>> {
+    let clock = TestClock::default();
+    let source = transfer.source;
+    let transfer = RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().unwrap(),
+            priority: transfer.priority,
+            transfer_kind: transfer.kind,
+            port_id: transfer.port,
+            remote_node_id: match transfer.kind {
+                TransferKind::Message => None,
+                _ => Some(RECEIVER),
+            },
+            transfer_id: transfer.transfer_id,
+        },
+        payload: &transfer.payload,
+    };
+    let mut iter = CanIter::new(&transfer, source).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = iter.next() {
+        frames.push(frame.clone());
+    }
+    frames
+}
+
+/// Every transfer `RECEIVER` gets out of `frames`, subscribed to `transfer`'s port.
+fn receive(transfer: &TestTransfer, frames: &[CanFrame<TestClock>]) -> Vec<TestTransfer> {
+    let mut sessions = TestSessionManager::<CanMetadata, TestClock>::new();
+    sessions
+        .subscribe(Subscription::new(
+            transfer.kind,
+            transfer.port,
+            MAX_PAYLOAD,
+            Milliseconds(1000),
+        ))
+        .unwrap();
+
+    let mut received = Vec::new();
+    for frame in frames {
+        let frame = match Can::rx_process_frame(&Some(RECEIVER), frame) {
+            Ok(Some(frame)) => frame,
+            _ => continue,
+        };
+        if let Ok(Some(transfer)) = sessions.ingest(frame) {
+            received.push(TestTransfer {
+                kind: transfer.metadata.transfer_kind,
+                priority: transfer.metadata.priority,
+                port: transfer.metadata.port_id,
+                source: transfer.metadata.remote_node_id,
+                transfer_id: transfer.metadata.transfer_id,
+                payload: transfer.payload.to_vec(),
+            });
+        }
+    }
+    received
+}
+
+proptest! {
+    #[test]
+    fn round_trip(transfer in transfer()) {
+        let frames = segment(&transfer);
+        prop_assert_eq!(receive(&transfer, &frames), vec![transfer]);
+    }
+
+    /// Flipping any bit the CRC or toggle bit covers, or the transfer ID, loses
+    /// the transfer.
+    ///
+    /// Flipping a start or end of transfer bit instead turns the transfer into a
+    /// shorter one, which the CRC only catches with a probability of 1 - 2^-16, so
+    /// those bits are left alone. So is the CAN ID, which only the bus protects.
+    #[test]
+    fn corrupted_bit_loses_transfer(
+        transfer in multi_frame_transfer(),
+        frame in any::<Index>(),
+        bit in any::<Index>(),
+    ) {
+        let mut frames = segment(&transfer);
+        let frame = frame.get_mut(&mut frames);
+        let data_bits = (frame.payload.len() - 1) * 8;
+        // Transfer ID and toggle bits of the tail byte
+        let bit = bit.index(data_bits + 6);
+        if bit < data_bits {
+            frame.payload[bit / 8] ^= 1 << (bit % 8);
+        } else {
+            let tail = frame.payload.len() - 1;
+            frame.payload[tail] ^= 1 << (bit - data_bits);
+        }
+
+        prop_assert_eq!(receive(&transfer, &frames), vec![]);
+    }
+
+    #[test]
+    fn dropped_frame_loses_transfer(transfer in multi_frame_transfer(), frame in any::<Index>()) {
+        let mut frames = segment(&transfer);
+        frames.remove(frame.index(frames.len()));
+
+        prop_assert_eq!(receive(&transfer, &frames), vec![]);
+    }
+}
@@ -0,0 +1,515 @@
+//! Discrete-event simulation of CAN buses, for testing nodes against each other.
+//!
+//! A `Simulator` moves a `TestClock` from one frame to the next. Every bus carries
+//! one frame at a time, taking as long as its bits do at the bus's bit rate, and
+//! the lowest CAN ID queued on a bus wins arbitration for it. Nodes queue the
+//! frames of a transfer on each of their buses, and receive every frame the other
+//! nodes on those buses send. As nothing depends on wall time, tests can assert
+//! exact latencies, timeouts and session expiry.
+//!
+//! Frames are timed without bit stuffing, so they are as short as they can be.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use embedded_time::duration::Microseconds;
+use embedded_time::Clock;
+
+use super::{Can, CanFrame};
+use crate::session::SessionManager;
+use crate::time::{TestClock, Timestamp};
+use crate::transfer::{OwnedTransfer, RefTransfer};
+use crate::{Node, RxError, StreamingIterator, TxError};
+
+/// Bits of an extended frame without data: start of frame, arbitration, control,
+/// CRC and acknowledgement fields, end of frame and interframe space.
+const FRAME_OVERHEAD_BITS: u32 = 67;
+/// Bits of an error flag, its delimiter and the interframe space after them.
+const ERROR_FRAME_BITS: u32 = 20;
+
+/// What happens to a frame on the bus.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// Delivered intact.
+    None,
+    /// Destroyed by an error frame once sent, so the sender sends it again.
+    ErrorFrame,
+    /// Lost to every receiver, e.g. to overruns, while the sender thinks it went out.
+    Drop,
+    /// Delivered with a bit of its data flipped, counting from the first byte's LSB
+    /// and wrapping around past the last byte.
+    /// On a real bus the CAN CRC would all but always catch this.
+    FlipBit(usize),
+}
+
+/// Decides what happens to each frame, given the bus it's sent on.
+pub type FaultInjector = Box<dyn FnMut(usize, &CanFrame<TestClock>) -> Fault>;
+
+/// Transfer a simulated node received.
+#[derive(Debug)]
+pub struct Received {
+    /// When its last frame arrived.
+    pub at: Timestamp<TestClock>,
+    pub transfer: OwnedTransfer<TestClock, Vec<u8>>,
+}
+
+/// Node on the simulated buses, with its own transmit queue on each of them.
+pub struct SimNode<S: SessionManager<TestClock>> {
+    pub node: Node<S, Can, TestClock>,
+    /// Buses the node is on, with the frames waiting to go out on each.
+    queues: Vec<(usize, Vec<CanFrame<TestClock>>)>,
+    pub received: Vec<Received>,
+    pub errors: Vec<RxError>,
+}
+
+impl<S: SessionManager<TestClock>> SimNode<S> {
+    /// Frames waiting to be sent, on every bus.
+    pub fn queued(&self) -> usize {
+        self.queues.iter().map(|(_, queue)| queue.len()).sum()
+    }
+}
+
+struct Bus {
+    bit_rate: u32,
+    /// Frame on the wire, if any.
+    transmission: Option<Transmission>,
+}
+
+struct Transmission {
+    node: usize,
+    frame: CanFrame<TestClock>,
+    fault: Fault,
+    end: u32,
+}
+
+/// Simulated CAN buses and the nodes on them.
+pub struct Simulator<S: SessionManager<TestClock>> {
+    clock: TestClock,
+    buses: Vec<Bus>,
+    nodes: Vec<SimNode<S>>,
+    faults: Option<FaultInjector>,
+}
+
+impl<S: SessionManager<TestClock>> Simulator<S> {
+    /// Simulator starting at `clock`'s current time. Nodes should use clones of the
+    /// same clock for their own timestamps.
+    pub fn new(clock: TestClock) -> Self {
+        Self {
+            clock,
+            buses: Vec::new(),
+            nodes: Vec::new(),
+            faults: None,
+        }
+    }
+
+    /// Add a bus running at `bit_rate` bits per second, returning its index.
+    pub fn add_bus(&mut self, bit_rate: u32) -> usize {
+        self.buses.push(Bus {
+            bit_rate,
+            transmission: None,
+        });
+        self.buses.len() - 1
+    }
+
+    /// Add a node on `buses`, returning its index.
+    pub fn add_node(&mut self, node: Node<S, Can, TestClock>, buses: &[usize]) -> usize {
+        self.nodes.push(SimNode {
+            node,
+            queues: buses.iter().map(|bus| (*bus, Vec::new())).collect(),
+            received: Vec::new(),
+            errors: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn node(&self, node: usize) -> &SimNode<S> {
+        &self.nodes[node]
+    }
+
+    pub fn node_mut(&mut self, node: usize) -> &mut SimNode<S> {
+        &mut self.nodes[node]
+    }
+
+    /// Decide the fate of every frame from now on with `faults`.
+    pub fn set_fault_injector(&mut self, faults: FaultInjector) {
+        self.faults = Some(faults);
+    }
+
+    pub fn now(&self) -> Timestamp<TestClock> {
+        self.clock.try_now().unwrap()
+    }
+
+    /// Queue `transfer` on every bus `node` is on.
+    pub fn transmit(
+        &mut self,
+        node: usize,
+        transfer: &RefTransfer<TestClock>,
+    ) -> Result<(), TxError> {
+        let node = &mut self.nodes[node];
+        let mut frames = node.node.transmit(transfer)?;
+        let mut count = 0;
+        while let Some(frame) = frames.next() {
+            for (_, queue) in node.queues.iter_mut() {
+                queue.push(frame.clone());
+            }
+            count += 1;
+        }
+        node.node.count_transmitted(count, transfer.payload.len());
+        Ok(())
+    }
+
+    /// Run the buses for `duration`.
+    pub fn run_for(&mut self, duration: Microseconds) {
+        let end = self.ticks().wrapping_add(duration.0);
+        loop {
+            self.arbitrate();
+            let now = self.ticks();
+            match self.next_end() {
+                Some(next) if next.wrapping_sub(now) <= end.wrapping_sub(now) => {
+                    self.advance_to(next);
+                    self.finish_transmissions();
+                }
+                _ => break,
+            }
+        }
+        self.advance_to(end);
+        self.update_sessions();
+    }
+
+    /// Run the buses until every queued frame has been sent.
+    pub fn run_until_idle(&mut self) {
+        loop {
+            self.arbitrate();
+            match self.next_end() {
+                Some(next) => {
+                    self.advance_to(next);
+                    self.finish_transmissions();
+                }
+                None => break,
+            }
+        }
+        self.update_sessions();
+    }
+
+    fn ticks(&self) -> u32 {
+        self.now().duration_since_epoch().integer()
+    }
+
+    /// When the first transmission in progress ends, if any. Ticks wrap along with
+    /// the clock, so they're compared by how far off they are.
+    fn next_end(&self) -> Option<u32> {
+        let now = self.ticks();
+        self.buses
+            .iter()
+            .filter_map(|bus| bus.transmission.as_ref().map(|t| t.end))
+            .min_by_key(|end| end.wrapping_sub(now))
+    }
+
+    fn advance_to(&mut self, ticks: u32) {
+        let delta = ticks.wrapping_sub(self.ticks());
+        self.clock.add_duration(&Microseconds(delta)).unwrap();
+    }
+
+    /// Start sending the highest priority frame on every idle bus.
+    fn arbitrate(&mut self) {
+        let now = self.ticks();
+        for (index, bus) in self.buses.iter_mut().enumerate() {
+            if bus.transmission.is_some() {
+                continue;
+            }
+
+            // Lowest CAN ID first, and frames with the same one in order
+            let winner = self
+                .nodes
+                .iter()
+                .enumerate()
+                .flat_map(|(node, sim_node)| {
+                    sim_node
+                        .queues
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (on, _))| *on == index)
+                        .flat_map(move |(queue, (_, frames))| {
+                            frames.iter().enumerate().map(move |(position, frame)| {
+                                (frame.id.as_raw(), node, queue, position)
+                            })
+                        })
+                })
+                .min();
+            let (_, node, queue, position) = match winner {
+                Some(winner) => winner,
+                None => continue,
+            };
+
+            let frame = self.nodes[node].queues[queue].1.remove(position);
+            let fault = match self.faults.as_mut() {
+                Some(faults) => faults(index, &frame),
+                None => Fault::None,
+            };
+            let mut bits = FRAME_OVERHEAD_BITS + 8 * frame.payload.len() as u32;
+            if fault == Fault::ErrorFrame {
+                bits += ERROR_FRAME_BITS;
+            }
+            let duration = (bits as u64 * 1_000_000).div_ceil(bus.bit_rate as u64);
+            bus.transmission = Some(Transmission {
+                node,
+                frame,
+                fault,
+                end: now.wrapping_add(duration as u32),
+            });
+        }
+    }
+
+    /// Deliver the frames whose transmission ends now.
+    fn finish_transmissions(&mut self) {
+        let now = self.ticks();
+        for index in 0..self.buses.len() {
+            let transmission = match &self.buses[index].transmission {
+                Some(transmission) if transmission.end == now => {
+                    self.buses[index].transmission.take().unwrap()
+                }
+                _ => continue,
+            };
+
+            let mut frame = transmission.frame;
+            match transmission.fault {
+                Fault::None => {}
+                Fault::ErrorFrame => {
+                    // Back to the front of the sender's queue, to try again
+                    let sender = &mut self.nodes[transmission.node];
+                    let queue = sender
+                        .queues
+                        .iter_mut()
+                        .find(|(on, _)| *on == index)
+                        .unwrap();
+                    queue.1.insert(0, frame);
+                    continue;
+                }
+                Fault::Drop => continue,
+                Fault::FlipBit(bit) => {
+                    if !frame.payload.is_empty() {
+                        let bit = bit % (8 * frame.payload.len());
+                        frame.payload[bit / 8] ^= 1 << (bit % 8);
+                    }
+                }
+            }
+
+            frame.timestamp = self.now();
+            for (node, sim_node) in self.nodes.iter_mut().enumerate() {
+                let on_bus = sim_node.queues.iter().any(|(on, _)| *on == index);
+                if node == transmission.node || !on_bus {
+                    continue;
+                }
+                match sim_node.node.try_receive_frame(frame.clone()) {
+                    Ok(Some(transfer)) => {
+                        let transfer = transfer.into_owned().unwrap();
+                        sim_node.received.push(Received {
+                            at: frame.timestamp,
+                            transfer,
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(err) => sim_node.errors.push(err),
+                }
+            }
+        }
+    }
+
+    /// Expire sessions, as every node would between frames.
+    fn update_sessions(&mut self) {
+        let now = self.now();
+        for sim_node in self.nodes.iter_mut() {
+            sim_node.node.sessions.update_sessions(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use embedded_time::duration::Milliseconds;
+
+    use super::*;
+    use crate::session::SessionError;
+    use crate::testing::TestSessionManager;
+    use crate::transfer::TransferMetadata;
+    use crate::transport::can::CanMetadata;
+    use crate::types::*;
+    use crate::{Priority, RxErrorKind, Subscription, TransferKind};
+
+    type TestSimulator = Simulator<TestSessionManager<CanMetadata, TestClock>>;
+
+    /// Simulator with a bus at `bit_rate`, and nodes 1 to `nodes` on it subscribed
+    /// to subject 100.
+    fn simulator(nodes: NodeId, bit_rate: u32, timeout: u32) -> TestSimulator {
+        let mut sim = Simulator::new(TestClock::default());
+        let bus = sim.add_bus(bit_rate);
+        for id in 1..=nodes {
+            let mut node = Node::new(Some(id), TestSessionManager::new());
+            node.sessions
+                .subscribe(Subscription::new(
+                    TransferKind::Message,
+                    100,
+                    256,
+                    Milliseconds(timeout),
+                ))
+                .unwrap();
+            sim.add_node(node, &[bus]);
+        }
+        sim
+    }
+
+    fn send(
+        sim: &mut TestSimulator,
+        node: usize,
+        priority: Priority,
+        transfer_id: TransferId,
+        payload: &[u8],
+    ) {
+        let transfer = RefTransfer {
+            metadata: TransferMetadata {
+                timestamp: sim.now(),
+                priority,
+                transfer_kind: TransferKind::Message,
+                port_id: 100,
+                remote_node_id: None,
+                transfer_id,
+            },
+            payload,
+        };
+        sim.transmit(node, &transfer).unwrap();
+    }
+
+    fn micros(at: Timestamp<TestClock>) -> u32 {
+        at.duration_since_epoch().integer()
+    }
+
+    #[test]
+    fn frame_timing() {
+        let mut sim = simulator(2, 1_000_000, 1000);
+        send(&mut sim, 0, Priority::Nominal, 0, &[1, 2, 3]);
+        sim.run_for(Microseconds(1000));
+
+        // 4 bytes with the tail byte
+        let received = &sim.node(1).received;
+        assert_eq!(received.len(), 1);
+        assert_eq!(micros(received[0].at), 67 + 4 * 8);
+        assert_eq!(received[0].transfer.payload, vec![1, 2, 3]);
+        // The sender doesn't hear itself
+        assert!(sim.node(0).received.is_empty());
+        assert_eq!(micros(sim.now()), 1000);
+
+        // 20 bytes and the CRC take 4 frames, back to back
+        send(&mut sim, 0, Priority::Nominal, 1, &[0; 20]);
+        sim.run_until_idle();
+        assert_eq!(
+            micros(sim.node(1).received[1].at),
+            1000 + 3 * 131 + 67 + 2 * 8
+        );
+        #[cfg(feature = "statistics")]
+        assert_eq!(sim.node(0).node.statistics().tx.frames, 5);
+    }
+
+    #[test]
+    fn arbitration() {
+        let mut sim = simulator(3, 1_000_000, 1000);
+        send(&mut sim, 0, Priority::Low, 0, &[0; 7]);
+        send(&mut sim, 1, Priority::High, 0, &[1; 7]);
+        sim.run_until_idle();
+
+        // Both queued at once, the higher priority frame goes first
+        let received = &sim.node(2).received;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].transfer.metadata.remote_node_id, Some(2));
+        assert_eq!(micros(received[0].at), 131);
+        assert_eq!(received[1].transfer.metadata.remote_node_id, Some(1));
+        assert_eq!(micros(received[1].at), 2 * 131);
+    }
+
+    #[test]
+    fn error_injection() {
+        let mut sim = simulator(2, 1_000_000, 1000);
+        let mut frames = 0;
+        sim.set_fault_injector(Box::new(move |_, _| {
+            frames += 1;
+            match frames {
+                1 => Fault::ErrorFrame,
+                3 => Fault::Drop,
+                5 => Fault::FlipBit(3),
+                _ => Fault::None,
+            }
+        }));
+
+        // Sent again after the error frame
+        send(&mut sim, 0, Priority::Nominal, 0, &[0; 7]);
+        sim.run_until_idle();
+        let received = &sim.node(1).received;
+        assert_eq!(received.len(), 1);
+        assert_eq!(micros(received[0].at), 131 + 20 + 131);
+
+        // Lost
+        send(&mut sim, 0, Priority::Nominal, 1, &[0; 7]);
+        sim.run_until_idle();
+        assert_eq!(sim.node(1).received.len(), 1);
+
+        // Corrupted in the middle of a transfer, which the transfer CRC catches
+        send(&mut sim, 0, Priority::Nominal, 2, &[0; 20]);
+        sim.run_until_idle();
+        assert_eq!(sim.node(1).received.len(), 1);
+        assert_eq!(sim.node(1).errors.len(), 1);
+        assert_eq!(
+            sim.node(1).errors[0].kind,
+            RxErrorKind::Session(SessionError::CrcMismatch)
+        );
+    }
+
+    #[test]
+    fn flipped_bit_wraps() {
+        let mut sim = simulator(2, 1_000_000, 1000);
+        sim.set_fault_injector(Box::new(|_, _| Fault::FlipBit(4 * 8 + 1)));
+
+        // 4 bytes with the tail byte, so bit 33 is bit 1 of the first byte
+        send(&mut sim, 0, Priority::Nominal, 0, &[0; 3]);
+        sim.run_until_idle();
+        assert_eq!(sim.node(1).received[0].transfer.payload, vec![2, 0, 0]);
+    }
+
+    #[test]
+    fn clock_wrap() {
+        // 50 ms before the clock wraps, and full frames take 13.1 ms at 10 kbit/s
+        let mut sim = simulator(2, 10_000, 1000);
+        sim.clock.add_ticks(u32::MAX - 49_999);
+        send(&mut sim, 0, Priority::Nominal, 0, &[0; 40]);
+        sim.run_until_idle();
+        let received = &sim.node(1).received;
+        assert_eq!(received.len(), 1);
+        assert_eq!(micros(received[0].at), 6 * 13_100 - 50_000);
+
+        send(&mut sim, 0, Priority::Nominal, 1, &[0; 7]);
+        sim.run_for(Microseconds(100_000));
+        assert_eq!(sim.node(1).received.len(), 2);
+        assert_eq!(micros(sim.now()), 6 * 13_100 - 50_000 + 100_000);
+    }
+
+    #[test]
+    fn session_expiry() {
+        // Full frames take 13.1 ms at 10 kbit/s, so 40 bytes and the CRC take 78.6 ms
+        let mut sim = simulator(2, 10_000, 100);
+        send(&mut sim, 0, Priority::Nominal, 0, &[0; 40]);
+        sim.run_until_idle();
+        let received = &sim.node(1).received;
+        assert_eq!(received.len(), 1);
+        assert_eq!(micros(received[0].at), 6 * 13_100);
+
+        // Twice as much is over the 100 ms timeout, so the session expires partway
+        send(&mut sim, 0, Priority::Nominal, 1, &[0; 80]);
+        sim.run_until_idle();
+        assert_eq!(sim.node(1).received.len(), 1);
+        assert!(sim
+            .node(1)
+            .errors
+            .iter()
+            .any(|err| err.kind == RxErrorKind::Session(SessionError::Timeout)));
+    }
+}
@@ -1,4 +1,7 @@
+use alloc::collections::VecDeque;
 use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::time::TestClock;
 use arrayvec::ArrayVec;
@@ -7,7 +10,12 @@ use embedded_time::Clock;
 
 use super::bitfields::TailByte;
 use super::{legacy::*, *};
+use crate::handler::HandlerTable;
 use crate::internal::InternalRxFrame;
+use crate::serialization::{Deserialize, DeserializeError, Serialize};
+use crate::session::SessionManager;
+use crate::testing::{CanFrameBuilder, TestNode, TestSessionManager};
+use crate::transfer::{OwnedTransfer, RefTransfer, Transfer, TransferMetadata};
 use crate::transport::Transport;
 use crate::*;
 
@@ -107,7 +115,7 @@ fn discard_empty_frame() {
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.expect_err("Empty frame did not error out.");
     assert!(
-        matches!(err, RxError::FrameEmpty),
+        matches!(err.kind, RxErrorKind::FrameEmpty),
         "Did not catch empty frame!"
     );
 }
@@ -128,17 +136,17 @@ fn discard_anon_multi_frame() {
     frame.payload.push(TailByte::new(false, true, true, 0).0);
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.unwrap_err();
-    assert!(matches!(err, RxError::AnonNotSingleFrame));
+    assert!(matches!(err.kind, RxErrorKind::AnonNotSingleFrame));
 
     frame.payload[7] = TailByte::new(true, false, true, 0).0;
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.unwrap_err();
-    assert!(matches!(err, RxError::AnonNotSingleFrame));
+    assert!(matches!(err.kind, RxErrorKind::AnonNotSingleFrame));
 
     frame.payload[7] = TailByte::new(false, false, true, 0).0;
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.unwrap_err();
-    assert!(matches!(err, RxError::AnonNotSingleFrame));
+    assert!(matches!(err.kind, RxErrorKind::AnonNotSingleFrame));
 }
 
 /// Service transfers to non-local nodes can safely be ignored.
@@ -156,7 +164,7 @@ fn discard_misguided_service_frames() {
     let result = Can::rx_process_frame(&Some(42), &frame);
     let result = result.unwrap();
     assert!(
-        matches!(result, None),
+        result.is_none(),
         "Didn't discard misguided service request"
     );
 
@@ -164,7 +172,7 @@ fn discard_misguided_service_frames() {
     let result = Can::rx_process_frame(&None, &frame);
     let result = result.unwrap();
     assert!(
-        matches!(result, None),
+        result.is_none(),
         "Didn't discard service request to anonymous node"
     );
 
@@ -173,14 +181,14 @@ fn discard_misguided_service_frames() {
     let result = Can::rx_process_frame(&Some(42), &frame);
     let result = result.unwrap();
     assert!(
-        matches!(result, None),
+        result.is_none(),
         "Didn't discard misguided service response"
     );
 
     let result = Can::rx_process_frame(&None, &frame);
     let result = result.unwrap();
     assert!(
-        matches!(result, None),
+        result.is_none(),
         "Didn't discard service response to anonymous node"
     );
 }
@@ -200,7 +208,7 @@ fn tail_byte_checks() {
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.expect_err("Invalid toggle");
     assert!(
-        matches!(err, RxError::TransferStartMissingToggle),
+        matches!(err.kind, RxErrorKind::TransferStartMissingToggle),
         "Did not catch invalid start toggle"
     );
 
@@ -208,22 +216,24 @@ fn tail_byte_checks() {
     let result = Can::rx_process_frame(&Some(42), &frame);
     let err = result.expect_err("Invalid toggle");
     assert!(
-        matches!(err, RxError::NonLastUnderUtilization),
+        matches!(err.kind, RxErrorKind::NonLastUnderUtilization),
         "Did not catch unfilled non-end frame"
     );
 }
 
 /// Creates a transfer of message type to reduce boilerplate code in testing some
 /// CanIter functionality
-fn make_generic_message_transfer(payload: &[u8]) -> crate::transfer::Transfer<TestClock> {
+fn make_generic_message_transfer(payload: &[u8]) -> RefTransfer<'_, TestClock> {
     let clock = TestClock::default();
-    crate::transfer::Transfer {
-        timestamp: clock.try_now().unwrap(),
-        priority: Priority::Nominal,
-        transfer_kind: TransferKind::Message,
-        port_id: 0,
-        remote_node_id: None,
-        transfer_id: 0,
+    RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().unwrap(),
+            priority: Priority::Nominal,
+            transfer_kind: TransferKind::Message,
+            port_id: 0,
+            remote_node_id: None,
+            transfer_id: 0,
+        },
         payload,
     }
 }
@@ -254,7 +264,7 @@ fn transfer_valid_ids() {
     assert!(id.subject_id() == 0);
     assert!(id.priority() == Priority::Nominal as u8);
 
-    transfer.transfer_kind = TransferKind::Request;
+    transfer.metadata.transfer_kind = TransferKind::Request;
     let err = CanIter::new(&transfer, None).expect_err("Anonymous service transfers not allowed");
     assert!(matches!(err, TxError::ServiceNoSourceID));
 
@@ -296,3 +306,1120 @@ fn iter_crc_exclusive() {
     let transfer = make_generic_message_transfer(buf.as_slice());
     assert_frame_count(CanIter::new(&transfer, Some(0)).unwrap(), 3);
 }
+
+/// Small multi-frame data type for exercising the typed node API.
+#[derive(Debug, PartialEq)]
+struct TestMessage {
+    value: u32,
+    bytes: [u8; 10],
+}
+
+impl Serialize for TestMessage {
+    const MAX_SIZE: usize = 14;
+    type Buffer = [u8; 14];
+
+    fn serialize(&self, buffer: &mut [u8]) -> usize {
+        buffer[0..4].copy_from_slice(&self.value.to_le_bytes());
+        buffer[4..14].copy_from_slice(&self.bytes);
+        14
+    }
+}
+
+impl Deserialize for TestMessage {
+    const EXTENT: usize = 14;
+
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+        // Implicit zero extension
+        let mut padded = [0u8; 14];
+        let len = core::cmp::min(buffer.len(), 14);
+        padded[..len].copy_from_slice(&buffer[..len]);
+
+        let mut bytes = [0u8; 10];
+        bytes.copy_from_slice(&padded[4..14]);
+        Ok(Self {
+            value: u32::from_le_bytes([padded[0], padded[1], padded[2], padded[3]]),
+            bytes,
+        })
+    }
+}
+
+/// Older, smaller version of `TestMessage`, to check extent truncation.
+struct TestMessageHeader {
+    value: u32,
+}
+
+impl Deserialize for TestMessageHeader {
+    const EXTENT: usize = 4;
+
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+        let mut padded = [0u8; 4];
+        let len = core::cmp::min(buffer.len(), 4);
+        padded[..len].copy_from_slice(&buffer[..len]);
+        Ok(Self {
+            value: u32::from_le_bytes(padded),
+        })
+    }
+}
+
+/// Publishes `message` from node 41, returning the frames it produced.
+fn publish_test_message(
+    message: &TestMessage,
+    transfer_id: TransferId,
+) -> Vec<CanFrame<TestClock>> {
+    let clock = TestClock::default();
+    publish_test_message_at(message, transfer_id, clock.try_now().unwrap())
+}
+
+/// Publishes `message` from node 41 at `timestamp`, returning the frames it produced.
+fn publish_test_message_at(
+    message: &TestMessage,
+    transfer_id: TransferId,
+    timestamp: crate::time::Timestamp<TestClock>,
+) -> Vec<CanFrame<TestClock>> {
+    let mut node = TestNode::new(Some(41), TestSessionManager::new());
+
+    let mut frames = Vec::new();
+    node.publish(
+        timestamp,
+        Priority::Nominal,
+        100,
+        transfer_id,
+        message,
+        |frame| frames.push(frame.clone()),
+    )
+    .unwrap();
+
+    frames
+}
+
+/// Typed messages survive a round trip through the node API.
+#[test]
+fn typed_publish_receive() {
+    let message = TestMessage {
+        value: 0xDEADBEEF,
+        bytes: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+    };
+    let frames = publish_test_message(&message, 3);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(CanMessageId(frames[0].id.as_raw()).source_id(), 41);
+
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+
+    let (last, rest) = frames.split_last().unwrap();
+    for frame in rest {
+        assert!(node.try_receive_frame(frame.clone()).unwrap().is_none());
+    }
+    let transfer = node
+        .try_receive_frame(last.clone())
+        .unwrap()
+        .expect("Transfer not completed");
+
+    assert_eq!(transfer.metadata.transfer_id, 3);
+    assert_eq!(transfer.metadata.remote_node_id, Some(41));
+    assert_eq!(transfer.deserialize::<TestMessage>().unwrap(), message);
+}
+
+/// Payloads past the subscription's extent are dropped.
+#[test]
+fn typed_receive_truncates_to_extent() {
+    let message = TestMessage {
+        value: 1234,
+        bytes: [0xFF; 10],
+    };
+    let frames = publish_test_message(&message, 3);
+
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessageHeader>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+
+    let mut received = None;
+    for frame in frames {
+        if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+            assert_eq!(transfer.payload.len(), TestMessageHeader::EXTENT);
+            received = Some(transfer.deserialize::<TestMessageHeader>().unwrap().value);
+        }
+    }
+
+    assert_eq!(received, Some(1234));
+}
+
+/// Request handler that echoes the request payload back, reversed.
+fn reverse_handler(transfer: &RefTransfer<TestClock>, response: &mut [u8]) -> Option<usize> {
+    let len = transfer.payload.len();
+    for (out, byte) in response.iter_mut().zip(transfer.payload.iter().rev()) {
+        *out = *byte;
+    }
+    Some(len)
+}
+
+static MESSAGES_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_handler(_transfer: &RefTransfer<TestClock>) {
+    MESSAGES_HANDLED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Requests are routed to their handler and the response is queued for transmission.
+#[test]
+fn dispatch_request_queues_response() {
+    let clock = TestClock::default();
+    let client = TestNode::new(Some(41), TestSessionManager::new());
+    let request = RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().unwrap(),
+            priority: Priority::Nominal,
+            transfer_kind: TransferKind::Request,
+            port_id: 10,
+            remote_node_id: Some(42),
+            transfer_id: 7,
+        },
+        payload: &[1, 2, 3, 4, 5, 6, 7, 8, 9],
+    };
+    let mut frames = Vec::new();
+    let mut iter = client.transmit(&request).unwrap();
+    while let Some(frame) = iter.next() {
+        frames.push(frame.clone());
+    }
+
+    let mut server = TestNode::new(Some(42), TestSessionManager::new());
+    server
+        .sessions
+        .subscribe(Subscription::new(
+            TransferKind::Request,
+            10,
+            16,
+            embedded_time::duration::Milliseconds(500),
+        ))
+        .unwrap();
+    let mut handlers = HandlerTable::<TestClock, 4, 16>::new();
+    handlers.on_request(10, reverse_handler).unwrap();
+    assert!(matches!(
+        handlers.on_request(10, reverse_handler),
+        Err(session::SubscriptionError::SubscriptionExists)
+    ));
+
+    let mut tx_queue = VecDeque::new();
+    for frame in frames {
+        server
+            .dispatch_frame(frame, &mut handlers, &mut tx_queue)
+            .unwrap();
+    }
+
+    // Response should go back to the client, with the request's transfer ID
+    assert_eq!(tx_queue.len(), 2);
+    let id = CanServiceId(tx_queue[0].id.as_raw());
+    assert!(id.is_svc());
+    assert!(!id.is_req());
+    assert_eq!(id.service_id(), 10);
+    assert_eq!(id.source_id(), 42);
+    assert_eq!(id.destination_id(), 41);
+
+    let mut client = TestNode::new(Some(41), TestSessionManager::new());
+    client
+        .sessions
+        .subscribe(Subscription::new(
+            TransferKind::Response,
+            10,
+            16,
+            embedded_time::duration::Milliseconds(500),
+        ))
+        .unwrap();
+    let mut response = None;
+    for frame in tx_queue {
+        if let Some(transfer) = client.try_receive_frame(frame).unwrap() {
+            assert_eq!(transfer.metadata.transfer_id, 7);
+            response = Some(transfer.payload.to_vec());
+        }
+    }
+    assert_eq!(response, Some(vec![9, 8, 7, 6, 5, 4, 3, 2, 1]));
+}
+
+/// A handler claiming a response longer than its buffer is an error, not a panic.
+#[test]
+fn dispatch_response_out_of_bounds() {
+    let clock = TestClock::default();
+    let client = TestNode::new(Some(41), TestSessionManager::new());
+    let request = RefTransfer {
+        metadata: TransferMetadata {
+            timestamp: clock.try_now().unwrap(),
+            priority: Priority::Nominal,
+            transfer_kind: TransferKind::Request,
+            port_id: 10,
+            remote_node_id: Some(42),
+            transfer_id: 7,
+        },
+        payload: &[1, 2, 3, 4, 5],
+    };
+    let mut iter = client.transmit(&request).unwrap();
+    let frame = iter.next().unwrap().clone();
+
+    let mut server = TestNode::new(Some(42), TestSessionManager::new());
+    server
+        .sessions
+        .subscribe(Subscription::new(
+            TransferKind::Request,
+            10,
+            16,
+            embedded_time::duration::Milliseconds(500),
+        ))
+        .unwrap();
+    // Too small for the reversed request
+    let mut handlers = HandlerTable::<TestClock, 4, 4>::new();
+    handlers.on_request(10, reverse_handler).unwrap();
+
+    let mut tx_queue = VecDeque::new();
+    assert!(matches!(
+        server.dispatch_frame(frame, &mut handlers, &mut tx_queue),
+        Err(DispatchError::Tx(TxError::PayloadOutOfBounds))
+    ));
+    assert!(tx_queue.is_empty());
+}
+
+/// Messages only reach the handler registered for their subject.
+#[test]
+fn dispatch_message() {
+    let message = TestMessage {
+        value: 0,
+        bytes: [0; 10],
+    };
+
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+    let mut handlers = HandlerTable::<TestClock, 4, 0>::new();
+    handlers.on_message(101, count_handler).unwrap();
+    let mut tx_queue = heapless::Deque::<CanFrame<TestClock>, 4>::new();
+
+    for frame in publish_test_message(&message, 0) {
+        node.dispatch_frame(frame, &mut handlers, &mut tx_queue)
+            .unwrap();
+    }
+    assert_eq!(MESSAGES_HANDLED.load(Ordering::Relaxed), 0);
+
+    handlers.remove(TransferKind::Message, 101).unwrap();
+    handlers.on_message(100, count_handler).unwrap();
+    for frame in publish_test_message(&message, 1) {
+        node.dispatch_frame(frame, &mut handlers, &mut tx_queue)
+            .unwrap();
+    }
+    assert_eq!(MESSAGES_HANDLED.load(Ordering::Relaxed), 1);
+    assert!(tx_queue.is_empty());
+}
+
+/// A leased transfer keeps its session busy until it is dropped.
+#[test]
+fn leased_transfer_blocks_session() {
+    let message = TestMessage {
+        value: 0xCAFE,
+        bytes: [3; 10],
+    };
+
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+
+    let mut lease = None;
+    for frame in publish_test_message(&message, 0) {
+        lease = node.try_receive_frame_leased(frame).unwrap();
+    }
+    let lease = lease.expect("Transfer not completed");
+
+    // The node can still be used while the lease is alive, but the session can't
+    let frames = publish_test_message(&message, 1);
+    assert!(matches!(
+        node.try_receive_frame(frames[0].clone()).map_err(|err| err.kind),
+        Err(RxErrorKind::Session(session::SessionError::Leased))
+    ));
+    assert_eq!(lease.metadata.transfer_id, 0);
+    assert_eq!(lease.deserialize::<TestMessage>().unwrap(), message);
+
+    drop(lease);
+    let mut received = None;
+    for frame in frames {
+        if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+            received = Some(transfer.metadata.transfer_id);
+        }
+    }
+    assert_eq!(received, Some(1));
+}
+
+/// Owned transfers can be queued while the node keeps receiving.
+#[test]
+fn owned_transfers_outlive_receive() {
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+
+    let mut vec_queue: Vec<OwnedTransfer<TestClock, Vec<u8>>> = Vec::new();
+    let mut heapless_queue: Vec<OwnedTransfer<TestClock, heapless::Vec<u8, 16>>> = Vec::new();
+    let mut pool_queue: Vec<OwnedTransfer<TestClock, [u8; 14]>> = Vec::new();
+    for transfer_id in 0..3 {
+        let message = TestMessage {
+            value: transfer_id as u32,
+            bytes: [transfer_id; 10],
+        };
+        for frame in publish_test_message(&message, transfer_id) {
+            if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+                match transfer_id {
+                    0 => vec_queue.push(transfer.into()),
+                    1 => heapless_queue.push(transfer.into_owned().unwrap()),
+                    _ => pool_queue.push(
+                        transfer
+                            .into_owned_with(|payload| payload.try_into().ok())
+                            .unwrap(),
+                    ),
+                }
+            }
+        }
+    }
+
+    assert_eq!(vec_queue[0].metadata.transfer_id, 0);
+    assert_eq!(heapless_queue[0].metadata.transfer_id, 1);
+    assert_eq!(pool_queue[0].metadata.transfer_id, 2);
+    assert_eq!(
+        heapless_queue[0]
+            .deserialize::<TestMessage>()
+            .unwrap()
+            .bytes,
+        [1; 10]
+    );
+    assert_eq!(pool_queue[0].deserialize::<TestMessage>().unwrap().value, 2);
+}
+
+/// Storage that is too small for the payload is refused.
+#[test]
+fn owned_transfer_too_small() {
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.subscribe::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    )
+    .unwrap();
+
+    let message = TestMessage {
+        value: 0,
+        bytes: [0; 10],
+    };
+    let mut owned = None;
+    for frame in publish_test_message(&message, 0) {
+        if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+            owned = Some(transfer.into_owned::<heapless::Vec<u8, 8>>());
+        }
+    }
+    assert!(matches!(owned, Some(None)));
+}
+
+/// Feeds a transfer's frames to `node`, returning the transfer ID once it completes,
+/// or the kind of the first error.
+fn receive_test_transfer(
+    node: &mut TestNode,
+    frames: Vec<CanFrame<TestClock>>,
+) -> Result<Option<TransferId>, RxErrorKind> {
+    let mut received = None;
+    for frame in frames {
+        if let Some(transfer) = node.try_receive_frame(frame).map_err(|err| err.kind)? {
+            received = Some(transfer.metadata.transfer_id);
+        }
+    }
+    Ok(received)
+}
+
+fn make_test_receiver(subscription: Subscription) -> TestNode {
+    let mut node = TestNode::new(Some(42), TestSessionManager::new());
+    node.sessions.subscribe(subscription).unwrap();
+    node
+}
+
+const TEST_MESSAGE: TestMessage = TestMessage {
+    value: 7,
+    bytes: [7; 10],
+};
+
+/// A transfer repeated within the transfer-ID timeout is dropped, but accepted after it.
+#[test]
+fn duplicate_transfer_rejected() {
+    let mut clock = TestClock::default();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(
+        receive_test_transfer(&mut node, frames.clone()).unwrap(),
+        Some(3)
+    );
+    assert!(matches!(
+        receive_test_transfer(&mut node, frames),
+        Err(RxErrorKind::Session(session::SessionError::InvalidTransferId))
+    ));
+
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(100u32))
+        .unwrap();
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert!(receive_test_transfer(&mut node, frames).is_err());
+
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(500u32))
+        .unwrap();
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(3));
+}
+
+/// Received frames are counted by the node, and per subscription by the session manager.
+#[cfg(feature = "statistics")]
+#[test]
+fn reception_statistics() {
+    let clock = TestClock::default();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+
+    let frames = publish_test_message(&TEST_MESSAGE, 3);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(
+        receive_test_transfer(&mut node, frames.clone()).unwrap(),
+        Some(3)
+    );
+    // Dropped at its first frame
+    assert!(receive_test_transfer(&mut node, frames).is_err());
+
+    let mut frames = publish_test_message(&TEST_MESSAGE, 4);
+    frames[1].payload[0] ^= 0xFF;
+    assert!(matches!(
+        receive_test_transfer(&mut node, frames),
+        Err(RxErrorKind::Session(session::SessionError::CrcMismatch))
+    ));
+
+    // Nobody subscribed to subject 200
+    let mut publisher = TestNode::new(Some(41), TestSessionManager::new());
+    let mut frames = Vec::new();
+    publisher
+        .publish(
+            clock.try_now().unwrap(),
+            Priority::Nominal,
+            200,
+            0,
+            &TEST_MESSAGE,
+            |frame| frames.push(frame.clone()),
+        )
+        .unwrap();
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), None);
+    let tx = publisher.statistics().tx;
+    assert_eq!((tx.frames, tx.transfers, tx.bytes), (3, 1, 14));
+
+    let empty = CanFrame {
+        timestamp: clock.try_now().unwrap(),
+        id: CanMessageId::new(Priority::Nominal, 100, Some(41)),
+        payload: ArrayVec::new(),
+    };
+    assert!(matches!(
+        node.try_receive_frame(empty).map_err(|err| err.kind),
+        Err(RxErrorKind::FrameEmpty)
+    ));
+
+    let rx = node.statistics().rx;
+    assert_eq!((rx.frames, rx.transfers, rx.bytes), (11, 1, 14));
+    assert_eq!(rx.drops.invalid_transfer_id, 1);
+    assert_eq!(rx.drops.crc_mismatch, 1);
+    assert_eq!(rx.drops.frame_empty, 1);
+    assert_eq!(rx.drops.total(), 3);
+
+    // The empty frame never reached the session manager
+    let sessions = node.sessions.statistics().unwrap();
+    assert_eq!(sessions.frames, 10);
+    assert_eq!(sessions.drops.no_subscription, 3);
+    assert_eq!(sessions.drops.total(), 5);
+
+    let subscription = node
+        .sessions
+        .subscription_statistics(TransferKind::Message, 100)
+        .unwrap();
+    assert_eq!((subscription.frames, subscription.transfers), (7, 1));
+    assert_eq!(subscription.drops.total(), 2);
+    assert!(node
+        .sessions
+        .subscription_statistics(TransferKind::Message, 200)
+        .is_none());
+
+    let registers = node.statistics_registers();
+    assert!(registers.contains(&("stat.rx.drop.frame_empty".into(), 1)));
+    assert!(registers.contains(&("stat.message.100.frames".into(), 7)));
+    assert!(registers.contains(&("stat.message.100.drop.crc_mismatch".into(), 1)));
+
+    node.reset_statistics();
+    assert_eq!(node.statistics().rx.frames, 0);
+}
+
+/// Errors say which transfer they're about, and what went wrong with it.
+#[test]
+fn errors_carry_context() {
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+    let frames = publish_test_message(&TEST_MESSAGE, 5);
+
+    // Second frame went missing
+    assert!(node.try_receive_frame(frames[0].clone()).unwrap().is_none());
+    let err = node.try_receive_frame(frames[2].clone()).unwrap_err();
+    assert_eq!(
+        err.kind,
+        RxErrorKind::Session(session::SessionError::ToggleMismatch)
+    );
+    assert_eq!(
+        (
+            err.transfer_kind,
+            err.port_id,
+            err.source_node_id,
+            err.transfer_id
+        ),
+        (Some(TransferKind::Message), Some(100), Some(41), Some(5))
+    );
+    assert_eq!(
+        alloc::format!("{}", err),
+        "toggle bit mismatch: message 100, source node 41, transfer ID 5"
+    );
+
+    // Errors from the transport know what the CAN ID and tail byte say
+    let mut frame = frames[0].clone();
+    let tail = frame.payload.pop().unwrap();
+    frame.payload.truncate(2);
+    frame.payload.push(tail);
+    let err = node.try_receive_frame(frame).unwrap_err();
+    assert_eq!(err.kind, RxErrorKind::NonLastUnderUtilization);
+    assert_eq!(
+        alloc::format!("{}", err),
+        "frame other than the last one not using the full MTU: message 100, source node 41, \
+         transfer ID 5"
+    );
+
+    let mut frame = frames[0].clone();
+    frame.payload.clear();
+    assert_eq!(
+        alloc::format!("{}", node.try_receive_frame(frame).unwrap_err()),
+        "empty frame"
+    );
+}
+
+/// Transfer IDs behind the last one are stale, ones ahead are new.
+#[test]
+fn stale_transfer_rejected() {
+    let clock = TestClock::default();
+    let now = clock.try_now().unwrap();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+
+    let publish = |transfer_id| publish_test_message_at(&TEST_MESSAGE, transfer_id, now);
+    assert_eq!(
+        receive_test_transfer(&mut node, publish(10)).unwrap(),
+        Some(10)
+    );
+    for stale in [9, 5, 0, 31, 27] {
+        assert!(
+            matches!(
+                receive_test_transfer(&mut node, publish(stale)),
+                Err(RxErrorKind::Session(session::SessionError::InvalidTransferId))
+            ),
+            "transfer ID {} accepted",
+            stale
+        );
+    }
+    // Skipping transfers (e.g. lost frames) is fine
+    assert_eq!(
+        receive_test_transfer(&mut node, publish(14)).unwrap(),
+        Some(14)
+    );
+}
+
+/// Transfer IDs wrap around at the transport's modulo.
+#[test]
+fn transfer_id_wraparound() {
+    let clock = TestClock::default();
+    let now = clock.try_now().unwrap();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+
+    let modulo = <Can as crate::transport::Transport<TestClock>>::TRANSFER_ID_MODULO;
+    assert_eq!(modulo, 32);
+    let publish = |transfer_id| publish_test_message_at(&TEST_MESSAGE, transfer_id, now);
+
+    for transfer_id in [29, 30, 31, 0, 1] {
+        assert_eq!(
+            receive_test_transfer(&mut node, publish(transfer_id)).unwrap(),
+            Some(transfer_id)
+        );
+    }
+    // Just before the wrap is now in the past
+    for stale in [31, 30, 1] {
+        assert!(matches!(
+            receive_test_transfer(&mut node, publish(stale)),
+            Err(RxErrorKind::Session(session::SessionError::InvalidTransferId))
+        ));
+    }
+    // Up to half the modulo ahead is accepted across the wrap too
+    assert_eq!(
+        receive_test_transfer(&mut node, publish(17)).unwrap(),
+        Some(17)
+    );
+    assert_eq!(
+        receive_test_transfer(&mut node, publish(1)).unwrap(),
+        Some(1)
+    );
+}
+
+/// Transfer-ID timeout can be set shorter than the session timeout.
+#[test]
+fn transfer_id_timeout_configurable() {
+    let mut clock = TestClock::default();
+    let mut node = make_test_receiver(
+        Subscription::for_type::<TestMessage>(
+            TransferKind::Message,
+            100,
+            embedded_time::duration::Milliseconds(1000),
+        )
+        .with_transfer_id_timeout(embedded_time::duration::Milliseconds(50)),
+    );
+
+    let frames = publish_test_message_at(&TEST_MESSAGE, 20, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(20));
+
+    // Remote node restarted and started counting from zero again
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(100u32))
+        .unwrap();
+    let frames = publish_test_message_at(&TEST_MESSAGE, 0, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(0));
+}
+
+/// Completed transfers are remembered for the transfer-ID timeout, even when session
+/// housekeeping runs after a shorter session timeout.
+#[test]
+fn transfer_id_timeout_outlasts_session_timeout() {
+    let mut clock = TestClock::default();
+    let mut node = make_test_receiver(
+        Subscription::for_type::<TestMessage>(
+            TransferKind::Message,
+            100,
+            embedded_time::duration::Milliseconds(100),
+        )
+        .with_transfer_id_timeout(embedded_time::duration::Milliseconds(1000)),
+    );
+
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(3));
+
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(500u32))
+        .unwrap();
+    node.sessions.update_sessions(clock.try_now().unwrap());
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert!(matches!(
+        receive_test_transfer(&mut node, frames),
+        Err(RxErrorKind::Session(session::SessionError::InvalidTransferId))
+    ));
+
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(600u32))
+        .unwrap();
+    node.sessions.update_sessions(clock.try_now().unwrap());
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(3));
+}
+
+/// Anonymous transfers are received whatever the transfer IDs of earlier ones, as they
+/// can come from any number of nodes.
+#[test]
+fn receive_anonymous_transfers() {
+    let clock = TestClock::default();
+    let mut node = make_test_receiver(Subscription::new(
+        TransferKind::Message,
+        100,
+        7,
+        embedded_time::duration::Milliseconds(500),
+    ));
+
+    for transfer_id in [3, 3, 1] {
+        let mut payload = ArrayVec::<[u8; 8]>::new();
+        payload.extend([1, 2, 3].iter().copied());
+        payload.push(TailByte::new(true, true, true, transfer_id).0);
+        let frame = CanFrame {
+            timestamp: clock.try_now().unwrap(),
+            id: CanMessageId::new(Priority::Nominal, 100, None),
+            payload,
+        };
+
+        let transfer = node
+            .try_receive_frame(frame)
+            .unwrap()
+            .expect("Anonymous transfer not received");
+        assert_eq!(transfer.metadata.remote_node_id, None);
+        assert_eq!(transfer.metadata.transfer_id, transfer_id);
+        assert_eq!(transfer.payload, &[1, 2, 3][..]);
+    }
+}
+
+/// A duplicated frame is dropped on its own, and the transfer still completes.
+#[test]
+fn duplicate_frame_dropped() {
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+    let frames = publish_test_message(&TEST_MESSAGE, 3);
+    assert_eq!(frames.len(), 3);
+
+    for frame in &frames[0..2] {
+        assert!(node.try_receive_frame(frame.clone()).unwrap().is_none());
+    }
+    let err = node.try_receive_frame(frames[1].clone()).unwrap_err();
+    assert!(matches!(
+        err.kind,
+        RxErrorKind::Session(session::SessionError::ToggleMismatch)
+    ));
+
+    let transfer = node
+        .try_receive_frame(frames[2].clone())
+        .unwrap()
+        .expect("Transfer not completed after duplicate frame");
+    assert_eq!(transfer.metadata.transfer_id, 3);
+}
+
+/// The CRC is trimmed off received payloads, also when it's split over the last two
+/// frames or partly cut off by the extent.
+#[test]
+fn receive_crc_split() {
+    let payload: Vec<u8> = (0..13).collect();
+    let transfer = make_generic_message_transfer(&payload);
+    let mut iter = CanIter::new(&transfer, Some(41)).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = iter.next() {
+        frames.push(frame.clone());
+    }
+    assert_eq!(frames.len(), 3);
+
+    for (extent, expected) in [(100, 13), (14, 13), (13, 13), (12, 12)] {
+        let mut node = make_test_receiver(Subscription::new(
+            TransferKind::Message,
+            0,
+            extent,
+            embedded_time::duration::Milliseconds(500),
+        ));
+
+        let mut received = None;
+        for frame in frames.iter().cloned() {
+            if let Some(transfer) = node.try_receive_frame(frame).unwrap() {
+                received = Some(transfer.payload.to_vec());
+            }
+        }
+        assert_eq!(
+            received.as_deref(),
+            Some(&payload[..expected]),
+            "extent {}",
+            extent
+        );
+    }
+}
+
+/// Frames of a transfer whose session timed out can't finish it.
+#[test]
+fn continuation_after_session_timeout_rejected() {
+    let mut clock = TestClock::default();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        embedded_time::duration::Milliseconds(500),
+    ));
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert!(node.try_receive_frame(frames[0].clone()).unwrap().is_none());
+
+    clock
+        .add_duration(&embedded_time::duration::Milliseconds(600u32))
+        .unwrap();
+    let now = clock.try_now().unwrap();
+    node.sessions.update_sessions(now);
+    for frame in &frames[1..] {
+        let mut frame = frame.clone();
+        frame.timestamp = now;
+        let err = node.try_receive_frame(frame).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            RxErrorKind::Session(session::SessionError::NewSessionNoStart)
+        ));
+    }
+}
+
+/// Port list is published when the ports change, and at least every 10 seconds.
+#[test]
+fn port_list_publication() {
+    use crate::uavcan::node::port::{List, SubjectIdList, LIST_SUBJECT};
+    use embedded_time::duration::Milliseconds;
+
+    let mut clock = TestClock::default();
+    let mut node = TestNode::new(Some(41), TestSessionManager::new());
+    node.subscribe::<TestMessage>(TransferKind::Message, 100, Milliseconds(500))
+        .unwrap();
+    node.subscribe::<TestMessage>(TransferKind::Request, 430, Milliseconds(500))
+        .unwrap();
+    let mut receiver = TestNode::new(Some(42), TestSessionManager::new());
+    receiver
+        .subscribe::<List>(TransferKind::Message, LIST_SUBJECT, Milliseconds(500))
+        .unwrap();
+
+    let mut publish = |node: &mut TestNode, clock: &TestClock| -> Option<(TransferId, List)> {
+        let mut frames = Vec::new();
+        let published = node
+            .publish_port_list(clock.try_now().unwrap(), |frame| frames.push(frame.clone()))
+            .unwrap();
+        assert_eq!(published, !frames.is_empty());
+        frames.into_iter().find_map(|frame| {
+            receiver
+                .try_receive_frame(frame)
+                .unwrap()
+                .map(|transfer| {
+                    (
+                        transfer.metadata.transfer_id,
+                        transfer.deserialize::<List>().unwrap(),
+                    )
+                })
+        })
+    };
+
+    let (transfer_id, list) = publish(&mut node, &clock).unwrap();
+    assert_eq!(transfer_id, 0);
+    assert_eq!(
+        list.publishers,
+        SubjectIdList::Ids([LIST_SUBJECT].into_iter().collect())
+    );
+    assert_eq!(
+        list.subscribers,
+        SubjectIdList::Ids([100].into_iter().collect())
+    );
+    assert!(list.clients.is_empty());
+    assert_eq!(list.servers, [430].into_iter().collect());
+
+    // Nothing changed
+    clock.add_duration(&Milliseconds(5000u32)).unwrap();
+    assert!(publish(&mut node, &clock).is_none());
+
+    // Unsubscribing changes the list, so it goes out straight away
+    node.unsubscribe(TransferKind::Request, 430).unwrap();
+    let (transfer_id, list) = publish(&mut node, &clock).unwrap();
+    assert_eq!(transfer_id, 1);
+    assert!(list.servers.is_empty());
+    assert_eq!(list, node.port_list());
+
+    // Then again once the period is up
+    clock.add_duration(&Milliseconds(9999u32)).unwrap();
+    assert!(publish(&mut node, &clock).is_none());
+    clock.add_duration(&Milliseconds(1u32)).unwrap();
+    assert_eq!(publish(&mut node, &clock).unwrap().0, 2);
+
+    // Anonymous nodes can't publish it
+    let mut anonymous = TestNode::new(None, TestSessionManager::new());
+    assert!(publish(&mut anonymous, &clock).is_none());
+}
+
+/// Publishing on a new subject changes the port list, publishing on a known one doesn't.
+#[test]
+fn port_list_publishers() {
+    use crate::uavcan::node::port::{SubjectIdList, LIST_SUBJECT};
+
+    let clock = TestClock::default();
+    let now = clock.try_now().unwrap();
+    let mut node = TestNode::new(Some(41), TestSessionManager::new());
+    assert!(node.publish_port_list(now, |_| {}).unwrap());
+
+    let publish = |node: &mut TestNode| {
+        node.publish(now, Priority::Nominal, 100, 0, &TEST_MESSAGE, |_| {})
+            .unwrap()
+    };
+    publish(&mut node);
+    assert!(node.publish_port_list(now, |_| {}).unwrap());
+    assert_eq!(
+        node.port_list().publishers,
+        SubjectIdList::Ids([100, LIST_SUBJECT].into_iter().collect())
+    );
+    publish(&mut node);
+    assert!(!node.publish_port_list(now, |_| {}).unwrap());
+
+    node.remove_publisher(100);
+    assert!(node.publish_port_list(now, |_| {}).unwrap());
+    node.remove_publisher(100);
+    assert!(!node.publish_port_list(now, |_| {}).unwrap());
+}
+
+/// Clock 100 ms short of its counter wrapping, which `TestClock`'s does every 71 minutes.
+fn clock_before_wrap() -> TestClock {
+    let mut clock = TestClock::default();
+    clock.add_ticks(u32::MAX - 100_000);
+    clock
+}
+
+/// Receives `frames` one at a time, `step` apart, each stamped when it arrives.
+fn receive_spread_out(
+    node: &mut TestNode,
+    clock: &mut TestClock,
+    frames: Vec<CanFrame<TestClock>>,
+    step: embedded_time::duration::Milliseconds,
+) -> Result<Option<TransferId>, RxErrorKind> {
+    let mut received = None;
+    for mut frame in frames {
+        frame.timestamp = clock.try_now().unwrap();
+        clock.add_duration(&step).unwrap();
+        if let Some(transfer) = node.try_receive_frame(frame).map_err(|err| err.kind)? {
+            received = Some(transfer.metadata.transfer_id);
+        }
+    }
+    Ok(received)
+}
+
+#[test]
+fn session_timeout_across_clock_wrap() {
+    use embedded_time::duration::Milliseconds;
+
+    let mut clock = clock_before_wrap();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        Milliseconds(500),
+    ));
+
+    // The counter wraps between the first and last of the 3 frames
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(
+        receive_spread_out(&mut node, &mut clock, frames, Milliseconds(60)).unwrap(),
+        Some(3)
+    );
+
+    let frames = publish_test_message_at(&TEST_MESSAGE, 4, clock.try_now().unwrap());
+    assert_eq!(
+        receive_spread_out(&mut node, &mut clock, frames, Milliseconds(300)),
+        Err(RxErrorKind::Session(session::SessionError::Timeout))
+    );
+}
+
+#[test]
+fn transfer_id_timeout_across_clock_wrap() {
+    use embedded_time::duration::Milliseconds;
+
+    let mut clock = clock_before_wrap();
+    let mut node = make_test_receiver(Subscription::for_type::<TestMessage>(
+        TransferKind::Message,
+        100,
+        Milliseconds(500),
+    ));
+
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(3));
+
+    // Still a duplicate after the wrap
+    clock.add_duration(&Milliseconds(200u32)).unwrap();
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(
+        receive_test_transfer(&mut node, frames),
+        Err(RxErrorKind::Session(session::SessionError::InvalidTransferId))
+    );
+
+    clock.add_duration(&Milliseconds(400u32)).unwrap();
+    let frames = publish_test_message_at(&TEST_MESSAGE, 3, clock.try_now().unwrap());
+    assert_eq!(receive_test_transfer(&mut node, frames).unwrap(), Some(3));
+}
+
+/// The port list period is kept across the wrap as well.
+#[test]
+fn port_list_period_across_clock_wrap() {
+    use embedded_time::duration::Milliseconds;
+
+    let mut clock = clock_before_wrap();
+    let mut node = TestNode::new(Some(41), TestSessionManager::new());
+    let publish = |node: &mut TestNode, clock: &TestClock| {
+        node.publish_port_list(clock.try_now().unwrap(), |_| {})
+            .unwrap()
+    };
+
+    assert!(publish(&mut node, &clock));
+    clock.add_duration(&Milliseconds(9999u32)).unwrap();
+    assert!(!publish(&mut node, &clock));
+    // A tick short of the period, which comparing whole seconds would round away
+    clock.add_ticks(999);
+    assert!(!publish(&mut node, &clock));
+    clock.add_ticks(1);
+    assert!(publish(&mut node, &clock));
+}
+
+/// Past half the counter's range `TestClock` timestamps compare the wrong way round,
+/// so a session that was never updated looks like it started in the future. Its
+/// timestamps extended by `MonotonicClock` keep counting instead.
+#[test]
+fn monotonic_clock_outlasts_counter_range() {
+    use crate::time::MonotonicClock;
+    use embedded_time::duration::Milliseconds;
+
+    type MonotonicNode = Node<
+        TestSessionManager<super::CanMetadata, MonotonicClock<TestClock>>,
+        Can,
+        MonotonicClock<TestClock>,
+    >;
+
+    let mut test_clock = TestClock::default();
+    let clock = MonotonicClock::new(test_clock.to_owned());
+    let mut node = MonotonicNode::new(Some(42), TestSessionManager::new());
+    node.sessions
+        .subscribe(Subscription::new(
+            TransferKind::Message,
+            100,
+            8,
+            Milliseconds(500),
+        ))
+        .unwrap();
+    let frame = |clock: &MonotonicClock<TestClock>| {
+        CanFrameBuilder::message(Priority::Nominal, 100, Some(41))
+            .transfer_id(3)
+            .build(clock.try_now().unwrap(), &[1])
+    };
+    assert!(node.try_receive_frame(frame(&clock)).unwrap().is_some());
+
+    // 40 minutes later the same transfer ID starts a new transfer, and again once the
+    // counter has wrapped
+    for _ in 0..2 {
+        test_clock
+            .add_duration(&Milliseconds(40 * 60 * 1000u32))
+            .unwrap();
+        assert!(node.try_receive_frame(frame(&clock)).unwrap().is_some());
+    }
+}
@@ -8,13 +8,14 @@
 
 // Declaring all of the sub transport modules here.
 pub mod can;
+pub mod redundant;
 
 use streaming_iterator::StreamingIterator;
 
 use crate::internal::InternalRxFrame;
+use crate::transfer::Transfer;
 use crate::NodeId;
 use crate::{RxError, TxError};
-use crate::transfer::Transfer;
 
 /// Describes any transport-specific metadata required to construct a session.
 ///
@@ -22,16 +23,62 @@ use crate::transfer::Transfer;
 /// as well as the CRC for multi-frame transfers. This trait lets us pull that
 /// code out of the generic processing and into more modular implementations.
 pub trait SessionMetadata<C: embedded_time::Clock> {
+    /// Transport whose transfers these sessions reassemble, which also gives the
+    /// transfer-ID modulo.
+    type Transport: Transport<C>;
+
     /// Create a fresh instance of session metadata;
     fn new() -> Self;
 
     /// Update metadata with incoming frame's information.
     ///
-    /// If the frame is valid, returns Some(length of payload to ingest)
+    /// If the frame is valid, returns Some(length of payload to ingest). `None` is
+    /// reported as `SessionError::ToggleMismatch`.
     fn update(&mut self, frame: &InternalRxFrame<C>) -> Option<usize>;
 
-    /// Final check to see if transfer was successful.
+    /// Final check to see if transfer was successful, e.g. its CRC. Failing it is
+    /// reported as `SessionError::CrcMismatch`.
     fn is_valid(&self, frame: &InternalRxFrame<C>) -> bool;
+
+    /// Length of a valid transfer's payload, out of the `stored` bytes ingested
+    /// (after truncation to the extent). Transports that ingest a trailer along with
+    /// the payload, e.g. a CRC split over frames, trim it off here.
+    fn payload_len(&self, _frame: &InternalRxFrame<C>, stored: usize) -> usize {
+        stored
+    }
+}
+
+/// Storage for frames waiting to be sent out on the wire.
+///
+/// Implemented for `VecDeque` and `heapless::Deque`, so either can be used depending
+/// on whether the application has an allocator.
+pub trait TxQueue<F> {
+    /// Add a frame to the back of the queue, handing it back if the queue is full.
+    fn push(&mut self, frame: F) -> Result<(), F>;
+
+    /// Take the next frame to transmit.
+    fn pop(&mut self) -> Option<F>;
+}
+
+impl<F> TxQueue<F> for alloc::collections::VecDeque<F> {
+    fn push(&mut self, frame: F) -> Result<(), F> {
+        self.push_back(frame);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<F> {
+        self.pop_front()
+    }
+}
+
+impl<F, const N: usize> TxQueue<F> for heapless::Deque<F, N> {
+    fn push(&mut self, frame: F) -> Result<(), F> {
+        self.push_back(frame)
+    }
+
+    fn pop(&mut self) -> Option<F> {
+        self.pop_front()
+    }
 }
 
 /// This trait is to be implemented on a unit struct, in order to be specified
@@ -40,12 +87,16 @@ pub trait Transport<C: embedded_time::Clock> {
     type Frame;
     // TODO does this properly describe the lifetime semantics of this type?
     // I implemented this as a quick fix to get the PR tests going - David
-    type FrameIter<'a>: StreamingIterator
+    type FrameIter<'a>: StreamingIterator<Item = Self::Frame>
     where
         C: 'a;
 
     const MTU_SIZE: usize;
 
+    /// Transfer IDs count up modulo this, e.g. 32 on CAN, where they're five bits of
+    /// the tail byte.
+    const TRANSFER_ID_MODULO: usize;
+
     /// Process a frame, returning the internal transport-independant representation,
     /// or errors if invalid.
     fn rx_process_frame<'a>(
@@ -54,7 +105,10 @@ pub trait Transport<C: embedded_time::Clock> {
     ) -> Result<Option<InternalRxFrame<'a, C>>, RxError>;
 
     /// Prepare an iterator of frames to send out on the wire.
+    ///
+    /// `node_id` is the ID of the transmitting node, or `None` if it is anonymous.
     fn transmit<'a, X: Transfer<'a, C>>(
+        node_id: &Option<NodeId>,
         transfer: &'a X,
     ) -> Result<Self::FrameIter<'a>, TxError>;
 }
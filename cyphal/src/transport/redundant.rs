@@ -0,0 +1,382 @@
+//! Redundant transport support.
+//!
+//! `RedundantTransport` wraps another transport to run it over several interfaces
+//! at once (e.g. doubly redundant CAN). Every transfer is transmitted on each
+//! interface, and frames from all of them are fed into the same node.
+//!
+//! Deduplication follows the spec's transfer-ID timeout rule: each session locks
+//! onto the interface it first received from, and frames from the other
+//! interfaces are dropped as redundant copies. Only once the locked interface
+//! has been silent for a whole transfer-ID timeout does the session switch over,
+//! so the application sees every transfer exactly once.
+
+use core::marker::PhantomData;
+
+use streaming_iterator::StreamingIterator;
+
+use crate::internal::InternalRxFrame;
+use crate::transfer::Transfer;
+use crate::transport::Transport;
+use crate::{NodeId, RxError, TxError};
+
+/// Frame tagged with the interface it was received on, or should be sent on.
+#[derive(Clone, Debug)]
+pub struct RedundantFrame<F> {
+    pub iface: usize,
+    pub frame: F,
+}
+
+/// Runs transport `T` over `N` redundant interfaces.
+#[derive(Copy, Clone, Debug)]
+pub struct RedundantTransport<T, const N: usize>(PhantomData<T>);
+
+impl<C, T, const N: usize> Transport<C> for RedundantTransport<T, N>
+where
+    C: embedded_time::Clock,
+    T: Transport<C>,
+    T::Frame: Clone,
+{
+    type Frame = RedundantFrame<T::Frame>;
+    type FrameIter<'a>
+        = RedundantIter<'a, C, T, N>
+    where
+        C: 'a;
+
+    const MTU_SIZE: usize = T::MTU_SIZE;
+    const TRANSFER_ID_MODULO: usize = T::TRANSFER_ID_MODULO;
+
+    fn rx_process_frame<'a>(
+        node_id: &Option<NodeId>,
+        frame: &'a Self::Frame,
+    ) -> Result<Option<InternalRxFrame<'a, C>>, RxError> {
+        let internal = T::rx_process_frame(node_id, &frame.frame)?;
+        Ok(internal.map(|mut internal| {
+            internal.iface = frame.iface;
+            internal
+        }))
+    }
+
+    fn transmit<'a, X: Transfer<'a, C>>(
+        node_id: &Option<NodeId>,
+        transfer: &'a X,
+    ) -> Result<Self::FrameIter<'a>, TxError> {
+        let mut iters = heapless::Vec::new();
+        for _ in 0..N {
+            // Can't fail, there is room for exactly N
+            let _ = iters.push(T::transmit(node_id, transfer)?);
+        }
+
+        Ok(RedundantIter {
+            iters,
+            iface: 0,
+            current: None,
+        })
+    }
+}
+
+/// Iterator over the frames of a transfer on every interface, one interface after another.
+pub struct RedundantIter<'a, C, T, const N: usize>
+where
+    C: embedded_time::Clock + 'a,
+    T: Transport<C>,
+{
+    iters: heapless::Vec<T::FrameIter<'a>, N>,
+    iface: usize,
+    current: Option<RedundantFrame<T::Frame>>,
+}
+
+impl<'a, C, T, const N: usize> StreamingIterator for RedundantIter<'a, C, T, N>
+where
+    C: embedded_time::Clock + 'a,
+    T: Transport<C>,
+    T::Frame: Clone,
+{
+    type Item = RedundantFrame<T::Frame>;
+
+    fn advance(&mut self) {
+        self.current = None;
+        while let Some(iter) = self.iters.get_mut(self.iface) {
+            if let Some(frame) = iter.next() {
+                self.current = Some(RedundantFrame {
+                    iface: self.iface,
+                    frame: frame.clone(),
+                });
+                return;
+            }
+            self.iface += 1;
+        }
+    }
+
+    fn get(&self) -> Option<&Self::Item> {
+        self.current.as_ref()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Every interface sends the same frames
+        let (low, high) = match self.iters.first() {
+            Some(iter) => iter.size_hint(),
+            None => (0, Some(0)),
+        };
+        let remaining = self.iters.len().saturating_sub(self.iface);
+        (
+            low.saturating_mul(remaining),
+            high.and_then(|high| high.checked_mul(remaining)),
+        )
+    }
+}
+
+/// Health counters for a single interface.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InterfaceStats {
+    /// Frames received without error.
+    pub rx_frames: u32,
+    /// Frames that failed to be received.
+    pub rx_errors: u32,
+    /// Transmission failures reported by the driver.
+    pub tx_errors: u32,
+    /// Most recent reception error.
+    pub last_error: Option<RxError>,
+}
+
+/// Per-interface error counters, for monitoring the health of each bus.
+///
+/// The node has no knowledge of the drivers, so results are fed in by the
+/// application as it passes frames along.
+#[derive(Copy, Clone, Debug)]
+pub struct InterfaceCounters<const N: usize> {
+    ifaces: [InterfaceStats; N],
+}
+
+impl<const N: usize> InterfaceCounters<N> {
+    pub fn new() -> Self {
+        Self {
+            ifaces: [InterfaceStats::default(); N],
+        }
+    }
+
+    /// Record the result of receiving a frame that arrived on `iface`.
+    ///
+    /// The result is passed through, so this can wrap `Node::try_receive_frame`.
+    pub fn record_rx<R>(&mut self, iface: usize, result: Result<R, RxError>) -> Result<R, RxError> {
+        if let Some(stats) = self.ifaces.get_mut(iface) {
+            match &result {
+                Ok(_) => stats.rx_frames = stats.rx_frames.wrapping_add(1),
+                Err(err) => {
+                    stats.rx_errors = stats.rx_errors.wrapping_add(1);
+                    stats.last_error = Some(*err);
+                }
+            }
+        }
+        result
+    }
+
+    /// Record a driver failure to transmit on `iface`.
+    pub fn record_tx_error(&mut self, iface: usize) {
+        if let Some(stats) = self.ifaces.get_mut(iface) {
+            stats.tx_errors = stats.tx_errors.wrapping_add(1);
+        }
+    }
+
+    /// Counters for a single interface.
+    pub fn get(&self, iface: usize) -> Option<&InterfaceStats> {
+        self.ifaces.get(iface)
+    }
+
+    /// Reset all counters to zero.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<const N: usize> Default for InterfaceCounters<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use embedded_time::duration::Milliseconds;
+    use embedded_time::Clock;
+
+    use super::*;
+    use crate::session::SessionManager;
+    use crate::testing::{message_transfer, TestSessionManager};
+    use crate::time::{TestClock, Timestamp};
+    use crate::transport::can::{Can, CanFrame, CanMetadata};
+    use crate::{Node, RxErrorKind, Subscription, TransferKind};
+
+    type TestNode =
+        Node<TestSessionManager<CanMetadata, TestClock>, RedundantTransport<Can, 2>, TestClock>;
+    type TestFrame = RedundantFrame<CanFrame<TestClock>>;
+
+    /// Two-frame message from node 41 on subject 100, sent on both interfaces.
+    fn transmit(timestamp: Timestamp<TestClock>, transfer_id: u8) -> Vec<TestFrame> {
+        let node = TestNode::new(Some(41), TestSessionManager::new());
+        let payload = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let transfer = message_transfer(timestamp, 100, transfer_id, &payload);
+
+        let mut frames = Vec::new();
+        let mut iter = node.transmit(&transfer).unwrap();
+        while let Some(frame) = iter.next() {
+            frames.push(frame.clone());
+        }
+        frames
+    }
+
+    fn make_receiver() -> TestNode {
+        let mut node = TestNode::new(Some(42), TestSessionManager::new());
+        node.sessions
+            .subscribe(Subscription::new(
+                TransferKind::Message,
+                100,
+                64,
+                Milliseconds(500),
+            ))
+            .unwrap();
+        node
+    }
+
+    /// Returns the number of transfers completed by `frames`.
+    fn receive(node: &mut TestNode, frames: impl IntoIterator<Item = TestFrame>) -> usize {
+        let mut completed = 0;
+        for frame in frames {
+            if node.try_receive_frame(frame).unwrap().is_some() {
+                completed += 1;
+            }
+        }
+        completed
+    }
+
+    #[test]
+    fn transmit_on_every_interface() {
+        let clock = TestClock::default();
+        let frames = transmit(clock.try_now().unwrap(), 0);
+
+        let ifaces: Vec<usize> = frames.iter().map(|f| f.iface).collect();
+        assert_eq!(ifaces, [0, 0, 1, 1]);
+        for (a, b) in frames[..2].iter().zip(&frames[2..]) {
+            assert_eq!(a.frame.id, b.frame.id);
+            assert_eq!(a.frame.payload, b.frame.payload);
+        }
+    }
+
+    #[test]
+    fn duplicates_dropped() {
+        let mut clock = TestClock::default();
+        let mut node = make_receiver();
+
+        for transfer_id in 0..4 {
+            let frames = transmit(clock.try_now().unwrap(), transfer_id);
+            // Interleave the copies, with the second interface sometimes winning the race
+            let order = if transfer_id % 2 == 0 {
+                [0, 2, 1, 3]
+            } else {
+                [2, 0, 3, 1]
+            };
+            let frames = order.iter().map(|&i| frames[i].clone());
+            assert_eq!(receive(&mut node, frames), 1);
+            clock.add_duration(&Milliseconds(10u32)).unwrap();
+        }
+    }
+
+    #[test]
+    fn failover_after_timeout() {
+        let mut clock = TestClock::default();
+        let mut node = make_receiver();
+
+        let frames = transmit(clock.try_now().unwrap(), 0);
+        assert_eq!(receive(&mut node, frames[..2].iter().cloned()), 1);
+
+        // Interface 0 goes quiet, but hasn't timed out yet
+        clock.add_duration(&Milliseconds(100u32)).unwrap();
+        let frames = transmit(clock.try_now().unwrap(), 1);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 0);
+
+        clock.add_duration(&Milliseconds(500u32)).unwrap();
+        let frames = transmit(clock.try_now().unwrap(), 2);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 1);
+
+        // Now locked onto interface 1
+        clock.add_duration(&Milliseconds(10u32)).unwrap();
+        let frames = transmit(clock.try_now().unwrap(), 3);
+        assert_eq!(receive(&mut node, frames[..2].iter().cloned()), 0);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 1);
+    }
+
+    #[test]
+    fn failover_after_session_cleanup() {
+        let mut clock = TestClock::default();
+        let mut node = make_receiver();
+
+        let frames = transmit(clock.try_now().unwrap(), 0);
+        assert_eq!(receive(&mut node, frames[..2].iter().cloned()), 1);
+
+        // Housekeeping resets the session once interface 0 has timed out
+        clock.add_duration(&Milliseconds(600u32)).unwrap();
+        node.sessions.update_sessions(clock.try_now().unwrap());
+
+        let frames = transmit(clock.try_now().unwrap(), 1);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 1);
+
+        // Now locked onto interface 1
+        clock.add_duration(&Milliseconds(10u32)).unwrap();
+        let frames = transmit(clock.try_now().unwrap(), 2);
+        assert_eq!(receive(&mut node, frames[..2].iter().cloned()), 0);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 1);
+    }
+
+    #[test]
+    fn failover_after_transfer_timeout() {
+        let mut clock = TestClock::default();
+        let mut node = make_receiver();
+
+        // Interface 0 drops out partway through a transfer
+        let frames = transmit(clock.try_now().unwrap(), 0);
+        assert_eq!(receive(&mut node, frames[..1].iter().cloned()), 0);
+
+        clock.add_duration(&Milliseconds(600u32)).unwrap();
+        node.sessions.update_sessions(clock.try_now().unwrap());
+
+        let frames = transmit(clock.try_now().unwrap(), 1);
+        assert_eq!(receive(&mut node, frames[2..].iter().cloned()), 1);
+    }
+
+    #[test]
+    fn interface_counters() {
+        let clock = TestClock::default();
+        let mut node = make_receiver();
+        let mut counters = InterfaceCounters::<2>::new();
+
+        for frame in transmit(clock.try_now().unwrap(), 0) {
+            let iface = frame.iface;
+            counters
+                .record_rx(iface, node.try_receive_frame(frame))
+                .unwrap();
+        }
+        let mut broken = transmit(clock.try_now().unwrap(), 1).remove(2);
+        broken.frame.payload.clear();
+        assert!(counters
+            .record_rx(1, node.try_receive_frame(broken))
+            .is_err());
+        counters.record_tx_error(1);
+
+        let iface0 = counters.get(0).unwrap();
+        assert_eq!(
+            (iface0.rx_frames, iface0.rx_errors, iface0.tx_errors),
+            (2, 0, 0)
+        );
+        let iface1 = counters.get(1).unwrap();
+        assert_eq!(
+            (iface1.rx_frames, iface1.rx_errors, iface1.tx_errors),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            iface1.last_error.map(|err| err.kind),
+            Some(RxErrorKind::FrameEmpty)
+        );
+        assert!(counters.get(2).is_none());
+    }
+}
@@ -10,5 +10,10 @@
 pub type NodeId = u16;
 pub type PortId = u16;
 
+/// Highest subject ID.
+pub const MAX_SUBJECT_ID: PortId = 8191;
+/// Highest service ID.
+pub const MAX_SERVICE_ID: PortId = 511;
+
 // TODO set type with min/max bounds
 pub type TransferId = u8;
@@ -0,0 +1,8 @@
+//! Data types from the standard `uavcan` namespace that the crate itself needs,
+//! serialized by hand so they don't depend on any code generator.
+//!
+//! Only the types used by higher-level components like `tracker::NodeTracker`
+//! live here. Applications can implement the `serialization` traits for
+//! anything else.
+
+pub mod node;
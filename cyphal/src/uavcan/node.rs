@@ -0,0 +1,343 @@
+//! `uavcan.node`: heartbeats and node information.
+
+pub mod port;
+
+use crate::serialization::{Deserialize, DeserializeError, Serialize};
+use crate::types::*;
+
+/// Fixed subject ID of `Heartbeat`.
+pub const HEARTBEAT_SUBJECT: PortId = 7509;
+/// Fixed service ID of `GetInfo`.
+pub const GET_INFO_SERVICE: PortId = 430;
+
+/// `uavcan.node.Health.1.0`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Health {
+    Nominal = 0,
+    Advisory = 1,
+    Caution = 2,
+    Warning = 3,
+}
+
+impl Health {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Health::Nominal,
+            1 => Health::Advisory,
+            2 => Health::Caution,
+            _ => Health::Warning,
+        }
+    }
+}
+
+/// `uavcan.node.Mode.1.0`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Operational = 0,
+    Initialization = 1,
+    Maintenance = 2,
+    SoftwareUpdate = 3,
+}
+
+impl Mode {
+    fn from_bits(bits: u8) -> Result<Self, DeserializeError> {
+        match bits & 0b111 {
+            0 => Ok(Mode::Operational),
+            1 => Ok(Mode::Initialization),
+            2 => Ok(Mode::Maintenance),
+            3 => Ok(Mode::SoftwareUpdate),
+            _ => Err(DeserializeError::InvalidValue),
+        }
+    }
+}
+
+/// `uavcan.node.Heartbeat.1.0`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Heartbeat {
+    /// Seconds since the node started.
+    pub uptime: u32,
+    pub health: Health,
+    pub mode: Mode,
+    pub vendor_specific_status_code: u8,
+}
+
+impl Heartbeat {
+    /// Publication period nodes use, in ms.
+    pub const PERIOD: u32 = 1000;
+    /// A node that hasn't published a heartbeat for this long, in ms, is offline.
+    pub const OFFLINE_TIMEOUT: u32 = 3000;
+}
+
+impl Serialize for Heartbeat {
+    const MAX_SIZE: usize = 7;
+    type Buffer = [u8; 7];
+
+    fn serialize(&self, buffer: &mut [u8]) -> usize {
+        buffer[..4].copy_from_slice(&self.uptime.to_le_bytes());
+        buffer[4] = self.health as u8;
+        buffer[5] = self.mode as u8;
+        buffer[6] = self.vendor_specific_status_code;
+        Self::MAX_SIZE
+    }
+}
+
+impl Deserialize for Heartbeat {
+    const EXTENT: usize = 12;
+
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+        let mut reader = Reader::new(buffer);
+        Ok(Self {
+            uptime: reader.u32(),
+            health: Health::from_bits(reader.u8()),
+            mode: Mode::from_bits(reader.u8())?,
+            vendor_specific_status_code: reader.u8(),
+        })
+    }
+}
+
+/// `uavcan.node.Version.1.0`
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+}
+
+/// Request of `uavcan.node.GetInfo.1.0`, which is empty.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GetInfoRequest;
+
+impl Serialize for GetInfoRequest {
+    const MAX_SIZE: usize = 0;
+    type Buffer = [u8; 0];
+
+    fn serialize(&self, _buffer: &mut [u8]) -> usize {
+        0
+    }
+}
+
+impl Deserialize for GetInfoRequest {
+    const EXTENT: usize = 0;
+
+    fn deserialize(_buffer: &[u8]) -> Result<Self, DeserializeError> {
+        Ok(Self)
+    }
+}
+
+/// Response of `uavcan.node.GetInfo.1.0`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GetInfoResponse {
+    pub protocol_version: Version,
+    pub hardware_version: Version,
+    pub software_version: Version,
+    pub software_vcs_revision_id: u64,
+    pub unique_id: [u8; 16],
+    /// Reverse domain name, e.g. `org.example.motor_controller`.
+    pub name: heapless::Vec<u8, 50>,
+    pub software_image_crc: Option<u64>,
+    pub certificate_of_authenticity: heapless::Vec<u8, 222>,
+}
+
+impl Serialize for GetInfoResponse {
+    const MAX_SIZE: usize = 313;
+    type Buffer = [u8; 313];
+
+    fn serialize(&self, buffer: &mut [u8]) -> usize {
+        let mut writer = Writer { buffer, offset: 0 };
+        for version in [
+            self.protocol_version,
+            self.hardware_version,
+            self.software_version,
+        ] {
+            writer.bytes(&[version.major, version.minor]);
+        }
+        writer.bytes(&self.software_vcs_revision_id.to_le_bytes());
+        writer.bytes(&self.unique_id);
+        writer.bytes(&[self.name.len() as u8]);
+        writer.bytes(&self.name);
+        match self.software_image_crc {
+            Some(crc) => {
+                writer.bytes(&[1]);
+                writer.bytes(&crc.to_le_bytes());
+            }
+            None => writer.bytes(&[0]),
+        }
+        writer.bytes(&[self.certificate_of_authenticity.len() as u8]);
+        writer.bytes(&self.certificate_of_authenticity);
+        writer.offset
+    }
+}
+
+impl Deserialize for GetInfoResponse {
+    const EXTENT: usize = 448;
+
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+        let mut reader = Reader::new(buffer);
+        let mut version = || Version {
+            major: reader.u8(),
+            minor: reader.u8(),
+        };
+        let protocol_version = version();
+        let hardware_version = version();
+        let software_version = version();
+        let software_vcs_revision_id = reader.u64();
+        let mut unique_id = [0; 16];
+        unique_id.iter_mut().for_each(|byte| *byte = reader.u8());
+        let name = reader.vec()?;
+        let software_image_crc = match reader.u8() {
+            0 => None,
+            1 => Some(reader.u64()),
+            _ => return Err(DeserializeError::InvalidLength),
+        };
+        let certificate_of_authenticity = reader.vec()?;
+
+        Ok(Self {
+            protocol_version,
+            hardware_version,
+            software_version,
+            software_vcs_revision_id,
+            unique_id,
+            name,
+            software_image_crc,
+            certificate_of_authenticity,
+        })
+    }
+}
+
+/// Reads little-endian fields, with bytes past the end of the payload reading as
+/// zero (implicit zero extension).
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer, offset: 0 }
+    }
+
+    fn u8(&mut self) -> u8 {
+        let byte = self.buffer.get(self.offset).copied().unwrap_or(0);
+        self.offset += 1;
+        byte
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    fn u64(&mut self) -> u64 {
+        (self.u32() as u64) | (self.u32() as u64) << 32
+    }
+
+    /// Variable-length byte array with an 8-bit length prefix.
+    fn vec<const N: usize>(&mut self) -> Result<heapless::Vec<u8, N>, DeserializeError> {
+        let len = self.u8() as usize;
+        if len > N {
+            return Err(DeserializeError::InvalidLength);
+        }
+        Ok((0..len).map(|_| self.u8()).collect())
+    }
+
+    /// Reader for a delimited composite, which starts with its length in bytes.
+    fn delimited(&mut self) -> Reader<'a> {
+        let len = self.u32() as usize;
+        let start = self.offset.min(self.buffer.len());
+        let end = self.offset.saturating_add(len).min(self.buffer.len());
+        self.offset = self.offset.saturating_add(len);
+        Reader::new(&self.buffer[start..end])
+    }
+}
+
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+
+    /// Write a delimited composite, preceded by its length in bytes.
+    fn delimited(&mut self, write: impl FnOnce(&mut Writer)) {
+        let header = self.offset;
+        self.offset += 4;
+        write(self);
+        let len = (self.offset - header - 4) as u32;
+        self.buffer[header..header + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::serialization::Buffer;
+
+    #[test]
+    fn heartbeat() {
+        let heartbeat = Heartbeat {
+            uptime: 0x01020304,
+            health: Health::Caution,
+            mode: Mode::Maintenance,
+            vendor_specific_status_code: 0xAB,
+        };
+        let mut buffer = <Heartbeat as Serialize>::Buffer::zeroed();
+        assert_eq!(heartbeat.serialize(&mut buffer), 7);
+        assert_eq!(buffer, [4, 3, 2, 1, 2, 2, 0xAB]);
+        assert_eq!(Heartbeat::deserialize(&buffer).unwrap(), heartbeat);
+
+        // Zero extension, and padding bits are ignored
+        let heartbeat = Heartbeat::deserialize(&[1, 0, 0, 0, 0xFC]).unwrap();
+        assert_eq!(heartbeat.uptime, 1);
+        assert_eq!(heartbeat.health, Health::Nominal);
+        assert_eq!(heartbeat.mode, Mode::Operational);
+
+        assert!(matches!(
+            Heartbeat::deserialize(&[0, 0, 0, 0, 0, 4]),
+            Err(DeserializeError::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn get_info() {
+        let info = GetInfoResponse {
+            protocol_version: Version { major: 1, minor: 0 },
+            hardware_version: Version { major: 2, minor: 1 },
+            software_version: Version { major: 3, minor: 4 },
+            software_vcs_revision_id: 0xDEADBEEF,
+            unique_id: [7; 16],
+            name: heapless::Vec::from_slice(b"org.example.node").unwrap(),
+            software_image_crc: Some(0x1234),
+            certificate_of_authenticity: heapless::Vec::new(),
+        };
+        let mut buffer = <GetInfoResponse as Serialize>::Buffer::zeroed();
+        let len = info.serialize(&mut buffer);
+        assert_eq!(len, 6 + 8 + 16 + 1 + 16 + 1 + 8 + 1);
+        assert_eq!(&buffer[..6], &[1, 0, 2, 1, 3, 4]);
+        assert_eq!(buffer[30], 16);
+        assert_eq!(GetInfoResponse::deserialize(&buffer[..len]).unwrap(), info);
+
+        let mut full = info.clone();
+        full.name = heapless::Vec::from_slice(&[b'x'; 50]).unwrap();
+        full.certificate_of_authenticity = heapless::Vec::from_slice(&[1; 222]).unwrap();
+        assert_eq!(full.serialize(&mut buffer), GetInfoResponse::MAX_SIZE);
+        assert_eq!(GetInfoResponse::deserialize(&buffer).unwrap(), full);
+
+        // Name longer than its capacity
+        buffer[30] = 51;
+        assert!(matches!(
+            GetInfoResponse::deserialize(&buffer),
+            Err(DeserializeError::InvalidLength)
+        ));
+
+        assert_eq!(
+            GetInfoResponse::deserialize(&[]).unwrap(),
+            GetInfoResponse::default()
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! `uavcan.node.port`: introspection of the ports a node uses.
+
+use alloc::collections::BTreeSet;
+
+use super::{Reader, Writer};
+use crate::serialization::{Deserialize, DeserializeError, Serialize};
+use crate::types::*;
+
+/// Fixed subject ID of `List`.
+pub const LIST_SUBJECT: PortId = 7510;
+
+/// Most subject IDs a sparse list can hold, beyond which a mask is used.
+const SPARSE_LIST_CAPACITY: usize = 255;
+
+/// `uavcan.node.port.SubjectIDList.0.1`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SubjectIdList {
+    /// These subjects. Serialized as a sparse list or a mask, whichever fits.
+    Ids(BTreeSet<PortId>),
+    /// Every subject.
+    Total,
+}
+
+impl Default for SubjectIdList {
+    fn default() -> Self {
+        SubjectIdList::Ids(BTreeSet::new())
+    }
+}
+
+/// `uavcan.node.port.List.0.1`, with `ServiceIDList` as a plain set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct List {
+    pub publishers: SubjectIdList,
+    pub subscribers: SubjectIdList,
+    pub clients: BTreeSet<PortId>,
+    pub servers: BTreeSet<PortId>,
+}
+
+impl List {
+    /// Longest the list may go without being published, in ms.
+    pub const MAX_PUBLICATION_PERIOD: u32 = 10_000;
+}
+
+impl Serialize for List {
+    const MAX_SIZE: usize = 2 * (4 + 1 + 1024) + 2 * (4 + 64);
+    type Buffer = [u8; 2194];
+
+    fn serialize(&self, buffer: &mut [u8]) -> usize {
+        let mut writer = Writer { buffer, offset: 0 };
+        for subjects in [&self.publishers, &self.subscribers] {
+            writer.delimited(|writer| write_subjects(writer, subjects));
+        }
+        for services in [&self.clients, &self.servers] {
+            writer.delimited(|writer| write_mask(writer, services, MAX_SERVICE_ID));
+        }
+        writer.offset
+    }
+}
+
+impl Deserialize for List {
+    const EXTENT: usize = 8466;
+
+    fn deserialize(buffer: &[u8]) -> Result<Self, DeserializeError> {
+        let mut reader = Reader::new(buffer);
+        Ok(Self {
+            publishers: read_subjects(&mut reader.delimited())?,
+            subscribers: read_subjects(&mut reader.delimited())?,
+            clients: read_mask(&mut reader.delimited(), MAX_SERVICE_ID),
+            servers: read_mask(&mut reader.delimited(), MAX_SERVICE_ID),
+        })
+    }
+}
+
+fn write_subjects(writer: &mut Writer, subjects: &SubjectIdList) {
+    match subjects {
+        SubjectIdList::Ids(ids) => {
+            let ids: BTreeSet<PortId> = ids
+                .iter()
+                .copied()
+                .filter(|id| *id <= MAX_SUBJECT_ID)
+                .collect();
+            if ids.len() <= SPARSE_LIST_CAPACITY {
+                writer.bytes(&[1, ids.len() as u8]);
+                for id in ids {
+                    writer.bytes(&id.to_le_bytes());
+                }
+            } else {
+                writer.bytes(&[0]);
+                write_mask(writer, &ids, MAX_SUBJECT_ID);
+            }
+        }
+        SubjectIdList::Total => writer.bytes(&[2]),
+    }
+}
+
+fn read_subjects(reader: &mut Reader) -> Result<SubjectIdList, DeserializeError> {
+    match reader.u8() {
+        0 => Ok(SubjectIdList::Ids(read_mask(reader, MAX_SUBJECT_ID))),
+        1 => {
+            let len = reader.u8();
+            Ok(SubjectIdList::Ids(
+                (0..len).map(|_| reader.u16() & MAX_SUBJECT_ID).collect(),
+            ))
+        }
+        2 => Ok(SubjectIdList::Total),
+        _ => Err(DeserializeError::InvalidValue),
+    }
+}
+
+/// `bool[max + 1]`, one bit per port ID.
+fn write_mask(writer: &mut Writer, ids: &BTreeSet<PortId>, max: PortId) {
+    let mut byte = 0;
+    for id in 0..=max {
+        if ids.contains(&id) {
+            byte |= 1 << (id % 8);
+        }
+        if id % 8 == 7 {
+            writer.bytes(&[byte]);
+            byte = 0;
+        }
+    }
+}
+
+fn read_mask(reader: &mut Reader, max: PortId) -> BTreeSet<PortId> {
+    let mut ids = BTreeSet::new();
+    let mut byte = 0;
+    for id in 0..=max {
+        if id % 8 == 0 {
+            byte = reader.u8();
+        }
+        if byte & (1 << (id % 8)) != 0 {
+            ids.insert(id);
+        }
+    }
+    ids
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::serialization::Buffer;
+
+    fn serialize(list: &List) -> Vec<u8> {
+        let mut buffer = <List as Serialize>::Buffer::zeroed();
+        let len = list.serialize(&mut buffer);
+        buffer[..len].to_vec()
+    }
+
+    #[test]
+    fn sparse_and_masks() {
+        let list = List {
+            publishers: SubjectIdList::Ids([7509, 7510].into_iter().collect()),
+            subscribers: SubjectIdList::Ids(BTreeSet::new()),
+            clients: [430].into_iter().collect(),
+            servers: [0, 511].into_iter().collect(),
+        };
+        let payload = serialize(&list);
+        assert_eq!(payload.len(), (4 + 6) + (4 + 2) + 2 * (4 + 64));
+
+        // Publishers: length, sparse list tag, count and IDs
+        assert_eq!(&payload[..10], &[6, 0, 0, 0, 1, 2, 0x55, 0x1D, 0x56, 0x1D]);
+        // Nobody subscribed
+        assert_eq!(&payload[10..16], &[2, 0, 0, 0, 1, 0]);
+        // Clients: bit 430 of the mask
+        assert_eq!(&payload[16..20], &[64, 0, 0, 0]);
+        assert_eq!(payload[20 + 430 / 8], 1 << (430 % 8));
+        // Servers: the first and last bits
+        assert_eq!(payload[84 + 4], 1);
+        assert_eq!(payload[84 + 4 + 63], 0x80);
+
+        assert_eq!(List::deserialize(&payload).unwrap(), list);
+    }
+
+    #[test]
+    fn large_and_total_subject_lists() {
+        let list = List {
+            publishers: SubjectIdList::Total,
+            subscribers: SubjectIdList::Ids((0..=MAX_SUBJECT_ID).step_by(2).collect()),
+            ..Default::default()
+        };
+        let payload = serialize(&list);
+        assert_eq!(&payload[..5], &[1, 0, 0, 0, 2]);
+        // Too many for a sparse list, so a mask
+        assert_eq!(&payload[5..10], &[0x01, 0x04, 0, 0, 0]);
+        assert_eq!(payload[10], 0x55);
+        assert_eq!(List::deserialize(&payload).unwrap(), list);
+
+        // The biggest list fits
+        let list = List {
+            publishers: SubjectIdList::Ids((0..=MAX_SUBJECT_ID).collect()),
+            subscribers: SubjectIdList::Ids((0..=MAX_SUBJECT_ID).collect()),
+            clients: (0..=MAX_SERVICE_ID).collect(),
+            servers: (0..=MAX_SERVICE_ID).collect(),
+        };
+        let payload = serialize(&list);
+        assert_eq!(payload.len(), List::MAX_SIZE);
+        assert_eq!(List::deserialize(&payload).unwrap(), list);
+    }
+
+    #[test]
+    fn deserialize_edge_cases() {
+        // Everything missing is empty
+        assert_eq!(List::deserialize(&[]).unwrap(), List::default());
+
+        // Unknown union tag
+        assert!(matches!(
+            List::deserialize(&[1, 0, 0, 0, 3]),
+            Err(DeserializeError::InvalidValue)
+        ));
+
+        // A delimited field that's grown since this version is skipped over
+        let payload = [
+            7, 0, 0, 0, 1, 1, 0x64, 0x00, 0xAA, 0xBB,
+            0xCC, // publishers: [100] and extra bytes
+            2, 0, 0, 0, 1, 0, // no subscribers
+        ];
+        let list = List::deserialize(&payload).unwrap();
+        assert_eq!(
+            list.publishers,
+            SubjectIdList::Ids([100].into_iter().collect())
+        );
+        assert_eq!(list.subscribers, SubjectIdList::Ids(BTreeSet::new()));
+    }
+}
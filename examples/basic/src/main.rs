@@ -1,18 +1,17 @@
 #![deny(warnings)]
 
-use embedded_hal::can::ExtendedId;
-use embedded_time::Clock;
-use uavcan::{
-    session::StdVecSessionManager,
+use std::time::Duration;
+
+use cyphal::{
+    media::socketcan::SocketCan,
+    session::{SessionManager, StdVecSessionManager},
     time::StdClock,
-    transfer::Transfer,
-    transport::can::{Can, CanFrame as UavcanFrame, CanMetadata},
+    transfer::{RefTransfer, TransferMetadata},
+    transport::can::{Can, CanMetadata},
     types::TransferId,
-    Node, Priority, StreamingIterator, Subscription, TransferKind,
+    Node, Priority, Subscription, TransferKind,
 };
-
-use arrayvec::ArrayVec;
-use socketcan::{CANFrame, CANSocket};
+use embedded_time::Clock;
 
 fn main() {
     let clock = StdClock::new();
@@ -35,55 +34,35 @@ fn main() {
         .unwrap();
     let mut node = Node::<_, Can, StdClock>::new(Some(42), session_manager);
 
-    let sock = CANSocket::open("vcan0").unwrap();
+    let mut can = SocketCan::open("vcan0", clock.clone()).unwrap();
+    can.set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
 
     let mut last_publish = clock.try_now().unwrap();
     let mut transfer_id: TransferId = 30;
 
-    sock.set_read_timeout(std::time::Duration::from_millis(100))
-        .unwrap();
-
     loop {
-        let socketcan_frame = sock.read_frame().ok();
-
-        if let Some(socketcan_frame) = socketcan_frame {
-            // Note that this exposes some things I *don't* like about the API
-            // 1: we should have CanFrame::new or something
-            // 2: I don't like how the payload is working
-            let mut uavcan_frame = UavcanFrame {
-                timestamp: clock.try_now().unwrap(),
-                id: ExtendedId::new(socketcan_frame.id()).expect("not a extended CAN ID"),
-                payload: ArrayVec::new(),
-            };
-            uavcan_frame
-                .payload
-                .extend(socketcan_frame.data().iter().copied());
-
-            let xfer = match node.try_receive_frame(uavcan_frame) {
-                Ok(xfer) => xfer,
-                Err(err) => {
-                    println!("try_receive_frame error: {:?}", err);
-                    return;
-                }
-            };
-
-            if let Some(xfer) = xfer {
-                match xfer.transfer_kind {
-                    TransferKind::Message => {
-                        println!("UAVCAN message received!");
-                        print!("\tData: ");
-                        for byte in xfer.payload {
-                            print!("0x{:02x} ", byte);
-                        }
-                        println!();
-                    }
-                    TransferKind::Request => {
-                        println!("Request Received!");
-                    }
-                    TransferKind::Response => {
-                        println!("Response Received!");
+        match node.receive_from(&mut can) {
+            Ok(Some(xfer)) => match xfer.metadata.transfer_kind {
+                TransferKind::Message => {
+                    println!("UAVCAN message received!");
+                    print!("\tData: ");
+                    for byte in xfer.payload {
+                        print!("0x{:02x} ", byte);
                     }
+                    println!();
                 }
+                TransferKind::Request => {
+                    println!("Request Received!");
+                }
+                TransferKind::Response => {
+                    println!("Response Received!");
+                }
+            },
+            Ok(None) => {}
+            Err(err) => {
+                println!("receive error: {:?}", err);
+                return;
             }
         }
 
@@ -95,37 +74,21 @@ fn main() {
             let mut str = Vec::from([hello.len() as u8, 0]);
             str.extend_from_slice(hello.as_bytes());
 
-            let transfer = Transfer {
-                timestamp: clock.try_now().unwrap(),
-                priority: Priority::Nominal,
-                transfer_kind: TransferKind::Message,
-                port_id: 100,
-                remote_node_id: None,
-                transfer_id,
+            let transfer = RefTransfer {
+                metadata: TransferMetadata {
+                    timestamp: clock.try_now().unwrap(),
+                    priority: Priority::Nominal,
+                    transfer_kind: TransferKind::Message,
+                    port_id: 100,
+                    remote_node_id: None,
+                    transfer_id,
+                },
                 payload: &str,
             };
 
-            // unchecked_add is unstable :(
-            // unsafe { transfer_id.unchecked_add(1); }
-            transfer_id = (std::num::Wrapping(transfer_id) + std::num::Wrapping(1)).0;
-
-            let mut frame_iter = node.transmit(&transfer).unwrap();
-            while let Some(frame) = frame_iter.next() {
-                sock.write_frame(
-                    &CANFrame::new(frame.id.as_raw(), &frame.payload, false, false).unwrap(),
-                )
-                .unwrap();
+            transfer_id = (transfer_id + 1) % 32;
 
-                //print!("Can frame {}: ", i);
-                //for byte in &frame.payload {
-                //    print!("0x{:02x} ", byte);
-                //}
-                //println!("");
-
-                //if let Some(in_xfer) = node.try_receive_frame(frame).unwrap() {
-                //    println!("Received xfer!");
-                //}
-            }
+            node.transmit_to(&transfer, &mut can).unwrap();
 
             last_publish = clock.try_now().unwrap();
         }